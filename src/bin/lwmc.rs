@@ -0,0 +1,341 @@
+//! A client that sends messages to `lwm` over its socket
+
+#![deny(clippy::all, clippy::pedantic, clippy::nursery)]
+#![allow(clippy::redundant_pub_crate, clippy::exit)]
+
+#[allow(unused)]
+#[path = "../messages.rs"]
+mod messages;
+
+use anyhow::{Context, Result};
+use clap::{crate_version, AppSettings, ArgGroup, Parser, Subcommand, ValueHint};
+use messages::{
+    read_frame,
+    socket_path,
+    write_frame,
+    DesktopCommand,
+    Message,
+    MonitorCommand,
+    NodeCommand,
+    QueryKind,
+    Reply,
+    Request,
+    RuleCommand,
+    ScratchpadCommand,
+    TreeCommand,
+};
+use std::{fs, os::unix::net::UnixStream, path::PathBuf, process::exit};
+
+/// Options for the `lwmc` program
+#[derive(Parser, Clone, Debug, PartialEq)]
+#[clap(
+    version = crate_version!(),
+    about = "Send a message to the lwm window manager",
+    max_term_width = 100,
+    global_setting = AppSettings::DeriveDisplayOrder,
+    disable_help_subcommand = true,
+    infer_subcommands = true,
+)]
+struct Opts {
+    /// The domain of the message
+    #[clap(subcommand)]
+    domain: DomainOpts,
+}
+
+/// Options for each domain. Without an action, the ID of the selected item is
+/// printed
+#[derive(Subcommand, Clone, Debug, PartialEq)]
+enum DomainOpts {
+    /// Quit the window manager
+    Quit,
+    /// List the focus history, newest first
+    History,
+    /// Focus an older or newer node of the focus history, without recording
+    /// it, so that repeated jumps keep going back in time
+    Jump {
+        /// older or newer, optionally followed by node modifiers like
+        /// older.local
+        selector: String,
+    },
+    /// Act on the window manager as a whole
+    #[clap(group(ArgGroup::new("action").required(true)))]
+    Wm {
+        /// Print the whole state as JSON
+        #[clap(long, short, group = "action")]
+        dump_state: bool,
+        /// Replace the state with the one in the given file
+        #[clap(
+            long,
+            short,
+            group = "action",
+            value_name = "file",
+            value_hint = ValueHint::FilePath
+        )]
+        load_state: Option<PathBuf>,
+        /// Restart the window manager in place, keeping the layout
+        #[clap(long, short, group = "action")]
+        restart:    bool,
+    },
+    /// Print events as they happen
+    Subscribe {
+        /// Events to print: all, report, monitor, desktop, node, or the name of
+        /// an event like node_focus (default: report)
+        events: Vec<String>,
+        /// Print the path of a FIFO the events are written to instead
+        #[clap(long, short)]
+        fifo:   bool,
+        /// Stop after this many events
+        #[clap(long, short, value_name = "count")]
+        count:  Option<usize>,
+    },
+    /// Dump the tree as JSON, or list the IDs of the items matching the
+    /// selector
+    #[clap(group(ArgGroup::new("kind").required(true)))]
+    Query {
+        /// Only list the items matching this selector, or these modifiers if it
+        /// starts with a '.'
+        selector:  Option<String>,
+        /// Print the tree as JSON
+        #[clap(short = 'T', long, group = "kind")]
+        tree:      bool,
+        /// List the monitors
+        #[clap(short = 'M', long, group = "kind")]
+        monitors:  bool,
+        /// List the desktops
+        #[clap(short = 'D', long, group = "kind")]
+        desktops:  bool,
+        /// List the nodes
+        #[clap(short = 'N', long, group = "kind")]
+        nodes:     bool,
+        /// Restrict the query to the monitor matching this selector
+        #[clap(long, short, value_name = "monitor_sel")]
+        monitor:   Option<String>,
+        /// Restrict the query to the desktop matching this selector
+        #[clap(long, short, value_name = "desktop_sel")]
+        desktop:   Option<String>,
+        /// Restrict the query to the node matching this selector
+        #[clap(long, short, value_name = "node_sel")]
+        node:      Option<String>,
+        /// Print names instead of IDs
+        #[clap(long, conflicts_with_all = &["tree", "nodes"])]
+        names:     bool,
+    },
+    /// Act on the monitor matching the selector
+    Monitor {
+        /// The monitor selector (default: focused)
+        selector: Option<String>,
+        /// Focus the monitor
+        #[clap(long, short, conflicts_with = "rename")]
+        focus:    bool,
+        /// Rename the monitor
+        #[clap(long, short = 'n', value_name = "name")]
+        rename:   Option<String>,
+    },
+    /// Act on the desktop matching the selector
+    Desktop {
+        /// The desktop selector (default: focused)
+        selector:      Option<String>,
+        /// Show and focus the desktop
+        #[clap(long, short, conflicts_with_all = &["rename", "presel_cancel"])]
+        focus:         bool,
+        /// Rename the desktop
+        #[clap(long, short = 'n', value_name = "name", conflicts_with = "presel_cancel")]
+        rename:        Option<String>,
+        /// Cancel the preselection of every node of the desktop
+        #[clap(long)]
+        presel_cancel: bool,
+    },
+    /// Act on the node matching the selector
+    #[clap(group(ArgGroup::new("action")))]
+    Node {
+        /// The node selector (default: focused)
+        selector:      Option<String>,
+        /// Focus the node
+        #[clap(long, short, group = "action")]
+        focus:         bool,
+        /// Close the windows of the node gracefully
+        #[clap(long, short, group = "action")]
+        close:         bool,
+        /// Kill the clients of the node
+        #[clap(long, short, group = "action")]
+        kill:          bool,
+        /// Preselect the node, so that the next window is inserted in this
+        /// direction
+        #[clap(
+            long,
+            short,
+            group = "action",
+            value_name = "dir",
+            possible_values = &["north", "south", "east", "west"]
+        )]
+        presel_dir:    Option<String>,
+        /// Change the ratio of the preselection of the node
+        #[clap(long, short = 'r', group = "action", value_name = "ratio")]
+        presel_ratio:  Option<f32>,
+        /// Cancel the preselection of the node
+        #[clap(long, group = "action")]
+        presel_cancel: bool,
+    },
+    /// Add, remove or list the rules applied to new windows
+    #[clap(group(ArgGroup::new("action").required(true)))]
+    Rule {
+        /// Add a rule for the windows matching CLASS[:INSTANCE[:NAME]]. Each
+        /// field is a glob, or a regex between '/', negated by a leading '!'
+        #[clap(long, short, group = "action", value_name = "pattern")]
+        add:      Option<String>,
+        /// The key=value pairs applied by the added rule, like desktop=^2 or
+        /// state=floating
+        #[clap(requires = "add")]
+        effect:   Vec<String>,
+        /// Remove the added rule once it has been applied
+        #[clap(long, short, requires = "add")]
+        one_shot: bool,
+        /// Remove the rules matching head, tail, ^<n> or a pattern
+        #[clap(long, short, group = "action", value_name = "desc")]
+        remove:   Option<String>,
+        /// List the rules
+        #[clap(long, short, group = "action")]
+        list:     bool,
+    },
+    /// Toggle or list the scratchpads declared in the configuration
+    #[clap(group(ArgGroup::new("action").required(true)))]
+    Scratchpad {
+        /// Show the scratchpad on the focused desktop, spawning it if it isn't
+        /// running, or hide it if it is already shown there
+        #[clap(long, short, group = "action", value_name = "name")]
+        toggle: Option<String>,
+        /// List the scratchpads and their window
+        #[clap(long, short, group = "action")]
+        list:   bool,
+    },
+}
+
+impl TryFrom<DomainOpts> for Message {
+    type Error = anyhow::Error;
+
+    fn try_from(opts: DomainOpts) -> Result<Self> {
+        Ok(match opts {
+            DomainOpts::Quit => Self::Tree(TreeCommand::Quit),
+            DomainOpts::History => Self::Tree(TreeCommand::History),
+            DomainOpts::Jump { selector } => Self::Tree(TreeCommand::Jump(selector)),
+            DomainOpts::Wm { restart: true, .. } => Self::Tree(TreeCommand::Restart),
+            DomainOpts::Wm { load_state: Some(path), .. } => {
+                // The window manager doesn't share our working directory
+                let path = fs::canonicalize(&path)
+                    .context(format!("failed to find '{}'", path.display()))?;
+                Self::Tree(TreeCommand::LoadState(path.display().to_string()))
+            },
+            DomainOpts::Wm { load_state: None, .. } => Self::Tree(TreeCommand::DumpState),
+            DomainOpts::Subscribe { events, fifo, count } =>
+                Self::Tree(TreeCommand::Subscribe { events, fifo, count }),
+            DomainOpts::Query {
+                selector,
+                tree,
+                monitors,
+                desktops,
+                nodes: _,
+                monitor,
+                desktop,
+                node,
+                names,
+            } => {
+                let kind = match (tree, monitors, desktops) {
+                    (true, ..) => QueryKind::Tree,
+                    (false, true, _) => QueryKind::Monitors,
+                    (false, false, true) => QueryKind::Desktops,
+                    (false, false, false) => QueryKind::Nodes,
+                };
+                Self::Tree(TreeCommand::Query { kind, selector, monitor, desktop, node, names })
+            },
+            DomainOpts::Monitor { selector, focus, rename } => {
+                let command = match (focus, rename) {
+                    (true, _) => MonitorCommand::Focus,
+                    (false, Some(name)) => MonitorCommand::Rename(name),
+                    (false, None) => MonitorCommand::Query,
+                };
+                Self::Monitor { selector, command }
+            },
+            DomainOpts::Desktop { selector, focus, rename, presel_cancel } => {
+                let command = match (focus, rename, presel_cancel) {
+                    (true, ..) => DesktopCommand::Focus,
+                    (false, Some(name), _) => DesktopCommand::Rename(name),
+                    (false, None, true) => DesktopCommand::PreselCancel,
+                    (false, None, false) => DesktopCommand::Query,
+                };
+                Self::Desktop { selector, command }
+            },
+            DomainOpts::Node {
+                selector,
+                focus,
+                close,
+                kill,
+                presel_dir,
+                presel_ratio,
+                presel_cancel,
+            } => {
+                let command = if focus {
+                    NodeCommand::Focus
+                } else if close {
+                    NodeCommand::Close
+                } else if kill {
+                    NodeCommand::Kill
+                } else if let Some(dir) = presel_dir {
+                    NodeCommand::PreselDir(dir)
+                } else if let Some(ratio) = presel_ratio {
+                    NodeCommand::PreselRatio(ratio.to_string())
+                } else if presel_cancel {
+                    NodeCommand::PreselCancel
+                } else {
+                    NodeCommand::Query
+                };
+                Self::Node { selector, command }
+            },
+            DomainOpts::Rule { add: Some(pattern), effect, one_shot, .. } =>
+                Self::Rule(RuleCommand::Add { pattern, effect, one_shot }),
+            DomainOpts::Rule { remove: Some(desc), .. } => Self::Rule(RuleCommand::Remove(desc)),
+            DomainOpts::Rule { .. } => Self::Rule(RuleCommand::List),
+            DomainOpts::Scratchpad { toggle: Some(name), .. } =>
+                Self::Scratchpad(ScratchpadCommand::Toggle(name)),
+            DomainOpts::Scratchpad { .. } => Self::Scratchpad(ScratchpadCommand::List),
+        })
+    }
+}
+
+/// Send a [`Request`] to the window manager and print its [`Reply`]s
+///
+/// Subscriptions made over the socket get a [`Reply`] per event, until the
+/// window manager closes the connection
+fn send(request: &Request) -> Result<()> {
+    let socket = socket_path();
+    let mut stream =
+        UnixStream::connect(&socket).context(format!("failed to connect to '{}'", socket))?;
+    let streaming = matches!(
+        request.message,
+        Message::Tree(TreeCommand::Subscribe { fifo: false, .. })
+    );
+
+    write_frame(&mut stream, request)?;
+    loop {
+        let reply: Reply = match read_frame(&mut stream) {
+            Ok(reply) => reply,
+            Err(_) if streaming => return Ok(()),
+            Err(e) => return Err(e),
+        };
+
+        if !reply.is_success() {
+            eprintln!("{}", reply.payload);
+            exit(1);
+        }
+        if !reply.payload.is_empty() {
+            println!("{}", reply.payload);
+        }
+        if !streaming {
+            return Ok(());
+        }
+    }
+}
+
+fn main() -> Result<()> {
+    let opts = Opts::parse();
+    send(&Request::new(Message::try_from(opts.domain)?))
+}
@@ -126,6 +126,20 @@ pub(crate) struct Opts {
                 auto, never. The always selection only applies to the path as of now."
     )]
     pub(crate) color_when: Option<String>,
+
+    /// Restore a state written by `lwmc wm --dump-state`
+    #[clap(
+        long = "load-state",
+        takes_value = true,
+        number_of_values = 1,
+        value_name = "file",
+        value_hint = ValueHint::FilePath,
+        long_help = "\
+        Restore the monitors, desktops, trees and focus history written by `lwmc wm \
+                --dump-state`. Windows are adopted by their ID, so this is only useful within \
+                the same X session. It is passed by `lwmc wm --restart` to the new process"
+    )]
+    pub(crate) load_state: Option<PathBuf>,
 }
 
 // =============== Prettify Help ==================
@@ -3,6 +3,8 @@
 use crate::{
     core::{AutomaticScheme, ChildPolarity, PointerAction, StateTransition, Tightness},
     geometry::Padding,
+    rule::RuleEntry,
+    scratchpad::ScratchpadEntry,
     utils::{deserialize_absolute_path, deserialize_shellexpand},
     x::input::{Button, ModMask},
 };
@@ -225,6 +227,14 @@ pub(crate) struct GlobalSettings {
     #[serde(alias = "honor-size-hints")]
     pub(crate) honor_size_hints: bool,
 
+    /// Classes of the terminals that are swallowed by the windows they spawn
+    #[serde(alias = "swallow-classes")]
+    pub(crate) swallow_classes: Vec<String>,
+
+    /// Classes of the windows that never swallow their terminal
+    #[serde(alias = "swallow-exceptions")]
+    pub(crate) swallow_exceptions: Vec<String>,
+
     /// Consider disabled monitors as disconnected
     #[serde(alias = "remove-disabled-monitors")]
     pub(crate) remove_disabled_monitors: bool,
@@ -280,6 +290,14 @@ pub(crate) struct Config {
 
     /// The mappings of keys to shell commands
     pub(crate) bindings: Option<IndexMap<String, String>>,
+
+    /// The rules applied to the windows that are managed, in order
+    #[serde(default)]
+    pub(crate) rules: Vec<RuleEntry>,
+
+    /// The scratchpads toggled by name, in order
+    #[serde(default)]
+    pub(crate) scratchpads: IndexMap<String, ScratchpadEntry>,
 }
 
 impl Config {
@@ -522,6 +540,8 @@ impl Default for GlobalSettings {
 
             center_pseudotiled: true,
             honor_size_hints:   false,
+            swallow_classes:    vec![],
+            swallow_exceptions: vec![],
 
             remove_disabled_monitors:   false,
             remove_unplugged_monitors:  false,
@@ -576,6 +596,8 @@ impl Clone for GlobalSettings {
             ignore_ewmh_fullscreen: self.ignore_ewmh_fullscreen,
             center_pseudotiled: self.center_pseudotiled,
             honor_size_hints: self.honor_size_hints,
+            swallow_classes: self.swallow_classes.clone(),
+            swallow_exceptions: self.swallow_exceptions.clone(),
             remove_disabled_monitors: self.remove_disabled_monitors,
             remove_unplugged_monitors: self.remove_unplugged_monitors,
             merge_overlapping_monitors: self.merge_overlapping_monitors,
@@ -114,20 +114,6 @@ impl Colorscheme {
 
     /// Create a new [`Colorscheme`]
     pub(crate) fn new(config: &Config) -> Result<Self> {
-        let to_hex = |s: &str| -> Result<u32> {
-            let trim = s.strip_prefix("0x").map_or_else(
-                || s.strip_prefix('#').map_or_else(|| if_6!(s), |c| if_6!(c)),
-                |c| if_6!(c),
-            );
-
-            if let Some(color) = trim {
-                return u32::from_str_radix(color, 16)
-                    .context(format!("failed to convert {} to hex", s));
-            }
-
-            Err(anyhow!("invalid color found in configuration: {}", s))
-        };
-
         Ok(Self {
             normal:  to_hex(&config.global.normal_border_color)?,
             active:  to_hex(&config.global.active_border_color)?,
@@ -137,6 +123,20 @@ impl Colorscheme {
     }
 }
 
+/// Convert a color in the form of `#RRGGBB` or `0xRRGGBB` to a [`Color`]
+pub(crate) fn to_hex(s: &str) -> Result<Color> {
+    let trim = s.strip_prefix("0x").map_or_else(
+        || s.strip_prefix('#').map_or_else(|| if_6!(s), |c| if_6!(c)),
+        |c| if_6!(c),
+    );
+
+    if let Some(color) = trim {
+        return u32::from_str_radix(color, 16).context(format!("failed to convert {} to hex", s));
+    }
+
+    Err(anyhow!("invalid color found in configuration: {}", s))
+}
+
 impl Default for Colorscheme {
     fn default() -> Self {
         Self::DEFAULT
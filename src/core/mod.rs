@@ -19,6 +19,7 @@ use std::{
     fmt,
     ops::{Add, Div, Mul, Sub},
 };
+use strum_macros::{Display, EnumString};
 use x11rb::{
     protocol::{xproto, Event},
     rust_connection::Stream,
@@ -185,6 +186,35 @@ impl DragType {
             || self == Self::Corner(Corner::TopRight)
             || self == Self::Corner(Corner::BottomRight)
     }
+
+    /// Is the type of drag moving a left [`Corner`] or [`Edge`]?
+    pub(crate) fn is_left_drag(self) -> bool {
+        self == Self::Edge(Edge::Left)
+            || self == Self::Corner(Corner::TopLeft)
+            || self == Self::Corner(Corner::BottomLeft)
+    }
+
+    /// Is the type of drag moving a bottom [`Corner`] or [`Edge`]?
+    pub(crate) fn is_bottom_drag(self) -> bool {
+        self == Self::Edge(Edge::Bottom)
+            || self == Self::Corner(Corner::BottomLeft)
+            || self == Self::Corner(Corner::BottomRight)
+    }
+}
+
+impl From<ResizeHandle> for DragType {
+    fn from(handle: ResizeHandle) -> Self {
+        match handle {
+            ResizeHandle::Left => Self::Edge(Edge::Left),
+            ResizeHandle::Top => Self::Edge(Edge::Top),
+            ResizeHandle::Right => Self::Edge(Edge::Right),
+            ResizeHandle::Bottom => Self::Edge(Edge::Bottom),
+            ResizeHandle::TopLeft => Self::Corner(Corner::TopLeft),
+            ResizeHandle::TopRight => Self::Corner(Corner::TopRight),
+            ResizeHandle::BottomRight => Self::Corner(Corner::BottomRight),
+            ResizeHandle::BottomLeft => Self::Corner(Corner::BottomLeft),
+        }
+    }
 }
 
 /// State of a [`Window`](xproto::Window) drag
@@ -257,6 +287,26 @@ impl WindowType {
         }
     }
 
+    /// Name of the [`WindowType`], its atom's suffix in lowercase
+    pub(crate) const fn name(self) -> &'static str {
+        match self {
+            Self::Combo => "combo",
+            Self::Desktop => "desktop",
+            Self::Dialog => "dialog",
+            Self::DND => "dnd",
+            Self::Dock => "dock",
+            Self::DropdownMenu => "dropdown_menu",
+            Self::Menu => "menu",
+            Self::Normal => "normal",
+            Self::Notification => "notification",
+            Self::PopupMenu => "popup_menu",
+            Self::Splash => "splash",
+            Self::Toolbar => "toolbar",
+            Self::ToolTip => "tooltip",
+            Self::Utility => "utility",
+        }
+    }
+
     /// Create a [`HashMap`] of [`Atom`]s and [`WindowType`]s
     pub(crate) fn to_hashmap(a: &Atoms) -> HashMap<Atom, Self> {
         maplit::hashmap! {
@@ -483,7 +533,8 @@ pub(crate) enum AlterState {
 }
 
 /// Window cycle direction
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, EnumString)]
+#[strum(serialize_all = "lowercase")]
 pub(crate) enum CycleDir {
     /// Cycle to the next item
     Next,
@@ -491,14 +542,18 @@ pub(crate) enum CycleDir {
     Prev,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, EnumString)]
+#[strum(serialize_all = "lowercase")]
 pub(crate) enum HistoryDir {
     Older,
     Newer,
 }
 
 /// A standard direction
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, EnumString, Display,
+)]
+#[strum(serialize_all = "lowercase")]
 pub(crate) enum Direction {
     /// North or above relative to something else
     North,
@@ -550,7 +605,8 @@ pub(crate) enum PointerAction {
 }
 
 /// The layout of the current [`Window`]
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub(crate) enum LayoutType {
     Tiled,
     Monocle,
@@ -562,7 +618,19 @@ pub(crate) enum Flip {
     Vertical,
 }
 
+/// Rotation of a tree, in degrees clockwise
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Rotation {
+    /// Rotate by 90 degrees
+    Quarter,
+    /// Rotate by 180 degrees
+    Half,
+    /// Rotate by 270 degrees
+    ThreeQuarters,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, EnumString)]
+#[strum(serialize_all = "lowercase")]
 pub(crate) enum AreaPeak {
     Biggest,
     Smallest,
@@ -30,6 +30,12 @@ impl Ratio {
     pub(crate) const fn new(numerator: i32, denominator: i32) -> Self {
         Self { numerator, denominator }
     }
+
+    /// Return the numerator and the denominator, unless one isn't positive
+    pub(crate) fn terms(self) -> Option<(u64, u64)> {
+        (self.numerator > 0 && self.denominator > 0)
+            .then(|| (self.numerator as u64, self.denominator as u64))
+    }
 }
 
 // =============================== Strut ==============================
@@ -46,14 +52,60 @@ impl Ratio {
 pub(crate) struct Strut {
     /// Window the strut is applied to
     pub(crate) window: Window,
-    /// TODO: The width on each side of the strut
+    /// Edge of the screen the space is reserved along
+    pub(crate) side:   Direction,
+    /// Width of the reserved space, from the edge of the screen
     pub(crate) width:  u32,
+    /// First coordinate along the edge covered by the strut
+    pub(crate) start:  u32,
+    /// Last coordinate along the edge covered by the strut (inclusive)
+    pub(crate) end:    u32,
 }
 
 impl Strut {
     /// Create a new [`Strut`]
-    pub(crate) const fn new(window: Window, width: u32) -> Self {
-        Self { window, width }
+    pub(crate) const fn new(
+        window: Window,
+        side: Direction,
+        width: u32,
+        start: u32,
+        end: u32,
+    ) -> Self {
+        Self { window, side, width, start, end }
+    }
+
+    /// Return how much of the `side` of `area` is reserved by the [`Strut`].
+    /// The strut is relative to the edges of the `screen`, so it only
+    /// reserves space on the areas it crosses
+    pub(crate) fn reserved(&self, area: Rectangle, screen: Rectangle) -> u32 {
+        let (x0, y0) = (i64::from(area.point.x), i64::from(area.point.y));
+        let x1 = x0 + i64::from(area.dimension.width);
+        let y1 = y0 + i64::from(area.dimension.height);
+        let (sx, sy) = (i64::from(screen.point.x), i64::from(screen.point.y));
+        let width = i64::from(self.width);
+
+        // The coordinates along the edge span `start..=end`
+        let (start, end) = (i64::from(self.start), i64::from(self.end));
+        let (spans, taken, size) = match self.side {
+            Direction::West => (start < y1 && end >= y0, sx + width - x0, x1 - x0),
+            Direction::East => (
+                start < y1 && end >= y0,
+                x1 - (sx + i64::from(screen.dimension.width) - width),
+                x1 - x0,
+            ),
+            Direction::North => (start < x1 && end >= x0, sy + width - y0, y1 - y0),
+            Direction::South => (
+                start < x1 && end >= x0,
+                y1 - (sy + i64::from(screen.dimension.height) - width),
+                y1 - y0,
+            ),
+        };
+
+        if spans && taken > 0 {
+            taken.min(size) as u32
+        } else {
+            0
+        }
     }
 }
 
@@ -336,6 +388,18 @@ impl Rectangle {
         self == other
     }
 
+    /// Move the [`Rectangle`] so that its center is the one of `area`
+    pub(crate) const fn centered_in(self, area: Self) -> Self {
+        Self {
+            point:     Point {
+                x: area.point.x + (area.dimension.width as i32 - self.dimension.width as i32) / 2,
+                y: area.point.y
+                    + (area.dimension.height as i32 - self.dimension.height as i32) / 2,
+            },
+            dimension: self.dimension,
+        }
+    }
+
     /// Return the top right [`Point`]
     pub(crate) const fn top_right(&self) -> Point {
         Point {
@@ -542,6 +606,19 @@ impl fmt::Display for Rectangle {
     }
 }
 
+impl Add<Self> for Padding {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self::Output {
+        Self::Output {
+            top:    self.top + other.top,
+            right:  self.right + other.right,
+            bottom: self.bottom + other.bottom,
+            left:   self.left + other.left,
+        }
+    }
+}
+
 impl Add<Padding> for Rectangle {
     type Output = Self;
 
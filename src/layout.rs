@@ -0,0 +1,638 @@
+//! Turning a [`Tree`] into geometry
+
+use crate::{
+    core::{Direction, LayoutType, Window},
+    geometry::{Dimension, Padding, Point, Rectangle},
+    monitor::client::ClientState,
+    tree::{NodeId, Presel, SplitType, Tree},
+    x::property::SizeHints,
+};
+use tern::t;
+
+/// Settings used to lay out a single desktop
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct LayoutParams {
+    /// The layout of the desktop
+    pub(crate) layout:             LayoutType,
+    /// The area of the monitor the desktop is shown on
+    pub(crate) monitor:            Rectangle,
+    /// Padding of the monitor and desktop combined
+    pub(crate) padding:            Padding,
+    /// Extra padding applied in [`LayoutType::Monocle`]
+    pub(crate) monocle_padding:    Padding,
+    /// Gap between windows. May be negative to overlap borders
+    pub(crate) window_gap:         i32,
+    /// Width of a window's border
+    pub(crate) border_width:       u32,
+    /// Remove the gaps in [`LayoutType::Monocle`]
+    pub(crate) gapless_monocle:    bool,
+    /// Center pseudo-tiled windows within their tile
+    pub(crate) center_pseudotiled: bool,
+    /// Apply the ICCCM size hints of the windows that no rule decided for
+    pub(crate) honor_size_hints:   bool,
+}
+
+impl LayoutParams {
+    /// Create [`LayoutParams`] without any monocle specific settings
+    pub(crate) const fn new(
+        layout: LayoutType,
+        monitor: Rectangle,
+        padding: Padding,
+        window_gap: i32,
+        border_width: u32,
+    ) -> Self {
+        Self {
+            layout,
+            monitor,
+            padding,
+            monocle_padding: Padding { top: 0, right: 0, bottom: 0, left: 0 },
+            window_gap,
+            border_width,
+            gapless_monocle: false,
+            center_pseudotiled: true,
+            honor_size_hints: false,
+        }
+    }
+
+    /// Return the gap used between windows for the current layout
+    pub(crate) const fn gap(&self) -> i32 {
+        if self.gapless_monocle && matches!(self.layout, LayoutType::Monocle) {
+            0
+        } else {
+            self.window_gap
+        }
+    }
+
+    /// Return the area that is available for tiling
+    fn tiling_area(&self) -> Rectangle {
+        let mut rect = pad(self.monitor, self.padding);
+        if matches!(self.layout, LayoutType::Monocle) {
+            rect = pad(rect, self.monocle_padding);
+        }
+
+        let gap = self.gap();
+        Rectangle {
+            point:     Point::new(rect.point.x + gap, rect.point.y + gap),
+            dimension: Dimension::new(
+                shrink(rect.dimension.width, gap, 1),
+                shrink(rect.dimension.height, gap, 1),
+            ),
+        }
+    }
+}
+
+/// Compute the geometry of every [`Node`](crate::tree::Node) in the [`Tree`]
+///
+/// Each leaf has its `tiled_rectangle` assigned from the `split_type` and
+/// `split_ratio` of its ancestors. Hidden leaves and the ones that aren't
+/// tiled take no space. Nothing is sent to the X server; instead, the
+/// [`Window`]s and the [`Rectangle`]s they should occupy are returned, to be
+/// applied with
+/// [`place_window`](crate::x::xconnection::XConnection::place_window).
+///
+/// The returned rectangles include the border of the window
+pub(crate) fn arrange(tree: &mut Tree, params: &LayoutParams) -> Vec<(Window, Rectangle)> {
+    let mut placements = vec![];
+    tree.update_vacancy();
+
+    if let Some(root) = tree.root() {
+        let area = params.tiling_area();
+        apply(tree, root, area, params, &mut placements);
+    }
+
+    placements
+}
+
+/// Recursively assign the [`Rectangle`] of a node and its children
+fn apply(
+    tree: &mut Tree,
+    id: NodeId,
+    rect: Rectangle,
+    params: &LayoutParams,
+    placements: &mut Vec<(Window, Rectangle)>,
+) {
+    let (first, second, split_type, split_ratio) = match tree.get_mut(id) {
+        Some(node) => {
+            node.set_rectangle(rect);
+            (
+                node.first_child(),
+                node.second_child(),
+                node.split_type(),
+                node.split_ratio(),
+            )
+        },
+        None => return,
+    };
+
+    match (first, second) {
+        (Some(first), Some(second)) => {
+            let (first_rect, second_rect) = if matches!(params.layout, LayoutType::Monocle)
+                || is_vacant(tree, first)
+                || is_vacant(tree, second)
+            {
+                (rect, rect)
+            } else {
+                split(rect, split_type, split_ratio)
+            };
+
+            apply(tree, first, first_rect, params, placements);
+            apply(tree, second, second_rect, params, placements);
+        },
+        _ => place_leaf(tree, id, rect, params, placements),
+    }
+}
+
+/// Assign the `tiled_rectangle` of a leaf, recording where its [`Window`] goes
+fn place_leaf(
+    tree: &mut Tree,
+    id: NodeId,
+    rect: Rectangle,
+    params: &LayoutParams,
+    placements: &mut Vec<(Window, Rectangle)>,
+) {
+    let hidden = tree.get(id).map_or(true, |n| n.is_hidden());
+    let client = match tree.get_mut(id).and_then(|n| n.client_mut()) {
+        Some(client) => client,
+        None => return,
+    };
+
+    // Never let the window become smaller than its own border
+    let min = params.border_width * 2 + 1;
+    let tiled = Rectangle {
+        point:     rect.point,
+        dimension: Dimension::new(
+            shrink(rect.dimension.width, params.gap(), min),
+            shrink(rect.dimension.height, params.gap(), min),
+        ),
+    };
+    client.set_tiled_rectangle(tiled);
+
+    if hidden {
+        return;
+    }
+
+    let honor = client
+        .get_honor_size_hints()
+        .unwrap_or(params.honor_size_hints);
+    let hints = *client.get_size_hints();
+    let hinted = |dimension| {
+        t!(honor ? apply_size_hints(&hints, dimension, params.border_width) : dimension)
+    };
+
+    let placed = match *client.get_state() {
+        // The gaps left by the size hints are shared on both sides
+        ClientState::Tiled => Rectangle {
+            point:     tiled.point,
+            dimension: hinted(tiled.dimension),
+        }
+        .centered_in(tiled),
+        ClientState::PsuedoTiled => {
+            let floating = client.get_floating_rectangle().dimension;
+            let placed = Rectangle {
+                point:     tiled.point,
+                dimension: hinted(Dimension::new(
+                    floating.width.min(tiled.dimension.width),
+                    floating.height.min(tiled.dimension.height),
+                )),
+            };
+
+            t!(params.center_pseudotiled ? placed.centered_in(tiled) : placed)
+        },
+        ClientState::Floating => {
+            let floating = *client.get_floating_rectangle();
+            Rectangle {
+                point:     floating.point,
+                dimension: hinted(floating.dimension),
+            }
+        },
+        ClientState::Fullscreen => params.monitor,
+    };
+
+    placements.push((*client.get_window(), placed));
+}
+
+/// Apply the [`SizeHints`] of a [`Window`] to a [`Dimension`] that includes
+/// its border
+fn apply_size_hints(hints: &SizeHints, dimension: Dimension, border_width: u32) -> Dimension {
+    let border = border_width * 2;
+    let inner = hints.constrain(Dimension::new(
+        dimension.width.saturating_sub(border),
+        dimension.height.saturating_sub(border),
+    ));
+
+    Dimension::new(inner.width + border, inner.height + border)
+}
+
+/// Compute the area covered by the feedback [`Window`] of a [`Presel`]
+///
+/// `rect` is the [`Rectangle`] of the preselected node, including the gap that
+/// follows it, just like the one assigned by [`arrange`]
+pub(crate) fn presel_rectangle(rect: Rectangle, presel: &Presel, window_gap: i32) -> Rectangle {
+    let width = shrink(rect.dimension.width, window_gap, 1);
+    let height = shrink(rect.dimension.height, window_gap, 1);
+    let ratio = f64::from(presel.split_ratio());
+
+    let (x, y, width, height) = match presel.split_dir() {
+        Direction::North => (0, 0, width, fence(height, ratio)),
+        Direction::West => (0, 0, fence(width, ratio), height),
+        Direction::South => {
+            let h = height - fence(height, ratio);
+            (0, height - h, width, h)
+        },
+        Direction::East => {
+            let w = width - fence(width, ratio);
+            (width - w, 0, w, height)
+        },
+    };
+
+    Rectangle::new(
+        rect.point.x + x as i32,
+        rect.point.y + y as i32,
+        width.max(1),
+        height.max(1),
+    )
+}
+
+/// Is the node taking no space in the layout?
+fn is_vacant(tree: &Tree, id: NodeId) -> bool {
+    tree.get(id).map_or(true, |n| n.is_vacant())
+}
+
+/// Split a [`Rectangle`] in two according to the [`SplitType`] and ratio
+pub(crate) fn split(rect: Rectangle, split_type: SplitType, ratio: f64) -> (Rectangle, Rectangle) {
+    let Rectangle { point, dimension } = rect;
+
+    match split_type {
+        SplitType::Vertical => {
+            let fence = fence(dimension.width, ratio);
+            (
+                Rectangle::new(point.x, point.y, fence, dimension.height),
+                Rectangle::new(
+                    point.x + fence as i32,
+                    point.y,
+                    dimension.width - fence,
+                    dimension.height,
+                ),
+            )
+        },
+        SplitType::Horizontal => {
+            let fence = fence(dimension.height, ratio);
+            (
+                Rectangle::new(point.x, point.y, dimension.width, fence),
+                Rectangle::new(
+                    point.x,
+                    point.y + fence as i32,
+                    dimension.width,
+                    dimension.height - fence,
+                ),
+            )
+        },
+    }
+}
+
+/// Return the position of the split within a length
+fn fence(length: u32, ratio: f64) -> u32 {
+    ((f64::from(length) * ratio.clamp(0.0, 1.0)) as u32).min(length)
+}
+
+/// Reduce a length by `amount`, never going below `min`
+fn shrink(length: u32, amount: i32, min: u32) -> u32 {
+    let shrunk = i64::from(length) - i64::from(amount);
+    t!(shrunk < i64::from(min) ? min : shrunk as u32)
+}
+
+/// Apply [`Padding`] to a [`Rectangle`] without underflowing
+fn pad(rect: Rectangle, padding: Padding) -> Rectangle {
+    Rectangle {
+        point:     Point::new(
+            rect.point.x + padding.left as i32,
+            rect.point.y + padding.top as i32,
+        ),
+        dimension: Dimension::new(
+            rect.dimension
+                .width
+                .saturating_sub(padding.left + padding.right)
+                .max(1),
+            rect.dimension
+                .height
+                .saturating_sub(padding.top + padding.bottom)
+                .max(1),
+        ),
+    }
+}
+
+/// Tests for [`arrange`]
+#[cfg(test)]
+mod tests {
+    use super::{arrange, presel_rectangle, split, LayoutParams};
+    use crate::{
+        core::{ChildPolarity, Direction, LayoutType},
+        geometry::{Padding, Rectangle},
+        monitor::client::ClientState,
+        test_utils::leaf,
+        tree::{Node, NodeId, Presel, SplitType, Tree},
+        x::property::SizeHints,
+    };
+
+    /// Create an internal node
+    fn internal(id: u32, split_type: SplitType, ratio: f64) -> Node {
+        let mut node = Node::new(id, None, ratio);
+        node.set_type(split_type);
+        node
+    }
+
+    /// Build the tree `(1 (2 3))`, split vertically then horizontally
+    fn three_leaves() -> (Tree, [NodeId; 3]) {
+        let mut tree = Tree::new();
+        let a = tree.insert_root(leaf(1)).unwrap();
+        let b = tree
+            .insert_at(a, leaf(2), internal(100, SplitType::Vertical, 0.5), ChildPolarity::Second)
+            .unwrap();
+        let c = tree
+            .insert_at(
+                b,
+                leaf(3),
+                internal(101, SplitType::Horizontal, 0.25),
+                ChildPolarity::Second,
+            )
+            .unwrap();
+        (tree, [a, b, c])
+    }
+
+    /// Return the tiled rectangle of the leaf
+    fn tiled(tree: &Tree, id: NodeId) -> Rectangle {
+        *tree.get(id).unwrap().client().unwrap().get_tiled_rectangle()
+    }
+
+    const MONITOR: Rectangle = Rectangle::new(0, 0, 1920, 1080);
+
+    #[test]
+    fn split_rectangle() {
+        let rect = Rectangle::new(10, 20, 100, 50);
+        assert_eq!(
+            split(rect, SplitType::Vertical, 0.3),
+            (Rectangle::new(10, 20, 30, 50), Rectangle::new(40, 20, 70, 50))
+        );
+        assert_eq!(
+            split(rect, SplitType::Horizontal, 0.5),
+            (Rectangle::new(10, 20, 100, 25), Rectangle::new(10, 45, 100, 25))
+        );
+    }
+
+    #[test]
+    fn empty_tree() {
+        let mut tree = Tree::new();
+        let params = LayoutParams::new(LayoutType::Tiled, MONITOR, Padding::default(), 0, 0);
+        assert!(arrange(&mut tree, &params).is_empty());
+    }
+
+    #[test]
+    fn single_window_fills_area() {
+        let mut tree = Tree::new();
+        let a = tree.insert_root(leaf(1)).unwrap();
+        let params = LayoutParams::new(LayoutType::Tiled, MONITOR, Padding::default(), 0, 1);
+
+        assert_eq!(arrange(&mut tree, &params), vec![(1, MONITOR)]);
+        assert_eq!(tiled(&tree, a), MONITOR);
+    }
+
+    #[test]
+    fn tiled_without_gaps() {
+        let (mut tree, [a, b, c]) = three_leaves();
+        let params = LayoutParams::new(LayoutType::Tiled, MONITOR, Padding::default(), 0, 1);
+
+        assert_eq!(arrange(&mut tree, &params), vec![
+            (1, Rectangle::new(0, 0, 960, 1080)),
+            (2, Rectangle::new(960, 0, 960, 270)),
+            (3, Rectangle::new(960, 270, 960, 810)),
+        ]);
+        assert_eq!(tiled(&tree, b), Rectangle::new(960, 0, 960, 270));
+        assert_eq!(
+            tree.get(tree.parent(c).unwrap()).unwrap().rectangle(),
+            Rectangle::new(960, 0, 960, 1080)
+        );
+        assert_eq!(tiled(&tree, a), Rectangle::new(0, 0, 960, 1080));
+    }
+
+    #[test]
+    fn tiled_with_padding_and_gaps() {
+        let (mut tree, _) = three_leaves();
+        let padding = Padding { top: 20, right: 0, bottom: 0, left: 0 };
+        let params = LayoutParams::new(LayoutType::Tiled, MONITOR, padding, 10, 1);
+
+        // Usable area is (10, 30) 1910x1050
+        assert_eq!(arrange(&mut tree, &params), vec![
+            (1, Rectangle::new(10, 30, 945, 1040)),
+            (2, Rectangle::new(965, 30, 945, 252)),
+            (3, Rectangle::new(965, 292, 945, 778)),
+        ]);
+    }
+
+    #[test]
+    fn monocle_stacks_windows() {
+        let (mut tree, _) = three_leaves();
+        let mut params = LayoutParams::new(LayoutType::Monocle, MONITOR, Padding::default(), 10, 1);
+        params.gapless_monocle = true;
+
+        let placements = arrange(&mut tree, &params);
+        assert_eq!(placements.len(), 3);
+        assert!(placements.iter().all(|(_, r)| *r == MONITOR));
+    }
+
+    #[test]
+    fn non_tiled_states() {
+        let (mut tree, [a, b, c]) = three_leaves();
+        let params = LayoutParams::new(LayoutType::Tiled, MONITOR, Padding::default(), 0, 1);
+
+        let client = tree.get_mut(a).unwrap().client_mut().unwrap();
+        client.set_state(ClientState::Floating);
+        client.set_floating_rectangle(Rectangle::new(100, 100, 300, 200));
+
+        let client = tree.get_mut(b).unwrap().client_mut().unwrap();
+        client.set_state(ClientState::PsuedoTiled);
+        client.set_floating_rectangle(Rectangle::new(0, 0, 160, 2000));
+
+        tree.get_mut(c)
+            .unwrap()
+            .client_mut()
+            .unwrap()
+            .set_state(ClientState::Fullscreen);
+
+        // The windows that aren't tiled leave their space to the others
+        assert_eq!(arrange(&mut tree, &params), vec![
+            (1, Rectangle::new(100, 100, 300, 200)),
+            (2, Rectangle::new(880, 0, 160, 1080)),
+            (3, MONITOR),
+        ]);
+        assert!(!tree.get(tree.parent(c).unwrap()).unwrap().is_vacant());
+        assert!(tree.get(a).unwrap().is_vacant());
+        assert_eq!(tiled(&tree, b), MONITOR);
+
+        tree.get_mut(a)
+            .unwrap()
+            .client_mut()
+            .unwrap()
+            .set_state(ClientState::Tiled);
+        assert_eq!(arrange(&mut tree, &params)[0], (1, Rectangle::new(0, 0, 960, 1080)));
+        assert_eq!(tiled(&tree, b), Rectangle::new(960, 0, 960, 1080));
+    }
+
+    #[test]
+    fn size_hints() {
+        let (mut tree, [a, b, _]) = three_leaves();
+        let mut params = LayoutParams::new(LayoutType::Tiled, MONITOR, Padding::default(), 0, 1);
+
+        // The cells of a terminal
+        let hints = SizeHints {
+            base_width: Some(2),
+            base_height: Some(2),
+            inc_width: Some(7),
+            inc_height: Some(15),
+            ..SizeHints::default()
+        };
+        for id in [a, b] {
+            tree.get_mut(id)
+                .unwrap()
+                .client_mut()
+                .unwrap()
+                .set_size_hints(hints);
+        }
+        tree.get_mut(a)
+            .unwrap()
+            .client_mut()
+            .unwrap()
+            .set_honor_size_hints(Some(true));
+
+        // The window is centered in its tile, which stays the same
+        assert_eq!(arrange(&mut tree, &params), vec![
+            (1, Rectangle::new(2, 5, 956, 1069)),
+            (2, Rectangle::new(960, 0, 960, 270)),
+            (3, Rectangle::new(960, 270, 960, 810)),
+        ]);
+        assert_eq!(tiled(&tree, a), Rectangle::new(0, 0, 960, 1080));
+
+        // A rule wins over the configuration
+        params.honor_size_hints = true;
+        tree.get_mut(a)
+            .unwrap()
+            .client_mut()
+            .unwrap()
+            .set_honor_size_hints(Some(false));
+        assert_eq!(arrange(&mut tree, &params), vec![
+            (1, Rectangle::new(0, 0, 960, 1080)),
+            (2, Rectangle::new(962, 5, 956, 259)),
+            (3, Rectangle::new(960, 270, 960, 810)),
+        ]);
+    }
+
+    #[test]
+    fn hidden_leaves_are_vacant() {
+        let (mut tree, [a, b, c]) = three_leaves();
+        let params = LayoutParams::new(LayoutType::Tiled, MONITOR, Padding::default(), 0, 1);
+
+        tree.get_mut(b).unwrap().set_hidden(true);
+        assert_eq!(arrange(&mut tree, &params), vec![
+            (1, Rectangle::new(0, 0, 960, 1080)),
+            (3, Rectangle::new(960, 0, 960, 1080)),
+        ]);
+        assert!(!tree.get(tree.parent(c).unwrap()).unwrap().is_vacant());
+
+        tree.get_mut(c).unwrap().set_hidden(true);
+        assert_eq!(arrange(&mut tree, &params), vec![(1, MONITOR)]);
+        assert!(tree.get(tree.parent(c).unwrap()).unwrap().is_vacant());
+
+        tree.get_mut(b).unwrap().set_hidden(false);
+        tree.get_mut(c).unwrap().set_hidden(false);
+        assert_eq!(arrange(&mut tree, &params).len(), 3);
+        assert_eq!(tiled(&tree, a), Rectangle::new(0, 0, 960, 1080));
+    }
+
+    #[test]
+    fn minimum_size_is_kept() {
+        let mut tree = Tree::new();
+        tree.insert_root(leaf(1)).unwrap();
+        let monitor = Rectangle::new(0, 0, 10, 10);
+        let params = LayoutParams::new(LayoutType::Tiled, monitor, Padding::default(), 20, 2);
+
+        let placements = arrange(&mut tree, &params);
+        assert_eq!(placements[0].1.dimension.width, 5);
+        assert_eq!(placements[0].1.dimension.height, 5);
+    }
+
+    #[test]
+    fn presel_feedback_area() {
+        let mut tree = Tree::new();
+        let a = tree.insert_root(leaf(1)).unwrap();
+        let params = LayoutParams::new(LayoutType::Tiled, MONITOR, Padding::default(), 10, 1);
+        arrange(&mut tree, &params);
+
+        let rect = tree.get(a).unwrap().rectangle();
+        let presel = *tree.presel_dir(a, Direction::West, 0.25).unwrap();
+        assert_eq!(
+            presel_rectangle(rect, &presel, params.gap()),
+            Rectangle::new(10, 10, 475, 1060)
+        );
+
+        let presel = *tree.presel_dir(a, Direction::East, 0.25).unwrap();
+        assert_eq!(
+            presel_rectangle(rect, &presel, params.gap()),
+            Rectangle::new(485, 10, 1425, 1060)
+        );
+
+        // Cancel first, as an existing preselection keeps its ratio
+        tree.presel_cancel(a);
+        let presel = *tree.presel_dir(a, Direction::South, 0.5).unwrap();
+        assert_eq!(
+            presel_rectangle(rect, &presel, params.gap()),
+            Rectangle::new(10, 540, 1900, 530)
+        );
+    }
+
+    #[test]
+    fn insert_into_presel() {
+        let (mut tree, [a, b, c]) = three_leaves();
+        let params = LayoutParams::new(LayoutType::Tiled, MONITOR, Padding::default(), 0, 1);
+
+        tree.presel_dir(a, Direction::North, 0.5).unwrap();
+        tree.presel_ratio(a, 0.4).unwrap();
+        assert!(tree.presel_ratio(a, 1.5).is_err());
+        assert!(tree.presel_ratio(a, f32::NAN).is_err());
+
+        let (d, presel) = tree
+            .insert_node(
+                Some(a),
+                leaf(4),
+                Node::new(102, None, 0.5),
+                crate::core::AutomaticScheme::LongestSide,
+                ChildPolarity::Second,
+            )
+            .unwrap();
+
+        assert_eq!(presel.map(|p| p.split_dir()), Some(Direction::North));
+        assert!(tree.get(a).unwrap().presel().is_none());
+        assert_eq!(tree.leaves().collect::<Vec<_>>(), vec![d, a, b, c]);
+
+        arrange(&mut tree, &params);
+        assert_eq!(
+            *tree.get(d).unwrap().client().unwrap().get_tiled_rectangle(),
+            Rectangle::new(0, 0, 960, 432)
+        );
+        assert_eq!(
+            *tree.get(a).unwrap().client().unwrap().get_tiled_rectangle(),
+            Rectangle::new(0, 432, 960, 648)
+        );
+    }
+
+    #[test]
+    fn cancel_presel() {
+        let (mut tree, [a, b, _]) = three_leaves();
+        tree.presel_dir(a, Direction::East, 0.5).unwrap();
+        tree.presel_dir(b, Direction::West, 0.5).unwrap();
+        assert_eq!(tree.preselected().collect::<Vec<_>>(), vec![a, b]);
+
+        assert!(tree.presel_cancel(a).is_some());
+        assert!(tree.presel_cancel(a).is_none());
+        assert_eq!(tree.presel_cancel_all().len(), 1);
+        assert_eq!(tree.preselected().count(), 0);
+    }
+}
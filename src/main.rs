@@ -133,6 +133,7 @@ mod cycle;
 mod error;
 mod events;
 mod geometry;
+mod layout;
 mod macros;
 mod manager;
 mod messages;
@@ -140,8 +141,12 @@ mod monitor;
 mod pointer;
 mod query;
 mod rule;
+mod scratchpad;
 mod stack;
+mod state;
 mod subscribe;
+#[cfg(test)]
+mod test_utils;
 mod tree;
 mod utils;
 mod x;
@@ -154,6 +159,7 @@ use std::{
 };
 
 use anyhow::Result;
+use clap::Parser;
 use colored::Colorize;
 use config::Config;
 
@@ -168,18 +174,22 @@ use x11rb::{
 
 use x::{utils::XUtility, xconnection::XConnection};
 
-use crate::tree::Presel;
+use crate::{cli::Opts, manager::wmanager::WindowManager};
 
 fn main() -> Result<()> {
-    // let (conn, screen_num) = XUtility::setup_connection()?;
+    let opts = Opts::parse();
+    let (conn, screen_num) = XUtility::setup_connection()?;
     let config = Config::load_default()?;
-    // let xconn = LWM::new(conn, screen_num, &config)?;
+    let xconn = XConnection::new(conn, screen_num, &config)?;
 
     log::debug!("{}: {:#?}", "Configuration options".bright_blue(), config);
 
-    let presel = Presel::new(0.5);
-    let s = serde_json::to_string(&presel)?;
-    println!("{}", s);
-
-    Ok(())
+    let mut wm = WindowManager::new(xconn, config)?;
+    if let Some(path) = &opts.load_state {
+        // Keep the windows that were adopted rather than failing to start
+        if let Err(e) = wm.load_state(path) {
+            log::error!("failed to load the state: {:?}", e);
+        }
+    }
+    wm.run()
 }
@@ -1,3 +1,3 @@
 //! Interactions with structs comprising the window manager
 
-// pub(crate) mod wmanager;
+pub(crate) mod wmanager;
@@ -0,0 +1,2334 @@
+//! The event loop of the window manager
+
+use crate::{
+    config::{Config, SHELL},
+    core::{
+        ChildPolarity, Direction, StackMode, StateTransition, Window, WindowState, WindowType, Xid,
+    },
+    geometry::{Extents, Point, Rectangle, Strut},
+    layout::{arrange, LayoutParams},
+    messages::{
+        DesktopCommand,
+        Message,
+        MonitorCommand,
+        NodeCommand,
+        QueryKind,
+        Reply,
+        Request,
+        RuleCommand,
+        ScratchpadCommand,
+        TreeCommand,
+        PROTOCOL_VERSION,
+    },
+    monitor::client::{Client, ClientState},
+    pointer::{Drag, MoveResize, KEY_ESCAPE, KEY_RETURN},
+    query::{
+        jump_in_history,
+        query_desktops,
+        query_history,
+        query_monitors,
+        query_nodes,
+        query_tree,
+        QueryScope,
+    },
+    rule::{Matcher, PendingRule, Rule, RuleConsequence, RuleList},
+    scratchpad::ScratchpadList,
+    stack::{StackLayer, StackLayer1, StackManager},
+    state::State,
+    subscribe::{report, Event, Subscriber, SubscriberList, SubscriberMask},
+    tree::{Coordinates, Desktop, Monitor, Node, Tree},
+    utils::{process_ancestors, process_parent},
+    x::{
+        event::{ClientMessageEvent, ConfigureRequestData, PropertyEvent, XEvent},
+        stream::Stream,
+        xconnection::XConnection,
+    },
+    WM_NAME,
+};
+use anyhow::{anyhow, Context, Result};
+use std::{
+    collections::HashMap,
+    env,
+    ffi::OsString,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    mem,
+    os::unix::{fs::OpenOptionsExt, process::CommandExt},
+    path::{Path, PathBuf},
+    process::{self, Command},
+};
+use tern::t;
+use x11rb::protocol::xproto::{AtomEnum, MapState};
+
+/// Name of the [`Monitor`] used when no output information is available
+const DEFAULT_MONITOR_NAME: &str = "MONITOR";
+/// Name of the [`Desktop`] used when none is configured
+const DEFAULT_DESKTOP_NAME: &str = "Desktop";
+/// `_NET_WM_DESKTOP` of the [`Window`]s shown on every [`Desktop`]
+const ALL_DESKTOPS: usize = 0xFFFF_FFFF;
+
+/// The window manager, which dispatches the events of the X-Server and the
+/// requests received on the socket to the [`State`]
+pub(crate) struct WindowManager {
+    /// Connection to the X-Server
+    xconn:       XConnection,
+    /// Configuration of the user
+    config:      Config,
+    /// Every [`Monitor`], [`Desktop`] and [`Node`] that is managed
+    state:       State,
+    /// Clients that subscribed to events
+    subscribers: SubscriberList,
+    /// The last report sent to the subscribers
+    report:      String,
+    /// Is the event loop running?
+    running:     bool,
+    /// Where the state was dumped when asked to restart
+    restart:     Option<PathBuf>,
+    /// Rules applied to the [`Window`]s that are managed
+    rules:       RuleList,
+    /// [`Window`]s waiting for the external rules command to answer
+    pending:     Vec<PendingRule>,
+    /// Named [`Window`]s toggled on the focused [`Desktop`]
+    scratchpads: ScratchpadList,
+    /// The order in which the [`Window`]s are stacked
+    stack:       StackManager,
+    /// Space reserved by the docks at the edges of the screen
+    struts:      Vec<Strut>,
+    /// The [`Window`] being moved or resized, while the pointer is grabbed
+    drag:        Option<Drag>,
+    /// Classes of the terminals that are swallowed
+    terminals:   Vec<Matcher>,
+    /// Classes of the [`Window`]s that never swallow a terminal
+    exceptions:  Vec<Matcher>,
+}
+
+impl WindowManager {
+    /// Become the window manager and adopt the [`Window`]s that are already
+    /// mapped
+    pub(crate) fn new(xconn: XConnection, config: Config) -> Result<Self> {
+        xconn.become_wm()?;
+        xconn.init(WM_NAME!(), &config.global.desktops)?;
+
+        let global = &config.global;
+        let mut state = State::new(global.directional_focus_tightness);
+
+        let rectangle = xconn
+            .get_window_geometry(xconn.root())
+            .context("failed to get the geometry of the root window")?;
+        let mut monitor = Monitor::new(
+            String::from(DEFAULT_MONITOR_NAME),
+            xconn.generate_id()?,
+            0,
+            rectangle,
+        );
+
+        let names = if global.desktops.is_empty() {
+            vec![String::from(DEFAULT_DESKTOP_NAME)]
+        } else {
+            global.desktops.clone()
+        };
+        for name in names {
+            monitor.add_desktop(Desktop::new(
+                name,
+                xconn.generate_id()?,
+                global.padding,
+                global.window_gap as isize,
+                global.border_width as usize,
+            ));
+        }
+        state.add_monitor(monitor);
+
+        // The entries were validated when the configuration was loaded
+        let mut rules = RuleList::new();
+        for entry in &config.rules {
+            if let Err(e) = rules.add(Rule::from(entry.clone())) {
+                log::error!("invalid rule in the configuration: {}", e);
+            }
+        }
+
+        let terminals = compile_classes(&global.swallow_classes);
+        let exceptions = compile_classes(&global.swallow_exceptions);
+        let scratchpads = ScratchpadList::new(&config.scratchpads);
+        let mut wm = Self {
+            xconn,
+            config,
+            state,
+            subscribers: SubscriberList::new(),
+            report: String::new(),
+            running: false,
+            restart: None,
+            rules,
+            pending: vec![],
+            scratchpads,
+            stack: StackManager::new(),
+            struts: vec![],
+            drag: None,
+            terminals,
+            exceptions,
+        };
+        wm.adopt_windows()?;
+        wm.update_work_areas()?;
+
+        Ok(wm)
+    }
+
+    /// Manage the [`Window`]s that were mapped before the window manager
+    /// started
+    fn adopt_windows(&mut self) -> Result<()> {
+        let tree = self.xconn.query_tree(self.xconn.root())?;
+
+        for window in tree.children {
+            let viewable = self
+                .xconn
+                .get_window_attributes(window)
+                .map_or(false, |attr| {
+                    !attr.override_redirect && attr.map_state == MapState::VIEWABLE
+                });
+
+            if !viewable {
+                continue;
+            }
+            if self.xconn.must_manage_window(window) {
+                self.manage(window)?;
+            } else {
+                self.map_unmanaged(window)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Run the event loop until asked to quit, then clean up
+    ///
+    /// Every pending [`XEvent`] is handled before the requests received on the
+    /// socket, and neither side is waited on while the other has work to do
+    pub(crate) fn run(mut self) -> Result<()> {
+        self.running = true;
+
+        while self.running {
+            while let Some(event) = self.xconn.next_event() {
+                if let Err(e) = self.handle_event(event) {
+                    log::warn!("failed to handle event: {:?}", e);
+                }
+            }
+
+            for (stream, request) in self.xconn.receive_requests::<Request>() {
+                self.handle_request(stream, request);
+            }
+            self.finish_pending_rules();
+            self.restack();
+            self.put_status();
+
+            self.xconn.flush();
+            if self.running {
+                let fds = self.pending.iter().map(PendingRule::fd).collect::<Vec<_>>();
+                self.xconn.wait_for_updates(&fds);
+            }
+        }
+
+        if let Some(path) = self.restart.take() {
+            // The FIFOs of the subscribers are removed when they are dropped
+            drop(mem::take(&mut self.subscribers));
+            let error = exec_restart(&path);
+            drop(fs::remove_file(&path));
+            return Err(error);
+        }
+        self.xconn.cleanup();
+
+        Ok(())
+    }
+
+    // ============================ Events ============================
+
+    /// Dispatch an [`XEvent`] to its handler, or queue it if its [`Window`] is
+    /// waiting for the external rules command
+    fn handle_event(&mut self, event: XEvent) -> Result<()> {
+        log::trace!("handling event: {:?}", event);
+
+        if let Some(window) = event.window() {
+            if let Some(pending) = self.pending.iter_mut().find(|p| p.window() == window) {
+                pending.queue(event);
+                return Ok(());
+            }
+        }
+
+        match event {
+            XEvent::MapRequest(window, override_redirect) =>
+                self.map_request(window, override_redirect),
+            XEvent::UnmapNotify(window) => self.unmap_notify(window),
+            XEvent::DestroyNotify(window) => self.unmanage(window),
+            XEvent::ConfigureRequest(data) => self.configure_request(&data),
+            XEvent::PropertyNotify(event) => self.property_notify(&event),
+            XEvent::ClientMessage(event) => self.client_message(&event),
+            XEvent::MouseMotion(event) => self.drag_motion(event.location),
+            XEvent::MouseRelease(_) => self.end_drag(false),
+            XEvent::KeyPress(_, event) => self.drag_key(event.keycode),
+            XEvent::ConfigureNotify(_)
+            | XEvent::MapNotify(_)
+            | XEvent::EnterNotify(..)
+            | XEvent::LeaveNotify(..)
+            | XEvent::ReparentNotify(_)
+            | XEvent::KeyRelease
+            | XEvent::MouseEvent(_)
+            | XEvent::RandrNotify
+            | XEvent::ScreenChange
+            | XEvent::Unknown(_) => Ok(()),
+        }
+    }
+
+    /// A client asks for its [`Window`] to be shown
+    fn map_request(&mut self, window: Window, override_redirect: bool) -> Result<()> {
+        if override_redirect || self.state.locate_window(window).is_some() {
+            return self.xconn.map_window(window);
+        }
+
+        if !self.xconn.must_manage_window(window) {
+            return self.map_unmanaged(window);
+        }
+
+        self.manage(window)
+    }
+
+    /// Apply the [`Rule`]s to a new [`Window`], then manage it
+    fn manage(&mut self, window: Window) -> Result<()> {
+        let mut csq = RuleConsequence::new(
+            self.xconn.get_icccm_window_class(window),
+            self.xconn.get_icccm_window_instance(window),
+            self.xconn.get_icccm_window_name(window),
+        );
+        csq.set_window_type(
+            self.xconn
+                .get_window_preferred_type(window)
+                .name()
+                .to_owned(),
+        );
+        csq.set_role(self.xconn.get_window_role(window));
+        if self.xconn.must_free_window(window) {
+            csq.set_state(Some(ClientState::Floating));
+        }
+        if self.xconn.window_is_sticky(window) {
+            csq.set_sticky(true);
+        }
+        self.rules.apply(&mut csq);
+
+        if let Some(cmd) = &self.config.global.external_rules_cmd {
+            match PendingRule::spawn(cmd, window, csq.clone()) {
+                Ok(pending) => {
+                    self.pending.push(pending);
+                    return Ok(());
+                },
+                Err(e) => log::error!("{:#}", e),
+            }
+        }
+
+        self.claim_scratchpad(window, &mut csq);
+        self.manage_with(window, &csq)
+    }
+
+    /// Manage the [`Window`]s whose external rules command answered, then
+    /// handle the events that were queued for them
+    fn finish_pending_rules(&mut self) {
+        let mut idx = 0;
+        while idx < self.pending.len() {
+            if !self.pending[idx].read() {
+                idx += 1;
+                continue;
+            }
+
+            let (window, mut csq, events) = self.pending.remove(idx).finish();
+            self.claim_scratchpad(window, &mut csq);
+            if let Err(e) = self.manage_with(window, &csq) {
+                log::warn!("failed to manage Window({:#0x}): {:?}", window, e);
+            }
+            for event in events {
+                if let Err(e) = self.handle_event(event) {
+                    log::warn!("failed to handle event: {:?}", e);
+                }
+            }
+        }
+    }
+
+    /// Insert a new [`Window`] where the [`RuleConsequence`] says, the focused
+    /// [`Node`] by default, and focus it unless told otherwise
+    fn manage_with(&mut self, window: Window, csq: &RuleConsequence) -> Result<()> {
+        if self.state.locate_window(window).is_some() {
+            log::debug!("Window({:#0x}) is already managed", window);
+            return Ok(());
+        }
+        if !*csq.get_manage() {
+            return self.map_unmanaged(window);
+        }
+
+        log::debug!("managing Window({:#0x})", window);
+        let global = &self.config.global;
+        let mut loc = match self.rule_target(csq) {
+            Ok(loc) => loc,
+            Err(e) => {
+                log::warn!("failed to place Window({:#0x}) by its rules: {:#}", window, e);
+                self.state
+                    .focused()
+                    .context("there is no desktop to manage a window on")?
+            },
+        };
+        if *csq.get_sticky() {
+            // Sticky nodes live on the desktop shown on their monitor
+            if let Some(desk) = self.state.monitor(loc.monitor).and_then(Monitor::desk) {
+                if desk.id() != loc.desktop {
+                    loc = Coordinates::new(loc.monitor, desk.id(), desk.focus());
+                }
+            }
+        }
+        let monitor = self
+            .state
+            .monitor(loc.monitor)
+            .context(format!("Monitor({:#0x}) does not exist", loc.monitor))?;
+        let shown = monitor.desk_id() == Some(loc.desktop) && !*csq.get_hidden();
+
+        let mut client = Client::new(
+            window,
+            csq.get_name().clone(),
+            csq.get_class_name().clone(),
+            csq.get_instance_name().clone(),
+            t!(*csq.get_border() ? global.border_width as usize : 0),
+        );
+        client.set_shown(shown);
+        if let Some(state) = *csq.get_state() {
+            client.set_state(state);
+        }
+        if let Some(layer) = *csq.get_layer() {
+            client.set_layer(layer);
+        }
+        if let (_, Some(hints)) = self.xconn.get_icccm_window_size_hints(window, None, &None) {
+            client.set_size_hints(hints);
+        }
+        client.set_honor_size_hints(*csq.get_honor_size_hints());
+        if let Some(mut rect) = csq
+            .get_rect()
+            .map_or_else(|| self.xconn.get_window_geometry(window).ok(), Some)
+        {
+            if *csq.get_center() {
+                rect = rect.centered_in(monitor.rectangle());
+            }
+            client.set_floating_rectangle(rect);
+        }
+
+        let pid = self
+            .xconn
+            .get_window_pid(window)
+            .or_else(|| self.xconn.get_window_pid1(window).ok());
+        client.set_pid(pid);
+        client.set_ppid(pid.and_then(process_parent));
+        if !*csq.get_hidden() {
+            if let Some(terminal) = self.swallow_target(&client) {
+                return self.swallow(terminal, client, *csq.get_focus());
+            }
+        }
+
+        let ratio = f64::from(global.split_ratio);
+        let mut node = Node::new(window, Some(client), ratio);
+        node.set_hidden(*csq.get_hidden());
+        node.set_sticky(*csq.get_sticky());
+        node.set_private(*csq.get_private());
+        node.set_locked(*csq.get_locked());
+        node.set_marked(*csq.get_marked());
+        let parent = Node::new(self.xconn.generate_id()?, None, ratio);
+        let polarity = global.initial_polarity.unwrap_or(ChildPolarity::Second);
+        let scheme = global.automatic_scheme;
+
+        let desktop = self
+            .state
+            .desktop_mut(loc.desktop)
+            .context("the target desktop does not exist")?;
+        let tree = desktop.tree_mut();
+        if let Some(target) = loc.node {
+            let presel_ratio = csq.get_split_ratio().unwrap_or(ratio) as f32;
+            if let Some(dir) = *csq.get_split_dir() {
+                tree.presel_dir(target, dir, presel_ratio);
+            }
+            if csq.get_split_ratio().is_some() {
+                if let Err(e) = tree.presel_ratio(target, presel_ratio) {
+                    log::warn!("failed to apply the split ratio of Window({:#0x}): {:#}", window, e);
+                }
+            }
+        }
+        let parent_id = loc.node.and_then(|n| tree.get(n)).map_or(0, Node::id);
+        let (id, presel) = tree
+            .insert_node(loc.node, node, parent, scheme, polarity)
+            .context(format!("failed to insert Window({:#0x})", window))?;
+        if *csq.get_focus() || desktop.focus().is_none() {
+            desktop.set_focus(Some(id));
+        }
+
+        if let Some(presel) = presel {
+            self.xconn.destroy_presel_feedback(&presel)?;
+        }
+
+        let loc = Coordinates::new(loc.monitor, loc.desktop, Some(id));
+        self.subscribers.emit(&Event::NodeAdd {
+            monitor: loc.monitor,
+            desktop: loc.desktop,
+            parent:  parent_id,
+            node:    window,
+        });
+        self.state.update_sticky_counts();
+        self.add_to_stack(window);
+
+        self.xconn
+            .init_window(window, self.config.global.focus_follows_pointer)?;
+        self.xconn.insert_window_in_save_set(window)?;
+        self.update_window_desktop(&loc)?;
+        self.arrange(&loc)?;
+        if shown {
+            self.xconn.map_window(window)?;
+        }
+
+        if *csq.get_focus() {
+            if *csq.get_follow() {
+                self.focus(loc)?;
+            } else {
+                self.state.history_mut().add(loc);
+                self.focus_desktop_node(loc.desktop)?;
+            }
+        }
+        self.update_client_list()
+    }
+
+    /// Resolve the monitor, desktop and node selectors of a [`RuleConsequence`]
+    /// into the [`Coordinates`] a new [`Window`] is inserted at
+    fn rule_target(&self, csq: &RuleConsequence) -> Result<Coordinates> {
+        let mut loc = self
+            .state
+            .focused()
+            .context("there is no desktop to manage a window on")?;
+
+        if let Some(desc) = csq.get_monitor_desc() {
+            let id = Monitor::from_desc(desc, &self.state, &loc)?;
+            loc = self
+                .state
+                .monitor(id)
+                .and_then(Monitor::desk)
+                .map(|d| Coordinates::new(id, d.id(), d.focus()))
+                .context(format!("Monitor({:#0x}) has no desktop", id))?;
+        }
+        if let Some(desc) = csq.get_desktop_desc() {
+            loc = Desktop::from_desc(desc, &self.state, &loc)?;
+        }
+        if let Some(desc) = csq.get_node_desc() {
+            loc = Node::from_desc(desc, &self.state, &loc)?;
+        }
+
+        Ok(loc)
+    }
+
+    /// Give a new [`Window`] to the [`Scratchpad`](crate::scratchpad::Scratchpad)
+    /// that spawned it, if any. The [`Window`] then floats over the focused
+    /// [`Desktop`] with the geometry of the scratchpad, whatever the rules say
+    fn claim_scratchpad(&mut self, window: Window, csq: &mut RuleConsequence) {
+        let geometry = match self.scratchpads.claim(window, csq) {
+            Some(geometry) => geometry,
+            None => return,
+        };
+        let area = match self.state.focused_monitor() {
+            Some(monitor) => monitor.rectangle(),
+            None => return,
+        };
+
+        csq.set_monitor_desc(None);
+        csq.set_desktop_desc(None);
+        csq.set_node_desc(None);
+        csq.set_state(Some(ClientState::Floating));
+        csq.set_rect(Some(geometry.resolve(area)));
+        csq.set_center(false);
+        csq.set_hidden(false);
+        csq.set_manage(true);
+        csq.set_focus(true);
+        csq.set_follow(true);
+    }
+
+    // =========================== Swallowing ===========================
+
+    /// Find the terminal a new [`Client`] was spawned from, if the [`Client`]
+    /// swallows it
+    ///
+    /// Floating and fullscreen [`Client`]s, the terminals themselves and the
+    /// exceptions don't swallow anything
+    fn swallow_target(&self, client: &Client) -> Option<Coordinates> {
+        let class = client.get_class();
+        if matches!(client.get_state(), ClientState::Floating | ClientState::Fullscreen)
+            || class_matches(&self.terminals, class)
+            || class_matches(&self.exceptions, class)
+        {
+            return None;
+        }
+
+        process_ancestors((*client.get_pid())?)
+            .into_iter()
+            .find_map(|pid| {
+                self.state.locate_client(|c| {
+                    *c.get_pid() == Some(pid) && class_matches(&self.terminals, c.get_class())
+                })
+            })
+    }
+
+    /// Hand the place of a terminal over to a [`Client`] it spawned, hiding the
+    /// terminal until the [`Client`] goes away
+    fn swallow(&mut self, loc: Coordinates, mut client: Client, focus: bool) -> Result<()> {
+        let window = *client.get_window();
+        let node = self
+            .state
+            .node_mut(&loc)
+            .context("the swallowed terminal does not exist")?;
+        let shown = node.client().map_or(false, |c| *c.get_shown());
+        client.set_shown(shown);
+        let mut terminal = node
+            .replace_client(client)
+            .context("the swallowed terminal has no client")?;
+        let terminal_window = *terminal.get_window();
+        terminal.set_shown(false);
+        if let Some(client) = node.client_mut() {
+            client.swallow(terminal);
+        }
+        log::debug!("Window({:#0x}) swallows Window({:#0x})", window, terminal_window);
+
+        self.emit_handover(&loc, terminal_window, window);
+        self.stack.remove(terminal_window);
+        self.add_to_stack(window);
+        self.xconn
+            .init_window(window, self.config.global.focus_follows_pointer)?;
+        self.xconn.insert_window_in_save_set(window)?;
+        self.update_window_desktop(&loc)?;
+        // The terminal left the tree, so this isn't mistaken for a withdrawal
+        self.xconn.unmap_window(terminal_window)?;
+        self.arrange(&loc)?;
+        if shown {
+            self.xconn.map_window(window)?;
+        }
+
+        if focus {
+            self.focus(loc)?;
+        } else {
+            self.focus_desktop_node(loc.desktop)?;
+        }
+        self.update_client_list()
+    }
+
+    /// Give its place back to the terminal swallowed by a [`Window`] that went
+    /// away
+    fn unswallow(&mut self, loc: Coordinates, window: Window, mut terminal: Client) -> Result<()> {
+        let terminal_window = *terminal.get_window();
+        let node = self
+            .state
+            .node_mut(&loc)
+            .context(format!("Window({:#0x}) is not managed", window))?;
+        let shown = node.client().map_or(false, |c| *c.get_shown());
+        terminal.set_shown(shown);
+        node.replace_client(terminal);
+        log::debug!("Window({:#0x}) is back in place of Window({:#0x})", terminal_window, window);
+
+        self.emit_handover(&loc, window, terminal_window);
+        self.add_to_stack(terminal_window);
+        self.arrange(&loc)?;
+        if shown {
+            self.xconn.map_window(terminal_window)?;
+        }
+        self.focus_desktop_node(loc.desktop)?;
+        self.update_client_list()
+    }
+
+    /// Tell the subscribers that the leaf at `loc` went from holding one
+    /// [`Window`] to another
+    fn emit_handover(&mut self, loc: &Coordinates, from: Window, to: Window) {
+        let parent = self
+            .state
+            .desktop(loc.desktop)
+            .and_then(|(_, d)| {
+                let tree = d.tree();
+                loc.node
+                    .and_then(|n| tree.parent(n))
+                    .and_then(|p| tree.get(p))
+            })
+            .map_or(0, Node::id);
+
+        self.subscribers.emit(&Event::NodeRemove {
+            monitor: loc.monitor,
+            desktop: loc.desktop,
+            node:    from,
+        });
+        self.subscribers.emit(&Event::NodeAdd {
+            monitor: loc.monitor,
+            desktop: loc.desktop,
+            parent,
+            node: to,
+        });
+    }
+
+    /// A [`Window`] was unmapped. Unless the window manager hid it itself, it
+    /// stops being managed
+    ///
+    /// The [`Window`] is withdrawn, so it leaves the save-set as well. Otherwise
+    /// it would be mapped again when the window manager restarts
+    fn unmap_notify(&mut self, window: Window) -> Result<()> {
+        if self.state.locate_window(window).is_none() {
+            return self.forget_struts(window);
+        }
+
+        let hidden = self
+            .state
+            .locate_window(window)
+            .and_then(|loc| self.state.node(&loc))
+            .and_then(Node::client)
+            .map_or(true, |c| !*c.get_shown());
+        if hidden {
+            return Ok(());
+        }
+
+        if let Err(e) = self.xconn.remove_window_from_save_set(window) {
+            log::debug!("failed to remove Window({:#0x}) from the save-set: {:?}", window, e);
+        }
+        self.unmanage(window)
+    }
+
+    /// Stop managing a [`Window`] that was unmapped or destroyed
+    fn unmanage(&mut self, window: Window) -> Result<()> {
+        if self.drag.as_ref().map_or(false, |drag| drag.window() == window) {
+            self.end_drag(false)?;
+        }
+        self.scratchpads.release(window);
+        self.stack.remove(window);
+        let loc = match self.state.locate_window(window) {
+            Some(loc) => loc,
+            None => {
+                self.state.forget_producer(window);
+                return self.forget_struts(window);
+            },
+        };
+        log::debug!("unmanaging Window({:#0x})", window);
+
+        let producer = self
+            .state
+            .node_mut(&loc)
+            .and_then(Node::client_mut)
+            .and_then(Client::take_producer);
+        if let Some(terminal) = producer {
+            return self.unswallow(loc, window, terminal);
+        }
+
+        let global = &self.config.global;
+        for node in self.state.remove_node_automatic(
+            &loc,
+            global.automatic_scheme,
+            global.removal_adjustment,
+        ) {
+            if let Some(presel) = node.presel() {
+                self.xconn.destroy_presel_feedback(&presel)?;
+            }
+        }
+        self.subscribers.emit(&Event::NodeRemove {
+            monitor: loc.monitor,
+            desktop: loc.desktop,
+            node:    window,
+        });
+
+        self.arrange(&loc)?;
+        self.focus_desktop_node(loc.desktop)?;
+        self.update_client_list()
+    }
+
+    /// A client asks for its [`Window`] to be moved, resized or restacked
+    ///
+    /// Only floating and unmanaged [`Window`]s get what they ask for. The others
+    /// are told where they actually are
+    fn configure_request(&mut self, data: &ConfigureRequestData) -> Result<()> {
+        let loc = match self.state.locate_window(data.id) {
+            Some(loc) => loc,
+            None => return self.xconn.configure_window_request(data),
+        };
+
+        let rect = self.state.rectangle(&loc);
+        let client = self
+            .state
+            .node_mut(&loc)
+            .and_then(Node::client_mut)
+            .context(format!("Window({:#0x}) has no client", data.id))?;
+
+        if *client.get_state() == ClientState::Floating {
+            let mut rect = *client.get_floating_rectangle();
+            rect.point.x = data.x.unwrap_or(rect.point.x);
+            rect.point.y = data.y.unwrap_or(rect.point.y);
+            rect.dimension.width = data.width.unwrap_or(rect.dimension.width);
+            rect.dimension.height = data.height.unwrap_or(rect.dimension.height);
+            client.set_floating_rectangle(rect);
+            self.subscribers.emit(&Event::NodeGeometry {
+                monitor:   loc.monitor,
+                desktop:   loc.desktop,
+                node:      data.id,
+                rectangle: rect,
+            });
+
+            // The stacking is left to the stack manager, which only raises it
+            if data.stack_mode == Some(StackMode::Above) && data.sibling.is_none() {
+                self.stack.raise(data.id);
+            }
+            return self.xconn.configure_window_request(&ConfigureRequestData {
+                sibling: None,
+                stack_mode: None,
+                ..*data
+            });
+        }
+
+        let border_width = *client.get_border_width() as u32;
+        match rect {
+            Some(rect) => self.xconn.send_configure_notify(data.id, rect, border_width),
+            None => Ok(()),
+        }
+    }
+
+    /// A property of a [`Window`] changed
+    fn property_notify(&mut self, event: &PropertyEvent) -> Result<()> {
+        let loc = match self.state.locate_window(event.id) {
+            Some(loc) => loc,
+            None => {
+                let atoms = self.xconn.atoms();
+                if event.atom == atoms._NET_WM_STRUT || event.atom == atoms._NET_WM_STRUT_PARTIAL {
+                    return self.update_struts(event.id);
+                }
+                return Ok(());
+            },
+        };
+
+        if event.atom == u32::from(AtomEnum::WM_HINTS) {
+            let urgent = self
+                .xconn
+                .get_icccm_window_hints(event.id)
+                .map_or(false, |hints| hints.urgent);
+            if let Some(client) = self.state.node_mut(&loc).and_then(Node::client_mut) {
+                client.set_urgent(urgent);
+            }
+        } else if event.atom == self.xconn.atoms().WM_NORMAL_HINTS {
+            let (_, hints) = self.xconn.get_icccm_window_size_hints(event.id, None, &None);
+            if let (Some(hints), Some(client)) =
+                (hints, self.state.node_mut(&loc).and_then(Node::client_mut))
+            {
+                client.set_size_hints(hints);
+            }
+            return self.arrange(&loc);
+        }
+
+        Ok(())
+    }
+
+    /// A client sent a message to the root window or to its [`Window`]
+    ///
+    /// `ignore_ewmh_focus` and `ignore_ewmh_fullscreen` block the matching
+    /// requests, whether they come from the application or from a pager
+    fn client_message(&mut self, event: &ClientMessageEvent) -> Result<()> {
+        let atoms = self.xconn.atoms();
+        let data = match event.data.as_u32() {
+            Some(&data) => data,
+            None => {
+                log::debug!("ignoring `ClientMessage` {} not in U32 format", event.type_);
+                return Ok(());
+            },
+        };
+
+        if event.type_ == atoms._NET_CURRENT_DESKTOP {
+            return match self.desktop_at(data[0] as usize) {
+                Some(loc) => self.focus(loc),
+                None => Ok(()),
+            };
+        } else if event.type_ == atoms._NET_NUMBER_OF_DESKTOPS {
+            return self.set_number_of_desktops(data[0] as usize);
+        } else if event.type_ == atoms._NET_REQUEST_FRAME_EXTENTS {
+            // Sent before the window is mapped, so it usually isn't managed yet
+            let border = self
+                .state
+                .locate_window(event.window)
+                .and_then(|loc| self.state.node(&loc))
+                .and_then(Node::client)
+                .map_or(self.config.global.border_width, |c| *c.get_border_width() as u32);
+            return self
+                .xconn
+                .set_window_frame_extents(event.window, Extents::new(border, border, border, border));
+        }
+
+        let loc = match self.state.locate_window(event.window) {
+            Some(loc) => loc,
+            None => return Ok(()),
+        };
+
+        if event.type_ == atoms._NET_CLOSE_WINDOW {
+            self.xconn.close_window(event.window);
+        } else if event.type_ == atoms._NET_ACTIVE_WINDOW {
+            if !self.config.global.ignore_ewmh_focus {
+                self.focus(loc)?;
+            }
+        } else if event.type_ == atoms._NET_WM_STATE {
+            // The action is followed by one or two states
+            for &atom in &data[1..3] {
+                if let Some(state) = self.xconn.get_window_state_from_atom(atom) {
+                    let loc = self
+                        .state
+                        .locate_window(event.window)
+                        .context(format!("Window({:#0x}) is not managed", event.window))?;
+                    self.change_window_state(loc, event.window, state, data[0])?;
+                }
+            }
+        } else if event.type_ == atoms._NET_WM_DESKTOP {
+            self.move_to_desktop_at(loc, event.window, data[0] as usize)?;
+        } else if event.type_ == atoms._NET_WM_MOVERESIZE {
+            match MoveResize::from_direction(data[2]) {
+                Some(MoveResize::Cancel) => self.end_drag(false)?,
+                Some(operation) => {
+                    let pointer = Point::new(data[0] as i32, data[1] as i32);
+                    self.start_drag(loc, event.window, operation, pointer)?;
+                },
+                None => log::debug!("ignoring the `_NET_WM_MOVERESIZE` direction {}", data[2]),
+            }
+        } else if event.type_ == atoms._NET_MOVERESIZE_WINDOW {
+            // Bits 8 to 11 of the flags tell which of the values are given
+            let given = |bit: u32| data[0] & (1 << bit) != 0;
+            self.configure_request(&ConfigureRequestData {
+                id:         event.window,
+                parent:     self.xconn.root(),
+                sibling:    None,
+                x:          Some(data[1] as i32).filter(|_| given(8)),
+                y:          Some(data[2] as i32).filter(|_| given(9)),
+                height:     Some(data[4]).filter(|_| given(11)),
+                width:      Some(data[3]).filter(|_| given(10)),
+                stack_mode: None,
+                is_root:    false,
+            })?;
+        } else if event.type_ == atoms._NET_RESTACK_WINDOW {
+            let sibling = Some(data[1]).filter(|&sibling| sibling != x11rb::NONE);
+            match data[2] {
+                0 => self.stack.stack_relative(event.window, StackMode::Above, sibling),
+                1 => self.stack.stack_relative(event.window, StackMode::Below, sibling),
+                // `TopIf`, `BottomIf` and `Opposite` depend on the overlapping windows
+                _ => log::debug!("ignoring the stack mode {} of `_NET_RESTACK_WINDOW`", data[2]),
+            }
+        } else {
+            log::debug!(
+                "ignoring `ClientMessage` {} for Window({:#0x})",
+                event.type_,
+                event.window
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Remove (0), add (1) or toggle (2) a [`WindowState`] of a managed
+    /// [`Window`], as asked with `_NET_WM_STATE`
+    ///
+    /// The states that the window manager doesn't act upon are only recorded
+    fn change_window_state(
+        &mut self,
+        loc: Coordinates,
+        window: Window,
+        state: WindowState,
+        action: u32,
+    ) -> Result<()> {
+        let node = self
+            .state
+            .node(&loc)
+            .context(format!("Window({:#0x}) is not managed", window))?;
+        let client = node
+            .client()
+            .context(format!("Window({:#0x}) has no client", window))?;
+        let current = match state {
+            WindowState::Fullscreen => *client.get_state() == ClientState::Fullscreen,
+            WindowState::Above => *client.get_layer() == StackLayer::Above,
+            WindowState::Below => *client.get_layer() == StackLayer::Below,
+            WindowState::Sticky => node.is_sticky(),
+            WindowState::Hidden => node.is_hidden(),
+            WindowState::DemandsAttention => *client.get_urgent(),
+            WindowState::MaximizedHorz
+            | WindowState::MaximizedVert
+            | WindowState::Modal
+            | WindowState::Shaded
+            | WindowState::SkipPager
+            | WindowState::SkipTaskbar => client.get_wm_flags().contains(&state),
+        };
+        let on = match action {
+            0 => false,
+            1 => true,
+            2 => !current,
+            _ => {
+                log::debug!("ignoring the `_NET_WM_STATE` action {}", action);
+                return Ok(());
+            },
+        };
+        if on == current {
+            return Ok(());
+        }
+
+        match state {
+            WindowState::Fullscreen => {
+                let transition = t!(on ? StateTransition::Enter : StateTransition::Exit);
+                if self.config.global.ignore_ewmh_fullscreen == transition {
+                    return Ok(());
+                }
+                let state = t!(on ? ClientState::Fullscreen : *client.get_last_state());
+                self.set_client_state(&loc, state)?;
+            },
+            WindowState::Above | WindowState::Below => {
+                let layer = match (state, on) {
+                    (WindowState::Above, true) => StackLayer::Above,
+                    (WindowState::Below, true) => StackLayer::Below,
+                    _ => StackLayer::Normal,
+                };
+                if let Some(client) = self.state.node_mut(&loc).and_then(Node::client_mut) {
+                    client.change_layer(layer);
+                }
+            },
+            WindowState::Sticky => self.set_sticky(loc, window, on)?,
+            WindowState::Hidden => self.set_node_hidden(loc, window, on)?,
+            WindowState::DemandsAttention => {
+                if let Some(client) = self.state.node_mut(&loc).and_then(Node::client_mut) {
+                    client.set_urgent(on);
+                }
+            },
+            WindowState::MaximizedHorz
+            | WindowState::MaximizedVert
+            | WindowState::Modal
+            | WindowState::Shaded
+            | WindowState::SkipPager
+            | WindowState::SkipTaskbar => {
+                if let Some(client) = self.state.node_mut(&loc).and_then(Node::client_mut) {
+                    let mut flags = client.get_wm_flags().clone();
+                    flags.retain(|&flag| flag != state);
+                    if on {
+                        flags.push(state);
+                    }
+                    client.set_wm_flags(flags);
+                }
+            },
+        }
+
+        // The layer may have replaced the opposite one
+        if matches!(state, WindowState::Above | WindowState::Below) && on {
+            let opposite = t!(state == WindowState::Above ? WindowState::Below : WindowState::Above);
+            self.xconn.set_window_state(window, opposite, false)?;
+        }
+        self.xconn.set_window_state(window, state, on)
+    }
+
+    // ============================ Helpers ===========================
+
+    /// Compute the layout of a [`Desktop`] and place its [`Window`]s
+    fn arrange(&mut self, loc: &Coordinates) -> Result<()> {
+        let global = &self.config.global;
+        let monitor = self
+            .state
+            .monitor(loc.monitor)
+            .context(format!("Monitor({:#0x}) does not exist", loc.monitor))?;
+        // Fullscreen windows cover the space reserved by the docks
+        let (area, reserved) = (monitor.rectangle(), monitor.reserved());
+        let shown = monitor.desk_id() == Some(loc.desktop);
+        let desktop = self
+            .state
+            .desktop_mut(loc.desktop)
+            .context(format!("Desktop({:#0x}) does not exist", loc.desktop))?;
+
+        let mut params = LayoutParams::new(
+            desktop.layout(),
+            area,
+            desktop.padding() + reserved,
+            desktop.window_gap() as i32,
+            desktop.border_width() as u32,
+        );
+        params.monocle_padding = global.monocle_padding;
+        params.gapless_monocle = global.gapless_monocle;
+        params.center_pseudotiled = global.center_pseudotiled;
+        params.honor_size_hints = global.honor_size_hints;
+
+        let placements = arrange(desktop.tree_mut(), &params);
+        self.xconn.apply_layout(&placements)?;
+
+        // The feedback of the preselections follows the nodes they split
+        let tree = desktop.tree_mut();
+        for id in tree.preselected().collect::<Vec<_>>() {
+            let (rect, vacant) = match tree.get(id) {
+                Some(node) => (node.rectangle(), node.is_vacant()),
+                None => continue,
+            };
+            if let Some(presel) = tree.presel_mut(id) {
+                if shown && !vacant {
+                    self.xconn.draw_presel_feedback(presel, rect, params.gap(), true)?;
+                } else if presel.feedback() != x11rb::NONE {
+                    self.xconn.unmap_window(presel.feedback())?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Focus the [`Node`] at the given [`Coordinates`], or the [`Desktop`] if
+    /// there is no [`Node`], showing the [`Desktop`] if needed
+    fn focus(&mut self, loc: Coordinates) -> Result<()> {
+        self.focus_with_history(loc, true)
+    }
+
+    /// Focus the [`Node`] at the given [`Coordinates`], recording it in the
+    /// focus history only if `record` is set
+    ///
+    /// Jumps through the history aren't recorded, as that would move its
+    /// needle back to the newest entry
+    fn focus_with_history(&mut self, loc: Coordinates, record: bool) -> Result<()> {
+        let previous = self.state.focused();
+
+        self.show_desktop(loc.monitor, loc.desktop)?;
+        self.state.set_focus(loc.monitor);
+        if let Some(desktop) = self.state.desktop_mut(loc.desktop) {
+            desktop.set_focus(loc.node);
+        }
+        if record {
+            self.state.history_mut().add(loc);
+        }
+
+        if previous.map_or(true, |p| p.desktop != loc.desktop) {
+            self.subscribers.emit(&Event::DesktopFocus {
+                monitor: loc.monitor,
+                desktop: loc.desktop,
+            });
+        }
+        self.focus_desktop_node(loc.desktop)
+    }
+
+    /// Give the input focus to the focused [`Node`] of a [`Desktop`], if the
+    /// [`Desktop`] is shown on the focused [`Monitor`]
+    fn focus_desktop_node(&mut self, desktop: Xid) -> Result<()> {
+        let focused = match self.state.focused() {
+            Some(focused) if focused.desktop == desktop => focused,
+            _ => return Ok(()),
+        };
+
+        match self
+            .state
+            .node(&focused)
+            .and_then(Node::client)
+            .map(|c| *c.get_window())
+        {
+            Some(window) => {
+                self.subscribers.emit(&Event::NodeFocus {
+                    monitor: focused.monitor,
+                    desktop: focused.desktop,
+                    node:    window,
+                });
+                self.stack.raise(window);
+                self.xconn.focus_window(window)
+            },
+            None => self.xconn.clear_input_focus(),
+        }
+    }
+
+    /// Map a [`Window`] that isn't managed, keeping track of its layer and of
+    /// the space it reserves
+    fn map_unmanaged(&mut self, window: Window) -> Result<()> {
+        self.xconn.init_unmanaged(window)?;
+        let layer = self.unmanaged_layer(window);
+        self.stack.add(window, layer);
+        self.update_struts(window)?;
+        self.xconn.map_window(window)
+    }
+
+    /// Read the [`Strut`]s of a [`Window`] again, unless they are ignored
+    fn update_struts(&mut self, window: Window) -> Result<()> {
+        if self.config.global.ignore_ewmh_struts {
+            return Ok(());
+        }
+
+        let struts = self.xconn.get_window_strut(window);
+        let previous = self.struts.iter().filter(|s| s.window == window).count();
+        if previous == 0 && struts.is_empty() {
+            return Ok(());
+        }
+
+        self.struts.retain(|s| s.window != window);
+        self.struts.extend(struts);
+        self.update_work_areas()
+    }
+
+    /// Release the space reserved by a [`Window`] that went away
+    fn forget_struts(&mut self, window: Window) -> Result<()> {
+        let count = self.struts.len();
+        self.struts.retain(|s| s.window != window);
+        if self.struts.len() == count {
+            return Ok(());
+        }
+
+        self.update_work_areas()
+    }
+
+    /// Reserve the space of the [`Strut`]s on every [`Monitor`] and update
+    /// `_NET_WORKAREA`. The [`Monitor`]s whose work area changed are arranged
+    fn update_work_areas(&mut self) -> Result<()> {
+        let screen = self.xconn.get_window_geometry(self.xconn.root())?;
+        let mut changed = vec![];
+        for monitor in self.state.monitors_mut() {
+            if monitor.reserve(&self.struts, screen) {
+                if let Some(desktop) = monitor.desk_id() {
+                    changed.push(Coordinates::new(monitor.id(), desktop, None));
+                }
+            }
+        }
+
+        // There is one work area per desktop, which is the one of its monitor
+        let workareas = self
+            .state
+            .monitors()
+            .iter()
+            .flat_map(|m| m.desktops().iter().map(move |_| m.work_area()))
+            .collect::<Vec<_>>();
+        self.xconn
+            .set_workarea(&workareas.iter().collect::<Vec<_>>())?;
+
+        for loc in &changed {
+            self.arrange(loc)?;
+        }
+        Ok(())
+    }
+
+    /// Add a managed [`Window`] to the stack, above the [`Window`] it is
+    /// transient for
+    fn add_to_stack(&mut self, window: Window) {
+        self.stack.add(window, StackLayer1::Regular);
+        if let Some(parent) = self.xconn.get_icccm_window_transient_for(window) {
+            self.stack.keep_above(window, parent);
+        }
+    }
+
+    /// Return the [`StackLayer1`] of a [`Window`] that isn't managed, from its
+    /// type and state
+    fn unmanaged_layer(&self, window: Window) -> StackLayer1 {
+        match self.xconn.get_window_preferred_type(window) {
+            WindowType::Desktop => StackLayer1::Desktop,
+            WindowType::Dock | WindowType::Toolbar => StackLayer1::Dock,
+            WindowType::Notification => StackLayer1::Notification,
+            WindowType::Combo
+            | WindowType::Dialog
+            | WindowType::DND
+            | WindowType::DropdownMenu
+            | WindowType::Menu
+            | WindowType::Normal
+            | WindowType::PopupMenu
+            | WindowType::Splash
+            | WindowType::ToolTip
+            | WindowType::Utility => t!(self.xconn.window_is_below(window)
+                ? StackLayer1::Below
+                : StackLayer1::Above),
+        }
+    }
+
+    /// Restack the [`Window`]s that are out of order, with as few changes as
+    /// possible, and keep `_NET_CLIENT_LIST_STACKING` in sync
+    fn restack(&mut self) {
+        let keys = self
+            .state
+            .monitors()
+            .iter()
+            .flat_map(Monitor::desktops)
+            .flat_map(|d| {
+                let tree = d.tree();
+                tree.leaves()
+                    .filter_map(move |id| tree.get(id).and_then(Node::client))
+                    .map(|c| (*c.get_window(), (*c.get_layer(), *c.get_state())))
+            })
+            .collect::<HashMap<_, _>>();
+
+        let clients = self.stack.clients();
+        let changes = self.stack.restack(|window| {
+            keys.get(&window)
+                .copied()
+                .unwrap_or((StackLayer::Normal, ClientState::Tiled))
+        });
+        for (window, mode, sibling) in changes {
+            let result = match mode {
+                StackMode::Above => self.xconn.stack_window_above(window, Some(sibling)),
+                StackMode::Below => self.xconn.stack_window_below(window, Some(sibling)),
+            };
+            if let Err(e) = result {
+                log::debug!("failed to restack Window({:#0x}): {:?}", window, e);
+            }
+        }
+
+        let stacking = self.stack.clients();
+        if stacking != clients {
+            if let Err(e) = self.xconn.update_client_list_stacking(&stacking) {
+                log::warn!("failed to update the stacking order: {:?}", e);
+            }
+        }
+    }
+
+    /// Update `_NET_CLIENT_LIST` with every managed [`Window`]
+    fn update_client_list(&self) -> Result<()> {
+        let clients = self
+            .state
+            .monitors()
+            .iter()
+            .flat_map(Monitor::desktops)
+            .flat_map(|d| {
+                let tree = d.tree();
+                tree.leaves()
+                    .filter_map(move |id| tree.get(id).and_then(Node::client))
+                    .map(|c| *c.get_window())
+            })
+            .collect::<Vec<_>>();
+
+        self.xconn.update_client_list(&clients)
+    }
+
+    /// Show a [`Desktop`] on its [`Monitor`], hiding the one that was shown
+    fn show_desktop(&mut self, monitor: Xid, desktop: Xid) -> Result<()> {
+        let mon = self
+            .state
+            .monitor_mut(monitor)
+            .context(format!("Monitor({:#0x}) does not exist", monitor))?;
+        let current = mon.desk_id();
+        if current == Some(desktop) {
+            return Ok(());
+        }
+        mon.set_desk(desktop);
+
+        if let Some(current) = current {
+            self.transfer_sticky(current, desktop)?;
+            self.set_desktop_shown(current, false)?;
+        }
+        self.set_desktop_shown(desktop, true)?;
+        self.arrange(&Coordinates::new(monitor, desktop, None))?;
+        self.xconn.set_current_desktop(self.desktop_index(desktop))
+    }
+
+    /// Move the leaf holding a [`Window`] to the focused [`Node`] of another
+    /// [`Desktop`], returning its new [`Coordinates`]
+    fn transfer_leaf(
+        &mut self,
+        loc: Coordinates,
+        window: Window,
+        to: Coordinates,
+    ) -> Result<Coordinates> {
+        let presel = self
+            .state
+            .desktop_mut(loc.desktop)
+            .zip(loc.node)
+            .and_then(|(d, n)| d.tree_mut().presel_cancel(n));
+        if let Some(presel) = presel {
+            self.xconn.destroy_presel_feedback(&presel)?;
+        }
+
+        let global = &self.config.global;
+        let node = self
+            .state
+            .remove_node_automatic(&loc, global.automatic_scheme, global.removal_adjustment)
+            .into_iter()
+            .next()
+            .context(format!("Window({:#0x}) is not managed", window))?;
+        self.subscribers.emit(&Event::NodeRemove {
+            monitor: loc.monitor,
+            desktop: loc.desktop,
+            node:    window,
+        });
+        self.arrange(&loc)?;
+
+        let global = &self.config.global;
+        let ratio = f64::from(global.split_ratio);
+        let parent = Node::new(self.xconn.generate_id()?, None, ratio);
+        let polarity = global.initial_polarity.unwrap_or(ChildPolarity::Second);
+        let scheme = global.automatic_scheme;
+
+        let tree = self
+            .state
+            .desktop_mut(to.desktop)
+            .context("the target desktop does not exist")?
+            .tree_mut();
+        let parent_id = to.node.and_then(|n| tree.get(n)).map_or(0, Node::id);
+        let (id, presel) = tree
+            .insert_node(to.node, node, parent, scheme, polarity)
+            .context(format!("failed to insert Window({:#0x})", window))?;
+        if let Some(presel) = presel {
+            self.xconn.destroy_presel_feedback(&presel)?;
+        }
+
+        let loc = Coordinates::new(to.monitor, to.desktop, Some(id));
+        self.subscribers.emit(&Event::NodeAdd {
+            monitor: loc.monitor,
+            desktop: loc.desktop,
+            parent:  parent_id,
+            node:    window,
+        });
+        self.state.update_sticky_counts();
+        self.update_window_desktop(&loc)?;
+        Ok(loc)
+    }
+
+    /// Move the sticky [`Node`]s of a [`Desktop`] that is about to be hidden to
+    /// the one shown in its place. Floating [`Window`]s keep their geometry
+    fn transfer_sticky(&mut self, from: Xid, to: Xid) -> Result<()> {
+        let windows = match self.state.desktop(from) {
+            Some((_, desktop)) => {
+                let tree = desktop.tree();
+                tree.leaves()
+                    .filter_map(|id| tree.get(id))
+                    .filter(|n| n.is_sticky())
+                    .filter_map(Node::client)
+                    .map(|c| *c.get_window())
+                    .collect::<Vec<_>>()
+            },
+            None => return Ok(()),
+        };
+
+        for window in windows {
+            let loc = self
+                .state
+                .locate_window(window)
+                .context(format!("Window({:#0x}) is not managed", window))?;
+            let target = self
+                .state
+                .locate_desktop(to)
+                .context(format!("Desktop({:#0x}) does not exist", to))?;
+            self.transfer_leaf(loc, window, target)?;
+        }
+
+        Ok(())
+    }
+
+    /// Send the leaf holding a [`Window`] to the focused [`Node`] of another
+    /// [`Desktop`], mapping the [`Window`] only if that [`Desktop`] is shown
+    fn send_to_desktop(
+        &mut self,
+        loc: Coordinates,
+        window: Window,
+        desktop: Xid,
+    ) -> Result<Coordinates> {
+        let to = self
+            .state
+            .locate_desktop(desktop)
+            .context(format!("Desktop({:#0x}) does not exist", desktop))?;
+        let new = self.transfer_leaf(loc, window, to)?;
+
+        let shown = self.is_shown(desktop) && self.state.node(&new).map_or(false, |n| !n.is_hidden());
+        if let Some(client) = self.state.node_mut(&new).and_then(Node::client_mut) {
+            client.set_shown(shown);
+        }
+        self.arrange(&new)?;
+        if shown {
+            self.xconn.map_window(window)?;
+        } else {
+            self.xconn.unmap_window(window)?;
+        }
+
+        self.focus_desktop_node(loc.desktop)?;
+        Ok(new)
+    }
+
+    /// Make the [`Node`] holding a [`Window`] sticky or not. Sticky [`Node`]s
+    /// live on the [`Desktop`] shown on their [`Monitor`], so it is moved there
+    fn set_sticky(&mut self, mut loc: Coordinates, window: Window, sticky: bool) -> Result<()> {
+        let shown = self.state.monitor(loc.monitor).and_then(Monitor::desk_id);
+        if let Some(desktop) = shown.filter(|&d| sticky && d != loc.desktop) {
+            loc = self.send_to_desktop(loc, window, desktop)?;
+        }
+
+        if let Some(node) = self.state.node_mut(&loc) {
+            node.set_sticky(sticky);
+        }
+        self.state.update_sticky_counts();
+        self.update_window_desktop(&loc)
+    }
+
+    /// Hide or show the [`Node`] holding a [`Window`]. Hidden [`Node`]s take no
+    /// space and stay unmapped
+    fn set_node_hidden(&mut self, loc: Coordinates, window: Window, hidden: bool) -> Result<()> {
+        let shown = !hidden && self.is_shown(loc.desktop);
+        self.state.set_hidden(&loc, hidden);
+        if let Some(client) = self.state.node_mut(&loc).and_then(Node::client_mut) {
+            client.set_shown(shown);
+        }
+
+        self.arrange(&loc)?;
+        if shown {
+            self.xconn.map_window(window)?;
+        } else {
+            self.xconn.unmap_window(window)?;
+        }
+        self.focus_desktop_node(loc.desktop)
+    }
+
+    /// Change the [`ClientState`] of the [`Client`] at the given [`Coordinates`]
+    fn set_client_state(&mut self, loc: &Coordinates, state: ClientState) -> Result<()> {
+        let window = self
+            .state
+            .node(loc)
+            .and_then(Node::client)
+            .map(|client| *client.get_window())
+            .context("the node has no client")?;
+        let previous = match self.state.change_client_state(loc, state) {
+            Some(previous) => previous,
+            None => return Ok(()),
+        };
+
+        for (state, on) in [(previous, false), (state, true)] {
+            self.subscribers.emit(&Event::NodeState {
+                monitor: loc.monitor,
+                desktop: loc.desktop,
+                node:    window,
+                state,
+                on,
+            });
+        }
+        self.arrange(loc)
+    }
+
+    /// Start moving or resizing a [`Window`] as asked with
+    /// `_NET_WM_MOVERESIZE`, grabbing the pointer and the keyboard until the
+    /// button is released, `Return` is pressed or `Escape` cancels it
+    ///
+    /// Tiled windows float where they are. Fullscreen windows aren't dragged
+    fn start_drag(
+        &mut self,
+        loc: Coordinates,
+        window: Window,
+        operation: MoveResize,
+        pointer: Point,
+    ) -> Result<()> {
+        if self.drag.is_some() {
+            return Ok(());
+        }
+
+        let rect = self.state.rectangle(&loc);
+        let client = self
+            .state
+            .node_mut(&loc)
+            .and_then(Node::client_mut)
+            .context(format!("Window({:#0x}) has no client", window))?;
+        let state = *client.get_state();
+        match state {
+            ClientState::Fullscreen => return Ok(()),
+            ClientState::Floating => {},
+            ClientState::Tiled | ClientState::PsuedoTiled => {
+                if let Some(rect) = rect {
+                    client.set_floating_rectangle(rect);
+                }
+                self.set_client_state(&loc, ClientState::Floating)?;
+            },
+        }
+
+        let start = self
+            .state
+            .node(&loc)
+            .and_then(Node::client)
+            .map(|client| *client.get_floating_rectangle())
+            .context(format!("Window({:#0x}) has no client", window))?;
+        let drag = match Drag::from_move_resize(window, operation, pointer, start, state) {
+            Some(drag) => drag,
+            None => return Ok(()),
+        };
+        if drag.is_keyboard() {
+            self.xconn.warp_pointer(drag.pointer())?;
+        }
+
+        self.xconn.grab_pointer(self.xconn.root(), None)?;
+        if let Err(e) = self.xconn.grab_keyboard() {
+            self.xconn.ungrab_pointer()?;
+            return Err(e);
+        }
+        self.stack.raise(window);
+        self.drag = Some(drag);
+
+        Ok(())
+    }
+
+    /// The pointer moved during a [`Drag`]
+    fn drag_motion(&mut self, pointer: Point) -> Result<()> {
+        let (window, rect) = match self.drag.as_mut() {
+            Some(drag) => (drag.window(), drag.motion(pointer)),
+            None => return Ok(()),
+        };
+        self.place_floating(window, rect)
+    }
+
+    /// A key was pressed during a [`Drag`]. The arrows move the pointer,
+    /// `Return` ends the drag and `Escape` cancels it
+    fn drag_key(&mut self, keycode: u8) -> Result<()> {
+        if self.drag.is_none() {
+            return Ok(());
+        }
+
+        match self.xconn.get_keysym(keycode) {
+            Some(KEY_ESCAPE) => self.end_drag(true),
+            Some(KEY_RETURN) => self.end_drag(false),
+            Some(keysym) => match self.drag.as_ref().and_then(|drag| drag.step(keysym)) {
+                Some(pointer) => {
+                    self.xconn.warp_pointer(pointer)?;
+                    self.drag_motion(pointer)
+                },
+                None => Ok(()),
+            },
+            None => Ok(()),
+        }
+    }
+
+    /// Release the pointer and the keyboard at the end of a [`Drag`]. A
+    /// cancelled drag puts the [`Window`] back where it started, tiled again if
+    /// it was floated to be dragged
+    fn end_drag(&mut self, cancel: bool) -> Result<()> {
+        let drag = match self.drag.take() {
+            Some(drag) => drag,
+            None => return Ok(()),
+        };
+
+        self.xconn.ungrab_pointer()?;
+        self.xconn.ungrab_keyboard()?;
+        if cancel {
+            self.place_floating(drag.window(), drag.start())?;
+            if drag.state() != ClientState::Floating {
+                if let Some(loc) = self.state.locate_window(drag.window()) {
+                    self.set_client_state(&loc, drag.state())?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Move a floating [`Window`] to the given [`Rectangle`]
+    fn place_floating(&mut self, window: Window, rect: Rectangle) -> Result<()> {
+        let loc = match self.state.locate_window(window) {
+            Some(loc) => loc,
+            None => return Ok(()),
+        };
+        if let Some(client) = self.state.node_mut(&loc).and_then(Node::client_mut) {
+            client.set_floating_rectangle(rect);
+        }
+
+        self.subscribers.emit(&Event::NodeGeometry {
+            monitor:   loc.monitor,
+            desktop:   loc.desktop,
+            node:      window,
+            rectangle: rect,
+        });
+        self.arrange(&loc)
+    }
+
+    /// Move the leaf holding a [`Window`] to the [`Desktop`] with the given
+    /// index, as asked with `_NET_WM_DESKTOP`. The leaf becomes sticky when
+    /// asked to be on every [`Desktop`]
+    fn move_to_desktop_at(&mut self, loc: Coordinates, window: Window, idx: usize) -> Result<()> {
+        if idx == ALL_DESKTOPS {
+            self.set_sticky(loc, window, true)?;
+            return self.xconn.set_window_state(window, WindowState::Sticky, true);
+        }
+
+        let to = match self.desktop_at(idx) {
+            Some(to) => to,
+            None => return Ok(()),
+        };
+        if self.state.node(&loc).map_or(false, Node::is_sticky) {
+            self.set_sticky(loc, window, false)?;
+            self.xconn.set_window_state(window, WindowState::Sticky, false)?;
+        }
+        if to.desktop != loc.desktop {
+            self.send_to_desktop(loc, window, to.desktop)?;
+        }
+
+        Ok(())
+    }
+
+    /// Add or remove [`Desktop`]s at the end of the last [`Monitor`] until
+    /// there are `count` of them, as asked with `_NET_NUMBER_OF_DESKTOPS`
+    ///
+    /// Only the [`Desktop`]s that are empty and not shown can be removed
+    fn set_number_of_desktops(&mut self, count: usize) -> Result<()> {
+        let mut total = self
+            .state
+            .monitors()
+            .iter()
+            .map(|m| m.desktops().len())
+            .sum::<usize>();
+
+        let global = &self.config.global;
+        while total < count {
+            let id = self.xconn.generate_id()?;
+            let name = (total + 1).to_string();
+            let monitor = self
+                .state
+                .monitors_mut()
+                .last_mut()
+                .context("there is no monitor to add desktops to")?;
+            monitor.add_desktop(Desktop::new(
+                name.clone(),
+                id,
+                global.padding,
+                global.window_gap as isize,
+                global.border_width as usize,
+            ));
+            self.subscribers.emit(&Event::DesktopAdd {
+                monitor: monitor.id(),
+                desktop: id,
+                name,
+            });
+            total += 1;
+        }
+
+        while total > count {
+            let removable = self.state.monitors().last().and_then(|m| {
+                m.desktops()
+                    .last()
+                    .filter(|d| m.desk_id() != Some(d.id()) && d.tree().is_empty())
+                    .map(Desktop::id)
+            });
+            match removable {
+                Some(id) => {
+                    self.state.remove_desktop(id);
+                    total -= 1;
+                },
+                None => break,
+            }
+        }
+
+        self.update_desktop_names()?;
+        self.update_work_areas()
+    }
+
+    /// Return the [`Coordinates`] of the [`Desktop`] at the given index, in the
+    /// order of `_NET_DESKTOP_NAMES`
+    fn desktop_at(&self, idx: usize) -> Option<Coordinates> {
+        self.state
+            .monitors()
+            .iter()
+            .flat_map(|m| {
+                m.desktops()
+                    .iter()
+                    .map(move |d| Coordinates::new(m.id(), d.id(), d.focus()))
+            })
+            .nth(idx)
+    }
+
+    /// Is the [`Desktop`] shown on its [`Monitor`]?
+    fn is_shown(&self, desktop: Xid) -> bool {
+        self.state
+            .desktop(desktop)
+            .map_or(false, |(m, _)| m.desk_id() == Some(desktop))
+    }
+
+    /// Update `_NET_WM_DESKTOP` of the [`Window`] at the given [`Coordinates`].
+    /// Sticky [`Window`]s are on every [`Desktop`]
+    fn update_window_desktop(&self, loc: &Coordinates) -> Result<()> {
+        let node = match self.state.node(loc) {
+            Some(node) => node,
+            None => return Ok(()),
+        };
+
+        match node.client() {
+            Some(client) => self.xconn.set_window_desktop(
+                *client.get_window(),
+                t!(node.is_sticky() ? ALL_DESKTOPS : self.desktop_index(loc.desktop)),
+            ),
+            None => Ok(()),
+        }
+    }
+
+    /// Return the index of a [`Desktop`] across every [`Monitor`], as used by
+    /// `_NET_CURRENT_DESKTOP`
+    fn desktop_index(&self, desktop: Xid) -> usize {
+        self.state
+            .monitors()
+            .iter()
+            .flat_map(Monitor::desktops)
+            .position(|d| d.id() == desktop)
+            .unwrap_or_default()
+    }
+
+    /// Map or unmap every [`Window`] of a [`Desktop`], marking the clients as
+    /// shown or hidden so that the resulting `UnmapNotify` events are ignored
+    ///
+    /// Hidden [`Node`]s stay unmapped
+    fn set_desktop_shown(&mut self, desktop: Xid, shown: bool) -> Result<()> {
+        let tree = match self.state.desktop_mut(desktop) {
+            Some(desktop) => desktop.tree_mut(),
+            None => return Ok(()),
+        };
+
+        // The feedback of the preselections is drawn again once arranged
+        if !shown {
+            for presel in tree.preselected().filter_map(|id| tree.get(id).and_then(Node::presel)) {
+                if presel.feedback() != x11rb::NONE {
+                    self.xconn.unmap_window(presel.feedback())?;
+                }
+            }
+        }
+
+        let mut windows = vec![];
+        for id in tree.leaves().collect::<Vec<_>>() {
+            if tree.get(id).map_or(false, Node::is_hidden) {
+                continue;
+            }
+            if let Some(client) = tree.get_mut(id).and_then(Node::client_mut) {
+                client.set_shown(shown);
+                windows.push(*client.get_window());
+            }
+        }
+
+        for window in windows {
+            t!(shown ? self.xconn.map_window(window) : self.xconn.unmap_window(window))?;
+        }
+
+        Ok(())
+    }
+
+    /// Return the [`Window`]s of the leaves below the [`Node`] at the given
+    /// [`Coordinates`]
+    fn windows_below(&self, loc: &Coordinates) -> Vec<Window> {
+        let (tree, id) = match (self.state.desktop(loc.desktop), loc.node) {
+            (Some((_, desktop)), Some(id)) => (desktop.tree(), id),
+            _ => return vec![],
+        };
+
+        tree.descendants(id)
+            .filter_map(|n| tree.get(n).and_then(Node::client))
+            .map(|c| *c.get_window())
+            .collect()
+    }
+
+    /// Update `_NET_NUMBER_OF_DESKTOPS` and `_NET_DESKTOP_NAMES`
+    fn update_desktop_names(&self) -> Result<()> {
+        let names = self
+            .state
+            .monitors()
+            .iter()
+            .flat_map(Monitor::desktops)
+            .map(Desktop::name)
+            .collect::<Vec<_>>();
+
+        self.xconn.update_desktops(&names)
+    }
+
+    // ========================== Scratchpads =========================
+
+    /// Show a [`Scratchpad`](crate::scratchpad::Scratchpad) on the focused
+    /// [`Desktop`], or hide it if it is already shown there
+    ///
+    /// A scratchpad that isn't running is spawned, unless its command is still
+    /// starting, and its [`Window`] is shown once it is managed
+    fn toggle_scratchpad(&mut self, name: &str) -> Result<()> {
+        let focused = self.reference()?;
+        let window = match self.scratchpads.get(name)?.window() {
+            Some(window) => window,
+            None => {
+                let shell = self.config.global.shell.clone().unwrap_or_else(|| SHELL.to_path_buf());
+                return self.scratchpads.spawn(name, &shell);
+            },
+        };
+        let loc = self
+            .state
+            .locate_window(window)
+            .context(format!("Window({:#0x}) is not managed", window))?;
+
+        let hidden = self.state.node(&loc).map_or(true, Node::is_hidden);
+        if loc.desktop == focused.desktop && !hidden {
+            self.set_node_hidden(loc, window, true)
+        } else {
+            self.show_scratchpad(name, loc, window)
+        }
+    }
+
+    /// Show a scratchpad floating over the focused [`Desktop`] with its
+    /// geometry, moving it there first if it is on another [`Desktop`]
+    fn show_scratchpad(&mut self, name: &str, loc: Coordinates, window: Window) -> Result<()> {
+        let focused = self.reference()?;
+        let loc = if loc.desktop == focused.desktop {
+            loc
+        } else {
+            self.transfer_leaf(loc, window, focused)?
+        };
+
+        let area = self
+            .state
+            .monitor(loc.monitor)
+            .context(format!("Monitor({:#0x}) does not exist", loc.monitor))?
+            .rectangle();
+        let rect = self.scratchpads.get(name)?.geometry().resolve(area);
+        self.state.set_hidden(&loc, false);
+        self.state.change_client_state(&loc, ClientState::Floating);
+        if let Some(client) = self.state.node_mut(&loc).and_then(Node::client_mut) {
+            client.set_floating_rectangle(rect);
+            client.set_shown(true);
+        }
+
+        self.arrange(&loc)?;
+        self.xconn.map_window(window)?;
+        self.focus(loc)?;
+        self.update_client_list()
+    }
+
+    // ========================== Persistence =========================
+
+    /// Serialize the [`State`] with the [`Window`]s of the scratchpads, which
+    /// are bound again when it is restored
+    fn dump_state(&mut self) -> Result<String> {
+        self.state.set_scratchpads(self.scratchpads.bindings());
+        self.state.dump()
+    }
+
+    /// Replace the [`State`] with the one written to `path` by [`State::dump`]
+    pub(crate) fn load_state(&mut self, path: &Path) -> Result<()> {
+        let json = fs::read_to_string(path)
+            .context(format!("failed to read the state from '{}'", path.display()))?;
+        if path == restart_dump_path() {
+            if let Err(e) = fs::remove_file(path) {
+                log::warn!("failed to remove '{}': {}", path.display(), e);
+            }
+        }
+        self.restore(State::load(&json)?)
+    }
+
+    /// Replace the [`State`], adopting the [`Window`]s of the new one by ID
+    ///
+    /// Leaves whose [`Window`] no longer exists are dropped, and [`Window`]s
+    /// that are managed but absent from the new [`State`] are managed again
+    /// on the focused [`Desktop`]
+    fn restore(&mut self, mut state: State) -> Result<()> {
+        let xconn = &self.xconn;
+        let scratchpads = state.take_scratchpads();
+        state.retain_windows(|window| xconn.get_window_attributes(window).is_ok());
+        for window in state.producers() {
+            if xconn.get_window_attributes(window).is_err() {
+                state.forget_producer(window);
+            }
+        }
+        state.merge_monitors(
+            self.state
+                .monitors()
+                .iter()
+                .map(|m| {
+                    let mut monitor = m.clone();
+                    for desktop in monitor.desktops_mut() {
+                        *desktop.tree_mut() = Tree::new();
+                        desktop.set_focus(None);
+                    }
+                    monitor
+                })
+                .collect(),
+        );
+
+        for desktop in self.state.monitors().iter().flat_map(Monitor::desktops) {
+            let tree = desktop.tree();
+            for presel in tree.preselected().filter_map(|id| tree.get(id).and_then(Node::presel)) {
+                self.xconn.destroy_presel_feedback(&presel)?;
+            }
+        }
+
+        let previous = self.state.windows();
+        let adopted = state.windows();
+        let producers = state.producers();
+
+        // The windows still waiting for their external rules are adopted as is
+        let mut idx = 0;
+        while idx < self.pending.len() {
+            if adopted.contains(&self.pending[idx].window()) {
+                drop(self.pending.remove(idx).finish());
+            } else {
+                idx += 1;
+            }
+        }
+        for &window in adopted.iter().filter(|w| !previous.contains(w)) {
+            self.xconn
+                .init_window(window, self.config.global.focus_follows_pointer)?;
+            self.xconn.insert_window_in_save_set(window)?;
+            self.stack.add(window, StackLayer1::Regular);
+        }
+        self.state = state;
+        self.update_work_areas()?;
+
+        for (name, window) in scratchpads.into_iter().filter(|(_, w)| adopted.contains(w)) {
+            if let Err(e) = self.scratchpads.bind(&name, window) {
+                log::warn!("Window({:#0x}) is not a scratchpad anymore: {}", window, e);
+            }
+        }
+
+        let desktops = self
+            .state
+            .monitors()
+            .iter()
+            .flat_map(|m| {
+                m.desktops()
+                    .iter()
+                    .map(move |d| (m.id(), d.id(), m.desk_id() == Some(d.id())))
+            })
+            .collect::<Vec<_>>();
+        for (monitor, desktop, shown) in desktops {
+            self.set_desktop_shown(desktop, shown)?;
+            if shown {
+                self.arrange(&Coordinates::new(monitor, desktop, None))?;
+            }
+        }
+
+        for window in previous.into_iter().filter(|w| !adopted.contains(w)) {
+            // Swallowed terminals were mapped again when the connection closed
+            if producers.contains(&window) {
+                self.xconn.unmap_window(window)?;
+            } else {
+                self.manage(window)?;
+            }
+        }
+
+        self.update_desktop_names()?;
+        if let Some(focused) = self.state.focused() {
+            self.xconn
+                .set_current_desktop(self.desktop_index(focused.desktop))?;
+            self.focus_desktop_node(focused.desktop)?;
+        }
+        self.update_client_list()
+    }
+
+    // =========================== Messages ===========================
+
+    /// Handle a [`Request`] received on the socket and reply to it
+    ///
+    /// Subscriptions that don't use a FIFO keep the [`Stream`], which receives
+    /// the events instead of a single [`Reply`]
+    fn handle_request(&mut self, mut stream: Stream, request: Request) {
+        log::debug!("handling request: {:?}", request);
+        let result = if request.version == PROTOCOL_VERSION {
+            match request.message {
+                Message::Tree(TreeCommand::Subscribe { events, fifo: false, count }) =>
+                    match SubscriberMask::parse(&events) {
+                        Ok(mask) => {
+                            self.add_subscriber(Subscriber::stream(stream, mask, count));
+                            return;
+                        },
+                        Err(e) => Err(e.into()),
+                    },
+                Message::Tree(command) => self.tree_command(&command),
+                Message::Monitor { selector, command } =>
+                    self.monitor_command(selector.as_deref(), command),
+                Message::Desktop { selector, command } =>
+                    self.desktop_command(selector.as_deref(), command),
+                Message::Node { selector, command } =>
+                    self.node_command(selector.as_deref(), &command),
+                Message::Rule(command) => self.rule_command(command),
+                Message::Scratchpad(command) => self.scratchpad_command(&command),
+            }
+        } else {
+            Err(anyhow!(
+                "protocol version mismatch: lwm speaks version {}, the client {}",
+                PROTOCOL_VERSION,
+                request.version
+            ))
+        };
+
+        let reply = Reply::from(result.map_err(|e| format!("{:#}", e)));
+        if let Err(e) = stream.send(&reply) {
+            log::warn!("failed to reply to a request: {:?}", e);
+        }
+    }
+
+    /// Handle a [`TreeCommand`]
+    fn tree_command(&mut self, command: &TreeCommand) -> Result<String> {
+        match command {
+            TreeCommand::Quit => {
+                self.running = false;
+                Ok(String::new())
+            },
+            TreeCommand::History => Ok(query_history(&self.state)),
+            TreeCommand::Jump(desc) => {
+                let reference = self.reference()?;
+                let loc = jump_in_history(desc, &mut self.state, &reference)?;
+                self.focus_with_history(loc, false)?;
+                Ok(String::new())
+            },
+            TreeCommand::DumpState => self.dump_state(),
+            TreeCommand::LoadState(path) => {
+                self.load_state(Path::new(path))?;
+                Ok(String::new())
+            },
+            TreeCommand::Restart => {
+                let path = restart_dump_path();
+                write_restart_dump(&path, &self.dump_state()?)
+                    .context(format!("failed to write the state to '{}'", path.display()))?;
+                self.restart = Some(path);
+                self.running = false;
+                Ok(String::new())
+            },
+            // Subscriptions over the socket are handled with their `Stream`
+            TreeCommand::Subscribe { events, count, .. } => {
+                let subscriber = Subscriber::fifo(SubscriberMask::parse(events)?, *count)?;
+                let path = subscriber
+                    .fifo_path()
+                    .map(|p| p.display().to_string())
+                    .unwrap_or_default();
+                self.add_subscriber(subscriber);
+                Ok(path)
+            },
+            TreeCommand::Query { kind, selector, monitor, desktop, node, names } => {
+                let reference = self.reference()?;
+                let scope = QueryScope::new(
+                    &self.state,
+                    &reference,
+                    monitor.as_deref(),
+                    desktop.as_deref(),
+                    node.as_deref(),
+                )?;
+                let selector = selector.as_deref();
+
+                match kind {
+                    QueryKind::Tree => query_tree(&self.state, &scope),
+                    QueryKind::Monitors =>
+                        Ok(query_monitors(&self.state, &reference, &scope, selector, *names)?),
+                    QueryKind::Desktops =>
+                        Ok(query_desktops(&self.state, &reference, &scope, selector, *names)?),
+                    QueryKind::Nodes => Ok(query_nodes(&self.state, &reference, &scope, selector)?),
+                }
+            },
+        }
+    }
+
+    /// Handle a [`MonitorCommand`] on the [`Monitor`] matching `selector`
+    fn monitor_command(&mut self, selector: Option<&str>, command: MonitorCommand) -> Result<String> {
+        let reference = self.reference()?;
+        let id = Monitor::from_desc(selector.unwrap_or("focused"), &self.state, &reference)?;
+
+        match command {
+            MonitorCommand::Query => return Ok(format!("0x{:08X}", id)),
+            MonitorCommand::Focus => {
+                let loc = self
+                    .state
+                    .monitor(id)
+                    .and_then(Monitor::desk)
+                    .map(|d| Coordinates::new(id, d.id(), d.focus()))
+                    .context(format!("Monitor({:#0x}) has no desktop", id))?;
+                self.focus(loc)?;
+            },
+            MonitorCommand::Rename(name) =>
+                if let Some(monitor) = self.state.monitor_mut(id) {
+                    monitor.set_name(name);
+                },
+        }
+
+        Ok(String::new())
+    }
+
+    /// Handle a [`DesktopCommand`] on the [`Desktop`] matching `selector`
+    fn desktop_command(&mut self, selector: Option<&str>, command: DesktopCommand) -> Result<String> {
+        let reference = self.reference()?;
+        let loc = Desktop::from_desc(selector.unwrap_or("focused"), &self.state, &reference)?;
+
+        match command {
+            DesktopCommand::Query => return Ok(format!("0x{:08X}", loc.desktop)),
+            DesktopCommand::Focus => self.focus(loc)?,
+            DesktopCommand::Rename(name) => {
+                if let Some(desktop) = self.state.desktop_mut(loc.desktop) {
+                    let old = desktop.name().to_owned();
+                    desktop.set_name(name.clone());
+                    self.subscribers.emit(&Event::DesktopRename {
+                        monitor: loc.monitor,
+                        desktop: loc.desktop,
+                        old,
+                        new: name,
+                    });
+                }
+                self.update_desktop_names()?;
+            },
+            DesktopCommand::PreselCancel => {
+                let presels = self
+                    .state
+                    .desktop_mut(loc.desktop)
+                    .map(|d| d.tree_mut().presel_cancel_all())
+                    .unwrap_or_default();
+                for presel in presels {
+                    self.xconn.destroy_presel_feedback(&presel)?;
+                }
+            },
+        }
+
+        Ok(String::new())
+    }
+
+    /// Handle a [`NodeCommand`] on the [`Node`] matching `selector`
+    fn node_command(&mut self, selector: Option<&str>, command: &NodeCommand) -> Result<String> {
+        let reference = self.reference()?;
+        let loc = Node::from_desc(selector.unwrap_or("focused"), &self.state, &reference)?;
+        let node = self.state.node(&loc).context("the selected node does not exist")?;
+
+        match command {
+            NodeCommand::Query => return Ok(format!("0x{:08X}", node.id())),
+            NodeCommand::Focus => self.focus(loc)?,
+            NodeCommand::Close | NodeCommand::Kill => {
+                let kill = *command == NodeCommand::Kill;
+                for window in self.windows_below(&loc) {
+                    t!(kill ? self.xconn.kill_window(window) : self.xconn.close_window(window));
+                }
+            },
+            NodeCommand::PreselDir(dir) => {
+                let dir = dir
+                    .parse::<Direction>()
+                    .context(format!("invalid preselection direction: {}", dir))?;
+                let ratio = self.config.global.split_ratio;
+                self.state
+                    .desktop_mut(loc.desktop)
+                    .zip(loc.node)
+                    .and_then(|(d, n)| d.tree_mut().presel_dir(n, dir, ratio))
+                    .context("failed to preselect the node")?;
+                self.arrange(&loc)?;
+            },
+            NodeCommand::PreselRatio(ratio) => {
+                let ratio = ratio
+                    .parse::<f32>()
+                    .context(format!("invalid preselection ratio: {}", ratio))?;
+                let (desktop, id) = self
+                    .state
+                    .desktop_mut(loc.desktop)
+                    .zip(loc.node)
+                    .context("failed to preselect the node")?;
+                desktop.tree_mut().presel_ratio(id, ratio)?;
+                self.arrange(&loc)?;
+            },
+            NodeCommand::PreselCancel => {
+                let presel = self
+                    .state
+                    .desktop_mut(loc.desktop)
+                    .zip(loc.node)
+                    .and_then(|(d, n)| d.tree_mut().presel_cancel(n));
+                if let Some(presel) = presel {
+                    self.xconn.destroy_presel_feedback(&presel)?;
+                }
+            },
+        }
+
+        Ok(String::new())
+    }
+
+    /// Handle a [`RuleCommand`]
+    fn rule_command(&mut self, command: RuleCommand) -> Result<String> {
+        match command {
+            RuleCommand::Add { pattern, effect, one_shot } => {
+                let mut rule = Rule::from_pattern(&pattern)?;
+                rule.set_effect(effect.join(" "));
+                rule.set_one_shot(one_shot);
+                self.rules.add(rule)?;
+            },
+            RuleCommand::Remove(desc) => {
+                self.rules.remove(&desc)?;
+            },
+            RuleCommand::List => return Ok(self.rules.list()),
+        }
+
+        Ok(String::new())
+    }
+
+    /// Handle a [`ScratchpadCommand`]
+    fn scratchpad_command(&mut self, command: &ScratchpadCommand) -> Result<String> {
+        match command {
+            ScratchpadCommand::Toggle(name) => self.toggle_scratchpad(name)?,
+            ScratchpadCommand::List => return Ok(self.scratchpads.list()),
+        }
+
+        Ok(String::new())
+    }
+
+    /// Add a [`Subscriber`], sending it the current report right away if it
+    /// wants it
+    fn add_subscriber(&mut self, mut subscriber: Subscriber) {
+        if subscriber.wants(SubscriberMask::REPORT) {
+            // The others get the changes they missed first, otherwise the last
+            // report would no longer be the one they were sent
+            self.put_status();
+            self.report = report(&self.state, &self.config.global.status_prefix);
+            if !subscriber.send(&Event::Report(self.report.clone())) {
+                return;
+            }
+        }
+
+        self.subscribers.add(subscriber);
+    }
+
+    /// Send the report to the subscribers if it changed
+    fn put_status(&mut self) {
+        if !self.subscribers.wants(SubscriberMask::REPORT) {
+            return;
+        }
+
+        let report = report(&self.state, &self.config.global.status_prefix);
+        if report != self.report {
+            self.subscribers.emit(&Event::Report(report.clone()));
+            self.report = report;
+        }
+    }
+
+    /// Return the focused [`Coordinates`], used as the reference of selectors
+    fn reference(&self) -> Result<Coordinates> {
+        self.state.focused().context("nothing is focused")
+    }
+}
+
+/// Replace the process with the `lwm` binary it was started from, which loads
+/// the state dumped to `path`. The binary is looked up again, so an upgraded
+/// one is used
+///
+/// The connection to the X-Server is closed on `exec`, and the save-set maps
+/// the hidden [`Window`]s back until the new process hides them again. This
+/// only returns if `exec` failed
+fn exec_restart(path: &Path) -> anyhow::Error {
+    let mut args = env::args_os();
+    let program = args.next().unwrap_or_else(|| OsString::from(WM_NAME!()));
+
+    let mut kept = vec![];
+    while let Some(arg) = args.next() {
+        if arg == "--load-state" {
+            args.next();
+        } else if !arg.to_string_lossy().starts_with("--load-state=") {
+            kept.push(arg);
+        }
+    }
+
+    log::info!("restarting with the state in '{}'", path.display());
+    let error = Command::new(program)
+        .args(kept)
+        .arg("--load-state")
+        .arg(path)
+        .exec();
+
+    anyhow::Error::new(error).context("failed to restart the window manager")
+}
+
+/// Where the state is dumped when restarting, in `$XDG_RUNTIME_DIR` if it is
+/// set. `exec` keeps the process ID, so the new process finds its dump there
+fn restart_dump_path() -> PathBuf {
+    env::var_os("XDG_RUNTIME_DIR")
+        .map_or_else(env::temp_dir, PathBuf::from)
+        .join(format!("lwm-state-{}.json", process::id()))
+}
+
+/// Write the state dumped for a restart to a new file only the user can read,
+/// so that a link or a file planted at `path` is never written through
+fn write_restart_dump(path: &Path, json: &str) -> io::Result<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?;
+    let result = file.write_all(json.as_bytes());
+    if result.is_err() {
+        drop(fs::remove_file(path));
+    }
+    result
+}
+
+/// Compile the class patterns of the swallowing configuration, leaving out
+/// the invalid ones
+fn compile_classes(patterns: &[String]) -> Vec<Matcher> {
+    patterns
+        .iter()
+        .filter_map(|pattern| {
+            Matcher::parse(pattern)
+                .map_err(|e| log::error!("invalid swallowing class '{}': {}", pattern, e))
+                .ok()
+        })
+        .collect()
+}
+
+/// Does the class match any of the [`Matcher`]s?
+fn class_matches(matchers: &[Matcher], class: &str) -> bool {
+    matchers.iter().any(|matcher| matcher.is_match(class))
+}
@@ -1,10 +1,29 @@
 //! Handle events and messages
+//!
+//! This module is shared with the `lwmc` binary, so it must not depend on
+//! anything else within the crate
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
+use bincode::Options;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::io::{Read, Write};
+
+/// Version of the protocol spoken over the socket. It is bumped whenever
+/// [`Request`] or [`Reply`] change in an incompatible way
+pub(crate) const PROTOCOL_VERSION: u32 = 1;
+
+/// Largest frame accepted over the socket, so that a bogus length can't make
+/// the reader allocate without bound
+pub(crate) const MAX_FRAME_SIZE: u32 = 1 << 24;
+
+/// Return the path of the socket the window manager listens on
+pub(crate) fn socket_path() -> String {
+    format!("/tmp/lwm-{}.sock", whoami::username())
+}
 
 /// The domain in which the messages are taking place
-#[derive(Debug)]
-enum Domain {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum Domain {
     /// Happening at the highest level
     Tree,
     /// Happening at the [`Monitor`] level
@@ -12,5 +31,389 @@ enum Domain {
     /// Happening at the [`Desktop`] level
     Desktop,
     /// Happening at the [`Node`] level
-    Node
+    Node,
+    /// Managing the [`Rule`]s
+    Rule,
+    /// Toggling the [`Scratchpad`]s
+    Scratchpad,
+}
+
+// ============================= Request ==============================
+
+/// A request sent to the window manager
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Request {
+    /// The [`PROTOCOL_VERSION`] of the sender
+    pub(crate) version: u32,
+    /// What is requested
+    pub(crate) message: Message,
+}
+
+impl Request {
+    /// Create a new [`Request`] using the current [`PROTOCOL_VERSION`]
+    pub(crate) const fn new(message: Message) -> Self {
+        Self { version: PROTOCOL_VERSION, message }
+    }
+}
+
+/// The content of a [`Request`], one variant per [`Domain`]
+///
+/// The selectors are the same as the ones used on the command line. When
+/// none is given, the focused item is used
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum Message {
+    /// Act on the window manager as a whole
+    Tree(TreeCommand),
+    /// Act on the [`Monitor`] matching the selector
+    Monitor {
+        /// The monitor selector
+        selector: Option<String>,
+        /// What to do with the [`Monitor`]
+        command:  MonitorCommand,
+    },
+    /// Act on the [`Desktop`] matching the selector
+    Desktop {
+        /// The desktop selector
+        selector: Option<String>,
+        /// What to do with the [`Desktop`]
+        command:  DesktopCommand,
+    },
+    /// Act on the [`Node`] matching the selector
+    Node {
+        /// The node selector
+        selector: Option<String>,
+        /// What to do with the [`Node`]
+        command:  NodeCommand,
+    },
+    /// Add, remove or list [`Rule`]s
+    Rule(RuleCommand),
+    /// Toggle or list the [`Scratchpad`]s
+    Scratchpad(ScratchpadCommand),
+}
+
+impl Message {
+    /// Return the [`Domain`] of the [`Message`]
+    pub(crate) const fn domain(&self) -> Domain {
+        match self {
+            Self::Tree(_) => Domain::Tree,
+            Self::Monitor { .. } => Domain::Monitor,
+            Self::Desktop { .. } => Domain::Desktop,
+            Self::Node { .. } => Domain::Node,
+            Self::Rule(_) => Domain::Rule,
+            Self::Scratchpad(_) => Domain::Scratchpad,
+        }
+    }
+}
+
+/// Commands of the [`Domain::Tree`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum TreeCommand {
+    /// Quit the window manager
+    Quit,
+    /// List the focus history, newest first
+    History,
+    /// Focus the [`Node`] selected by `older` or `newer` and modifiers,
+    /// without recording it in the focus history, so that repeated jumps keep
+    /// going back in time
+    Jump(String),
+    /// Receive the given events, or only the report if there are none
+    ///
+    /// Unless `fifo` is set, the events are sent back over the socket as
+    /// successful [`Reply`]s, one per event. Otherwise, a named FIFO is created
+    /// and its path is the only [`Reply`]
+    Subscribe {
+        /// Names of the events, or of the groups of events
+        events: Vec<String>,
+        /// Write the events to a named FIFO instead
+        fifo:   bool,
+        /// Stop after this many events
+        count:  Option<usize>,
+    },
+    /// Serialize the whole state of the window manager into JSON
+    DumpState,
+    /// Replace the state of the window manager with the one in the file at
+    /// the given path, written by [`TreeCommand::DumpState`]
+    LoadState(String),
+    /// Dump the state to a file and replace the window manager with a new
+    /// instance of its binary, which loads it back
+    Restart,
+    /// Dump the tree as JSON, or list the items matching the selector
+    ///
+    /// The `monitor`, `desktop` and `node` selectors restrict the query to
+    /// the items within them
+    Query {
+        /// What to print
+        kind:     QueryKind,
+        /// Only list the items matching this selector, or these modifiers if
+        /// it starts with a `.`
+        selector: Option<String>,
+        /// The monitor the query is restricted to
+        monitor:  Option<String>,
+        /// The desktop the query is restricted to
+        desktop:  Option<String>,
+        /// The node the query is restricted to
+        node:     Option<String>,
+        /// Print names instead of IDs
+        names:    bool,
+    },
+}
+
+/// What a [`TreeCommand::Query`] prints
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum QueryKind {
+    /// The JSON description of the most specific item of the scope
+    Tree,
+    /// The [`Monitor`]s matching the selector, one per line
+    Monitors,
+    /// The [`Desktop`]s matching the selector, one per line
+    Desktops,
+    /// The [`Node`]s matching the selector, one per line
+    Nodes,
+}
+
+/// Commands of the [`Domain::Monitor`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum MonitorCommand {
+    /// Return the ID of the [`Monitor`]
+    Query,
+    /// Focus the [`Monitor`]
+    Focus,
+    /// Rename the [`Monitor`]
+    Rename(String),
+}
+
+/// Commands of the [`Domain::Desktop`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum DesktopCommand {
+    /// Return the ID of the [`Desktop`]
+    Query,
+    /// Show the [`Desktop`] on its [`Monitor`] and focus it
+    Focus,
+    /// Rename the [`Desktop`]
+    Rename(String),
+    /// Cancel the preselection of every [`Node`] of the [`Desktop`]
+    PreselCancel,
+}
+
+/// Commands of the [`Domain::Node`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum NodeCommand {
+    /// Return the ID of the [`Node`]
+    Query,
+    /// Focus the [`Node`], showing its [`Desktop`] if needed
+    Focus,
+    /// Close the window of the [`Node`] gracefully
+    Close,
+    /// Kill the client of the [`Node`]
+    Kill,
+    /// Preselect the [`Node`], so that the next window is inserted in the
+    /// given direction (north, south, east or west)
+    PreselDir(String),
+    /// Change the ratio of the preselection of the [`Node`], which is in
+    /// `(0, 1)`
+    PreselRatio(String),
+    /// Cancel the preselection of the [`Node`]
+    PreselCancel,
+}
+
+/// Commands of the [`Domain::Rule`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum RuleCommand {
+    /// Add a [`Rule`] applying `key=value` pairs to the windows matching a
+    /// pattern in the form of `CLASS[:INSTANCE[:NAME]]`
+    Add {
+        /// The pattern the windows must match
+        pattern:  String,
+        /// The `key=value` pairs
+        effect:   Vec<String>,
+        /// Remove the [`Rule`] once it has been applied
+        one_shot: bool,
+    },
+    /// Remove the [`Rule`]s matching `head`, `tail`, `^<n>` or a pattern
+    Remove(String),
+    /// List the [`Rule`]s, one per line
+    List,
+}
+
+/// Commands of the [`Domain::Scratchpad`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum ScratchpadCommand {
+    /// Show the [`Scratchpad`] with the given name on the focused [`Desktop`],
+    /// or hide it if it is already shown there
+    Toggle(String),
+    /// List the [`Scratchpad`]s and their window, one per line
+    List,
+}
+
+// ============================== Reply ===============================
+
+/// Whether a [`Request`] succeeded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum Status {
+    /// The [`Request`] was carried out
+    Success,
+    /// The [`Request`] failed; the payload holds the reason
+    Failure,
+}
+
+/// The answer of the window manager to a [`Request`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Reply {
+    /// The [`PROTOCOL_VERSION`] of the window manager
+    pub(crate) version: u32,
+    /// Whether the [`Request`] succeeded
+    pub(crate) status:  Status,
+    /// Output of the [`Request`], or the error message
+    pub(crate) payload: String,
+}
+
+impl Reply {
+    /// Create a successful [`Reply`]
+    pub(crate) fn success<S: Into<String>>(payload: S) -> Self {
+        Self { version: PROTOCOL_VERSION, status: Status::Success, payload: payload.into() }
+    }
+
+    /// Create a failed [`Reply`]
+    pub(crate) fn failure<S: Into<String>>(payload: S) -> Self {
+        Self { version: PROTOCOL_VERSION, status: Status::Failure, payload: payload.into() }
+    }
+
+    /// Was the [`Request`] successful?
+    pub(crate) fn is_success(&self) -> bool {
+        self.status == Status::Success
+    }
+}
+
+impl From<Result<String, String>> for Reply {
+    fn from(result: Result<String, String>) -> Self {
+        match result {
+            Ok(payload) => Self::success(payload),
+            Err(payload) => Self::failure(payload),
+        }
+    }
+}
+
+// ============================= Framing ==============================
+
+/// Write a length-prefixed bincode frame, the same way `Stream::send` does
+pub(crate) fn write_frame<W: Write, T: Serialize>(writer: &mut W, item: &T) -> Result<()> {
+    let data = bincode::serialize(item).context("failed to serialize data")?;
+    let length =
+        bincode::serialize(&(data.len() as u32)).context("failed to serialize data length")?;
+
+    writer
+        .write_all(&length)
+        .and_then(|_| writer.write_all(&data))
+        .and_then(|_| writer.flush())
+        .context("failed to write frame")
+}
+
+/// Read a length-prefixed bincode frame, blocking until it is complete
+pub(crate) fn read_frame<R: Read, T: DeserializeOwned>(reader: &mut R) -> Result<T> {
+    let mut length = [0_u8; 4];
+    reader
+        .read_exact(&mut length)
+        .context("failed to read frame length")?;
+    let length = check_frame_length(&length)?;
+
+    let mut data = vec![0_u8; length];
+    reader
+        .read_exact(&mut data)
+        .context("failed to read frame")?;
+
+    decode_frame(&data)
+}
+
+/// Decode the length prefix of a frame, refusing lengths over
+/// [`MAX_FRAME_SIZE`]
+pub(crate) fn check_frame_length(prefix: &[u8]) -> Result<usize> {
+    let length: u32 = bincode::deserialize(prefix).context("failed to deserialize length")?;
+    if length > MAX_FRAME_SIZE {
+        return Err(anyhow!("frame of {} bytes is over the limit of {}", length, MAX_FRAME_SIZE));
+    }
+
+    Ok(length as usize)
+}
+
+/// Decode the data of a frame. The sizes it contains can't make the decoder
+/// allocate more than [`MAX_FRAME_SIZE`] bytes
+pub(crate) fn decode_frame<T: DeserializeOwned>(data: &[u8]) -> Result<T> {
+    // The same encoding as `bincode::deserialize`, with a limit
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+        .with_limit(u64::from(MAX_FRAME_SIZE))
+        .deserialize(data)
+        .context("failed to deserialize frame")
+}
+
+/// Tests for the framing of [`Request`]s and [`Reply`]s
+#[cfg(test)]
+mod tests {
+    use super::{
+        decode_frame,
+        read_frame,
+        write_frame,
+        Domain,
+        Message,
+        NodeCommand,
+        Reply,
+        Request,
+        Status,
+        TreeCommand,
+        MAX_FRAME_SIZE,
+        PROTOCOL_VERSION,
+    };
+    use std::io::Cursor;
+
+    #[test]
+    fn request_roundtrip() {
+        let request = Request::new(Message::Node {
+            selector: Some(String::from("west.local")),
+            command:  NodeCommand::Close,
+        });
+
+        let mut buf = vec![];
+        write_frame(&mut buf, &request).unwrap();
+        let read: Request = read_frame(&mut Cursor::new(buf)).unwrap();
+
+        assert_eq!(read, request);
+        assert_eq!(read.version, PROTOCOL_VERSION);
+        assert_eq!(read.message.domain(), Domain::Node);
+    }
+
+    #[test]
+    fn reply_status() {
+        let mut buf = vec![];
+        write_frame(&mut buf, &Reply::from(Err(String::from("no match")))).unwrap();
+        write_frame(&mut buf, &Reply::success("0x00000001")).unwrap();
+
+        let mut cursor = Cursor::new(buf);
+        let failure: Reply = read_frame(&mut cursor).unwrap();
+        let success: Reply = read_frame(&mut cursor).unwrap();
+
+        assert_eq!(failure.status, Status::Failure);
+        assert_eq!(failure.payload, "no match");
+        assert!(success.is_success());
+        assert_eq!(success.payload, "0x00000001");
+    }
+
+    #[test]
+    fn truncated_frame() {
+        let mut buf = vec![];
+        write_frame(&mut buf, &Request::new(Message::Tree(TreeCommand::Quit))).unwrap();
+        buf.pop();
+
+        assert!(read_frame::<_, Request>(&mut Cursor::new(buf)).is_err());
+    }
+
+    #[test]
+    fn oversized_frame() {
+        let mut buf = (MAX_FRAME_SIZE + 1).to_le_bytes().to_vec();
+        buf.extend([0_u8; 16]);
+        assert!(read_frame::<_, Request>(&mut Cursor::new(buf)).is_err());
+
+        // A string claiming to be huge is refused before it is allocated
+        assert!(decode_frame::<String>(&(1_u64 << 40).to_le_bytes()).is_err());
+    }
 }
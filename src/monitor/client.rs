@@ -10,6 +10,7 @@ use crate::{
     x::property::SizeHints,
 };
 use anyhow::{Context, Result};
+use attr_rs::{attr_accessor, attr_reader, attr_writer};
 use serde::{Deserialize, Serialize};
 use std::time::SystemTime;
 use x11rb::properties::WmSizeHints;
@@ -55,6 +56,22 @@ impl Default for IcccmProps {
 
 /// Information about a top-level [`Window`]
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[attr_reader(window, name, class, instance, last_layer, last_state)]
+#[attr_accessor(
+    pid,
+    ppid,
+    border_width,
+    layer,
+    state,
+    floating_rectangle,
+    tiled_rectangle,
+    size_hints,
+    honor_size_hints,
+    icccm_props,
+    wm_flags,
+    urgent,
+    shown
+)]
 pub(crate) struct Client {
     window:   Window,
     name:     String,
@@ -70,14 +87,94 @@ pub(crate) struct Client {
     floating_rectangle: Rectangle,
     tiled_rectangle:    Rectangle,
     size_hints:         SizeHints,
+    /// Whether the [`SizeHints`] are applied, as decided by a rule. The
+    /// configuration decides otherwise
+    #[serde(default)]
+    honor_size_hints:   Option<bool>,
     icccm_props:        IcccmProps,
-    wm_flags:           WindowState,
+    wm_flags:           Vec<WindowState>,
 
     urgent: bool,
     shown:  bool,
 
     pid:  Option<Pid>,
     ppid: Option<Pid>,
+
+    /// The terminal swallowed by the [`Client`], given back its place once the
+    /// [`Client`] goes away
+    #[serde(default)]
+    producer: Option<Box<Client>>,
+}
+
+impl Client {
+    /// Create a new tiled [`Client`]
+    pub(crate) fn new(
+        window: Window,
+        name: String,
+        class: String,
+        instance: String,
+        border_width: usize,
+    ) -> Self {
+        Self {
+            window,
+            name,
+            class,
+            instance,
+            border_width,
+            layer: StackLayer::Normal,
+            last_layer: StackLayer::Normal,
+            state: ClientState::Tiled,
+            last_state: ClientState::Tiled,
+            floating_rectangle: Rectangle::default(),
+            tiled_rectangle: Rectangle::default(),
+            size_hints: SizeHints::default(),
+            honor_size_hints: None,
+            icccm_props: IcccmProps::default(),
+            wm_flags: vec![],
+            urgent: false,
+            shown: false,
+            pid: None,
+            ppid: None,
+            producer: None,
+        }
+    }
+
+    /// Return the terminal swallowed by the [`Client`]
+    pub(crate) fn producer(&self) -> Option<&Self> {
+        self.producer.as_deref()
+    }
+
+    /// Swallow a terminal, which is hidden until the [`Client`] goes away
+    pub(crate) fn swallow(&mut self, producer: Self) {
+        self.producer = Some(Box::new(producer));
+    }
+
+    /// Take back the terminal swallowed by the [`Client`]
+    pub(crate) fn take_producer(&mut self) -> Option<Self> {
+        self.producer.take().map(|producer| *producer)
+    }
+
+    /// Change the [`ClientState`], remembering the previous one to go back to.
+    /// Return whether it changed
+    pub(crate) fn change_state(&mut self, state: ClientState) -> bool {
+        if self.state == state {
+            return false;
+        }
+        self.last_state = self.state;
+        self.state = state;
+        true
+    }
+
+    /// Change the [`StackLayer`], remembering the previous one to go back to.
+    /// Return whether it changed
+    pub(crate) fn change_layer(&mut self, layer: StackLayer) -> bool {
+        if self.layer == layer {
+            return false;
+        }
+        self.last_layer = self.layer;
+        self.layer = layer;
+        true
+    }
 }
 
 impl PartialEq for Client {
@@ -127,8 +224,6 @@ impl PartialEq for Client {
 
 // ============================== Client ==============================
 
-use attr_rs::{attr_accessor, attr_reader, attr_writer};
-
 // typedef struct {
 //     char class_name[MAXLEN];
 //     char instance_name[MAXLEN];
@@ -524,8 +619,29 @@ impl PartialEq for Client1 {
 //     }
 // }
 
+#[cfg(test)]
 mod tests {
-    use super::{Client1, WindowType};
+    use super::{Client, Client1, ClientState, StackLayer, WindowType};
+
+    #[test]
+    fn change_state() {
+        let mut client = Client::new(
+            1,
+            String::from("window1"),
+            String::from("class1"),
+            String::from("instance1"),
+            1,
+        );
+
+        assert!(client.change_state(ClientState::Floating));
+        assert!(client.change_state(ClientState::Fullscreen));
+        assert!(!client.change_state(ClientState::Fullscreen));
+        assert_eq!(client.get_last_state(), &ClientState::Floating);
+
+        assert!(client.change_layer(StackLayer::Above));
+        assert!(!client.change_layer(StackLayer::Above));
+        assert_eq!(client.get_last_layer(), &StackLayer::Normal);
+    }
 
     #[test]
     fn attr_accesor() {
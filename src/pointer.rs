@@ -1 +1,296 @@
-//! TODO:
+//! Moving and resizing floating [`Window`]s with the pointer, or with the
+//! keyboard when a client asks for it
+
+use crate::{
+    core::{Corner, DragType, ResizeHandle, Window},
+    geometry::{Point, Rectangle},
+    monitor::client::ClientState,
+};
+
+/// `XK_Escape`, which cancels a [`Drag`]
+pub(crate) const KEY_ESCAPE: u32 = 0xff1b;
+/// `XK_Return`, which ends a [`Drag`]
+pub(crate) const KEY_RETURN: u32 = 0xff0d;
+/// `XK_Left`
+const KEY_LEFT: u32 = 0xff51;
+/// `XK_Up`
+const KEY_UP: u32 = 0xff52;
+/// `XK_Right`
+const KEY_RIGHT: u32 = 0xff53;
+/// `XK_Down`
+const KEY_DOWN: u32 = 0xff54;
+/// Distance the pointer travels each time an arrow key is pressed
+const KEY_STEP: i32 = 10;
+
+/// Operation asked by a client with `_NET_WM_MOVERESIZE`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum MoveResize {
+    /// Resize with the pointer by moving a side or a corner
+    Size(ResizeHandle),
+    /// Move with the pointer
+    Move,
+    /// Resize with the keyboard
+    SizeKeyboard,
+    /// Move with the keyboard
+    MoveKeyboard,
+    /// Stop the [`Drag`] in progress
+    Cancel,
+}
+
+impl MoveResize {
+    /// Decode the direction of a `_NET_WM_MOVERESIZE` message
+    pub(crate) const fn from_direction(direction: u32) -> Option<Self> {
+        Some(match direction {
+            0 => Self::Size(ResizeHandle::TopLeft),
+            1 => Self::Size(ResizeHandle::Top),
+            2 => Self::Size(ResizeHandle::TopRight),
+            3 => Self::Size(ResizeHandle::Right),
+            4 => Self::Size(ResizeHandle::BottomRight),
+            5 => Self::Size(ResizeHandle::Bottom),
+            6 => Self::Size(ResizeHandle::BottomLeft),
+            7 => Self::Size(ResizeHandle::Left),
+            8 => Self::Move,
+            9 => Self::SizeKeyboard,
+            10 => Self::MoveKeyboard,
+            11 => Self::Cancel,
+            _ => return None,
+        })
+    }
+}
+
+/// What a [`Drag`] does to its [`Window`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DragAction {
+    /// The [`Window`] follows the pointer
+    Move,
+    /// The dragged side or corner follows the pointer, the opposite one stays
+    Resize(DragType),
+}
+
+/// A move or a resize of a floating [`Window`] in progress
+#[derive(Debug, Clone)]
+pub(crate) struct Drag {
+    /// Window that is being dragged
+    window:   Window,
+    /// What is done to the window
+    action:   DragAction,
+    /// Position of the pointer when the drag started
+    origin:   Point,
+    /// Last position of the pointer
+    pointer:  Point,
+    /// Rectangle of the window when the drag started
+    start:    Rectangle,
+    /// State of the window before it was made floating to be dragged
+    state:    ClientState,
+    /// Is the drag driven by the keyboard?
+    keyboard: bool,
+}
+
+impl Drag {
+    /// Start dragging a [`Window`], from the pointer at `origin`
+    ///
+    /// `state` is the [`ClientState`] the window goes back to if the drag is
+    /// cancelled
+    pub(crate) const fn new(
+        window: Window,
+        action: DragAction,
+        origin: Point,
+        start: Rectangle,
+        state: ClientState,
+        keyboard: bool,
+    ) -> Self {
+        Self {
+            window,
+            action,
+            origin,
+            pointer: origin,
+            start,
+            state,
+            keyboard,
+        }
+    }
+
+    /// Start a [`MoveResize`] operation of a [`Window`], from the pointer at
+    /// `pointer`. Keyboard operations start from the center of the window when
+    /// moving and from its bottom right corner when resizing
+    pub(crate) fn from_move_resize(
+        window: Window,
+        operation: MoveResize,
+        pointer: Point,
+        start: Rectangle,
+        state: ClientState,
+    ) -> Option<Self> {
+        let (action, origin, keyboard) = match operation {
+            MoveResize::Size(handle) => (DragAction::Resize(handle.into()), pointer, false),
+            MoveResize::Move => (DragAction::Move, pointer, false),
+            MoveResize::SizeKeyboard => (
+                DragAction::Resize(DragType::Corner(Corner::BottomRight)),
+                start.bottom_right(),
+                true,
+            ),
+            MoveResize::MoveKeyboard =>
+                (DragAction::Move, Point::from_center_of_rect(start), true),
+            MoveResize::Cancel => return None,
+        };
+
+        Some(Self::new(window, action, origin, start, state, keyboard))
+    }
+
+    /// Return the dragged [`Window`]
+    pub(crate) const fn window(&self) -> Window {
+        self.window
+    }
+
+    /// Return the [`Rectangle`] of the [`Window`] when the drag started
+    pub(crate) const fn start(&self) -> Rectangle {
+        self.start
+    }
+
+    /// Return the [`ClientState`] of the [`Window`] before the drag started
+    pub(crate) const fn state(&self) -> ClientState {
+        self.state
+    }
+
+    /// Return the last position of the pointer
+    pub(crate) const fn pointer(&self) -> Point {
+        self.pointer
+    }
+
+    /// Is the drag driven by the keyboard?
+    pub(crate) const fn is_keyboard(&self) -> bool {
+        self.keyboard
+    }
+
+    /// Follow the pointer to the given [`Point`] and return the new
+    /// [`Rectangle`] of the [`Window`], which is at least one pixel wide and
+    /// high
+    pub(crate) fn motion(&mut self, pointer: Point) -> Rectangle {
+        self.pointer = pointer;
+        let (dx, dy) = (pointer.x - self.origin.x, pointer.y - self.origin.y);
+        let mut rect = self.start;
+
+        match self.action {
+            DragAction::Move => {
+                rect.point.x += dx;
+                rect.point.y += dy;
+            },
+            DragAction::Resize(kind) => {
+                let width = self.start.dimension.width as i32;
+                let height = self.start.dimension.height as i32;
+
+                if kind.is_left_drag() {
+                    let w = (width - dx).max(1);
+                    rect.point.x += width - w;
+                    rect.dimension.width = w as u32;
+                } else if kind.is_right_drag() {
+                    rect.dimension.width = (width + dx).max(1) as u32;
+                }
+
+                if kind.is_top_drag() {
+                    let h = (height - dy).max(1);
+                    rect.point.y += height - h;
+                    rect.dimension.height = h as u32;
+                } else if kind.is_bottom_drag() {
+                    rect.dimension.height = (height + dy).max(1) as u32;
+                }
+            },
+        }
+
+        rect
+    }
+
+    /// Return where the pointer goes when an arrow key is pressed, if the
+    /// keysym is one
+    pub(crate) const fn step(&self, keysym: u32) -> Option<Point> {
+        let (dx, dy) = match keysym {
+            KEY_LEFT => (-KEY_STEP, 0),
+            KEY_UP => (0, -KEY_STEP),
+            KEY_RIGHT => (KEY_STEP, 0),
+            KEY_DOWN => (0, KEY_STEP),
+            _ => return None,
+        };
+
+        Some(Point::new(self.pointer.x + dx, self.pointer.y + dy))
+    }
+}
+
+/// Tests for decoding `_NET_WM_MOVERESIZE` and following the pointer
+#[cfg(test)]
+mod tests {
+    use super::{Drag, DragAction, MoveResize, KEY_DOWN, KEY_LEFT, KEY_RETURN};
+    use crate::{
+        core::{Corner, DragType, Edge, ResizeHandle},
+        geometry::{Point, Rectangle},
+        monitor::client::ClientState,
+    };
+
+    #[test]
+    fn from_direction() {
+        assert_eq!(
+            MoveResize::from_direction(0),
+            Some(MoveResize::Size(ResizeHandle::TopLeft))
+        );
+        assert_eq!(
+            MoveResize::from_direction(5),
+            Some(MoveResize::Size(ResizeHandle::Bottom))
+        );
+        assert_eq!(
+            MoveResize::from_direction(7),
+            Some(MoveResize::Size(ResizeHandle::Left))
+        );
+        assert_eq!(MoveResize::from_direction(8), Some(MoveResize::Move));
+        assert_eq!(MoveResize::from_direction(11), Some(MoveResize::Cancel));
+        assert_eq!(MoveResize::from_direction(12), None);
+
+        assert_eq!(
+            DragType::from(ResizeHandle::BottomLeft),
+            DragType::Corner(Corner::BottomLeft)
+        );
+        assert_eq!(DragType::from(ResizeHandle::Top), DragType::Edge(Edge::Top));
+    }
+
+    #[test]
+    fn motion() {
+        let start = Rectangle::new(100, 100, 200, 100);
+        let origin = Point::new(150, 110);
+        let state = ClientState::Floating;
+
+        let mut drag = Drag::new(1, DragAction::Move, origin, start, state, false);
+        assert_eq!(drag.motion(Point::new(140, 130)), Rectangle::new(90, 120, 200, 100));
+
+        // The opposite side stays where it is
+        let kind = DragType::from(ResizeHandle::TopLeft);
+        let mut drag = Drag::new(1, DragAction::Resize(kind), origin, start, state, false);
+        assert_eq!(drag.motion(Point::new(170, 100)), Rectangle::new(120, 90, 180, 110));
+        assert_eq!(drag.motion(Point::new(400, 300)), Rectangle::new(299, 199, 1, 1));
+
+        let kind = DragType::from(ResizeHandle::Right);
+        let mut drag = Drag::new(1, DragAction::Resize(kind), origin, start, state, false);
+        assert_eq!(drag.motion(Point::new(180, 0)), Rectangle::new(100, 100, 230, 100));
+        assert_eq!(drag.motion(Point::new(-100, 0)), Rectangle::new(100, 100, 1, 100));
+    }
+
+    #[test]
+    fn keyboard() {
+        let start = Rectangle::new(100, 100, 200, 100);
+        let pointer = Point::new(0, 0);
+        let state = ClientState::Tiled;
+
+        assert!(Drag::from_move_resize(1, MoveResize::Cancel, pointer, start, state).is_none());
+
+        let operation = MoveResize::MoveKeyboard;
+        let mut drag = Drag::from_move_resize(1, operation, pointer, start, state).unwrap();
+        assert!(drag.is_keyboard());
+        assert_eq!(drag.state(), ClientState::Tiled);
+        assert_eq!(drag.pointer(), Point::new(200, 150));
+        assert_eq!(drag.step(KEY_RETURN), None);
+        let pointer = drag.step(KEY_LEFT).unwrap();
+        assert_eq!(drag.motion(pointer), Rectangle::new(90, 100, 200, 100));
+
+        let operation = MoveResize::SizeKeyboard;
+        let mut drag = Drag::from_move_resize(1, operation, pointer, start, state).unwrap();
+        assert_eq!(drag.pointer(), Point::new(300, 200));
+        let pointer = drag.step(KEY_DOWN).unwrap();
+        assert_eq!(drag.motion(pointer), Rectangle::new(100, 100, 200, 110));
+    }
+}
@@ -2,7 +2,82 @@
 
 #![allow(clippy::missing_docs_in_private_items)]
 
+use crate::{
+    core::{AreaPeak, CycleDir, Direction, HistoryDir, LayoutType, Output, Xid},
+    geometry::{Padding, Rectangle},
+    monitor::client::{Client, ClientState},
+    stack::StackLayer,
+    state::State,
+    tree::{Coordinates, Desktop, Monitor, Node, NodeId, Presel, SplitType, Tree},
+};
 use anyhow::{Context, Result};
+use serde::Serialize;
+use std::str::FromStr;
+use tern::t;
+use thiserror::Error;
+
+// ============================== Errors ==============================
+
+/// Errors that occur when resolving a selector
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub(crate) enum SelectorError {
+    /// The descriptor of the selector is not known
+    #[error("invalid descriptor '{desc}' in {kind} selector '{selector}'")]
+    InvalidDescriptor {
+        kind:     &'static str,
+        selector: String,
+        desc:     String,
+    },
+
+    /// A modifier of the selector is not known
+    #[error("invalid modifier '{modifier}' in {kind} selector '{selector}'")]
+    InvalidModifier {
+        kind:     &'static str,
+        selector: String,
+        modifier: String,
+    },
+
+    /// A jump within a node path is not known
+    #[error("invalid jump '{jump}' in node path '{path}'")]
+    InvalidJump { path: String, jump: String },
+
+    /// The selector is valid, but nothing matches it
+    #[error("no {kind} matches the selector '{selector}'")]
+    NoMatch {
+        kind:     &'static str,
+        selector: String,
+    },
+}
+
+/// Check an optional modifier against a value
+fn check(modifier: Option<bool>, value: bool) -> bool {
+    modifier.map_or(true, |m| m == value)
+}
+
+/// Check an optional modifier against a property of a [`Client`]. The modifier
+/// never matches a [`Node`] without a [`Client`]
+fn check_client(modifier: Option<bool>, client: Option<&Client>, f: fn(&Client) -> bool) -> bool {
+    modifier.map_or(true, |m| client.map_or(false, |c| f(c) == m))
+}
+
+/// Split a selector into its descriptor and its modifiers
+///
+/// Modifiers start at the first `.` found after the last `:`, so that names
+/// containing a `.` can still be used before a `:`
+fn split_modifiers(desc: &str) -> (&str, &str) {
+    let start = desc.rfind(':').map_or(0, |c| c + 1);
+    desc[start..]
+        .find('.')
+        .map_or((desc, ""), |dot| desc.split_at(start + dot))
+}
+
+/// Parse an ID, either in hexadecimal (`0x` prefix) or decimal
+pub(crate) fn parse_id(s: &str) -> Option<Xid> {
+    s.strip_prefix("0x")
+        .map_or_else(|| s.parse().ok(), |hex| Xid::from_str_radix(hex, 16).ok())
+}
+
+// ============================ NodeSelect ============================
 
 /// Query information about the selected [`Node`]
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
@@ -33,8 +108,513 @@ pub(crate) struct NodeSelect {
     vertical:      Option<bool>,
 }
 
+impl NodeSelect {
+    /// Parse modifiers in the form of `.tiled.!floating`, returning the first
+    /// invalid modifier on failure
+    pub(crate) fn parse(modifiers: &str) -> Result<Self, String> {
+        let mut sel = Self::default();
+
+        for modifier in modifiers.split('.').skip(1) {
+            let (name, value) = modifier
+                .strip_prefix('!')
+                .map_or((modifier, true), |name| (name, false));
+
+            let field = match name {
+                "automatic" => &mut sel.automatic,
+                "focused" => &mut sel.focused,
+                "active" => &mut sel.active,
+                "local" => &mut sel.local,
+                "leaf" => &mut sel.leaf,
+                "window" => &mut sel.window,
+                "tiled" => &mut sel.tiled,
+                "pseudo_tiled" => &mut sel.pseudo_tiled,
+                "floating" => &mut sel.floating,
+                "fullscreen" => &mut sel.fullscreen,
+                "hidden" => &mut sel.hidden,
+                "sticky" => &mut sel.sticky,
+                "private" => &mut sel.private,
+                "locked" => &mut sel.locked,
+                "marked" => &mut sel.marked,
+                "urgent" => &mut sel.urgent,
+                "same_class" => &mut sel.same_class,
+                "descendant_of" => &mut sel.descendant_of,
+                "ancestor_of" => &mut sel.ancestor_of,
+                "below" => &mut sel.below,
+                "normal" => &mut sel.normal,
+                "above" => &mut sel.above,
+                "horizontal" => &mut sel.horizontal,
+                "vertical" => &mut sel.vertical,
+                _ => return Err(modifier.to_owned()),
+            };
+            *field = Some(value);
+        }
+
+        Ok(sel)
+    }
+
+    /// Does the [`Node`] at `loc` satisfy every modifier?
+    pub(crate) fn matches(&self, state: &State, loc: &Coordinates, reference: &Coordinates) -> bool {
+        let (id, node) = match (loc.node, state.node(loc)) {
+            (Some(id), Some(node)) => (id, node),
+            _ => return false,
+        };
+        let tree = match state.desktop(loc.desktop) {
+            Some((_, desktop)) => desktop.tree(),
+            None => return false,
+        };
+
+        let client = node.client();
+        let ref_client = state.node(reference).and_then(Node::client);
+        let ref_node = reference.node.filter(|_| reference.desktop == loc.desktop);
+        let active = state
+            .monitor(loc.monitor)
+            .and_then(|m| m.desk_id())
+            .map_or(false, |d| d == loc.desktop);
+
+        check(self.hidden, node.is_hidden())
+            && check(self.sticky, node.is_sticky())
+            && check(self.private, node.is_private())
+            && check(self.locked, node.is_locked())
+            && check(self.marked, node.is_marked())
+            && check(self.window, client.is_some())
+            && check(self.automatic, node.presel().is_none())
+            && check(self.local, loc.desktop == reference.desktop)
+            && check(self.active, active)
+            && check(self.leaf, node.is_leaf())
+            && check(self.focused, state.focused() == Some(*loc))
+            && check(
+                self.descendant_of,
+                ref_node.map_or(false, |r| tree.is_descendant(id, r)),
+            )
+            && check(
+                self.ancestor_of,
+                ref_node.map_or(false, |r| tree.is_descendant(r, id)),
+            )
+            && check(self.horizontal, node.split_type() == SplitType::Horizontal)
+            && check(self.vertical, node.split_type() == SplitType::Vertical)
+            && self.same_class.map_or(true, |m| match (client, ref_client) {
+                (Some(c), Some(r)) => (c.get_class() == r.get_class()) == m,
+                _ => false,
+            })
+            && check_client(self.tiled, client, |c| *c.get_state() == ClientState::Tiled)
+            && check_client(self.pseudo_tiled, client, |c| {
+                *c.get_state() == ClientState::PsuedoTiled
+            })
+            && check_client(self.floating, client, |c| {
+                *c.get_state() == ClientState::Floating
+            })
+            && check_client(self.fullscreen, client, |c| {
+                *c.get_state() == ClientState::Fullscreen
+            })
+            && check_client(self.below, client, |c| *c.get_layer() == StackLayer::Below)
+            && check_client(self.normal, client, |c| *c.get_layer() == StackLayer::Normal)
+            && check_client(self.above, client, |c| *c.get_layer() == StackLayer::Above)
+            && check_client(self.urgent, client, |c| *c.get_urgent())
+    }
+}
+
+// =========================== Node Selector ==========================
+
+/// A single jump within a node path
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Jump {
+    First,
+    Second,
+    Parent,
+    Brother,
+    Fence(Direction),
+}
+
+impl FromStr for Jump {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "first" | "1" => Ok(Self::First),
+            "second" | "2" => Ok(Self::Second),
+            "parent" => Ok(Self::Parent),
+            "brother" => Ok(Self::Brother),
+            _ => Direction::from_str(s).map(Self::Fence).map_err(|_| ()),
+        }
+    }
+}
+
+/// Resolve a node selector into the [`Coordinates`] of a [`Node`]
+///
+/// The grammar is the same as the one used by `bspc`:
+///
+/// ```text
+/// NODE_SEL := [NODE_SEL#](DIR|CYCLE_DIR|PATH|any|first_ancestor|last|newest|
+///             older|newer|focused|biggest|smallest|<node_id>)[.[!]MODIFIER]*
+///
+/// PATH := @[/]JUMP(/JUMP)*
+/// JUMP := first|1|second|2|brother|parent|DIR
+/// ```
+///
+/// `NODE_SEL#` replaces the reference with the [`Node`] it selects.
+/// Modifiers are the fields of [`NodeSelect`]
+pub(crate) fn node_from_desc(
+    desc: &str,
+    state: &State,
+    reference: &Coordinates,
+) -> Result<Coordinates, SelectorError> {
+    if let Some((ref_desc, desc)) = desc.rsplit_once('#') {
+        let reference = node_from_desc(ref_desc, state, reference)?;
+        return node_from_desc(desc, state, &reference);
+    }
+
+    let (head, modifiers) = split_modifiers(desc);
+    let sel = NodeSelect::parse(modifiers).map_err(|modifier| SelectorError::InvalidModifier {
+        kind: "node",
+        selector: desc.to_owned(),
+        modifier,
+    })?;
+    let matches = |loc: &Coordinates| sel.matches(state, loc, reference);
+
+    let found = if let Ok(dir) = Direction::from_str(head) {
+        find_nearest_neighbor(state, reference, dir, &sel)
+    } else if let Ok(dir) = CycleDir::from_str(head) {
+        find_closest_node(state, reference, dir, &sel)
+    } else if let Ok(dir) = HistoryDir::from_str(head) {
+        find_node_in_history(state, reference, dir, &sel)
+    } else if let Ok(peak) = AreaPeak::from_str(head) {
+        find_node_by_area(state, reference, peak, &sel)
+    } else if let Some(path) = head.strip_prefix('@') {
+        node_from_path(path, state, reference)?.filter(matches)
+    } else {
+        match head {
+            "focused" => state.focused().filter(matches),
+            "last" => find_node_in_history(state, reference, HistoryDir::Older, &sel),
+            "newest" => state
+                .history()
+                .find_newest(|loc| is_visible(state, loc) && matches(loc)),
+            "any" => find_any_node(state, reference, &sel),
+            "first_ancestor" => reference.node.and_then(|node| {
+                let (_, desktop) = state.desktop(reference.desktop)?;
+                desktop
+                    .tree()
+                    .ancestors(node)
+                    .map(|n| Coordinates::new(reference.monitor, reference.desktop, Some(n)))
+                    .find(matches)
+            }),
+            _ => state
+                .locate_node(parse_id(head).ok_or_else(|| SelectorError::InvalidDescriptor {
+                    kind:     "node",
+                    selector: desc.to_owned(),
+                    desc:     head.to_owned(),
+                })?)
+                .filter(matches),
+        }
+    };
+
+    found.ok_or_else(|| SelectorError::NoMatch {
+        kind:     "node",
+        selector: desc.to_owned(),
+    })
+}
+
+/// Follow a path of jumps from the reference [`Node`], or from the root of
+/// its [`Desktop`] if the path starts with `/`
+///
+/// The path can be prefixed by `DESK_SEL:`, in which case the focused [`Node`]
+/// of the selected [`Desktop`] becomes the reference
+fn node_from_path(
+    path: &str,
+    state: &State,
+    reference: &Coordinates,
+) -> Result<Option<Coordinates>, SelectorError> {
+    let (reference, path) = match path.rsplit_once(':') {
+        Some((desc, path)) => (desktop_from_desc(desc, state, reference)?, path),
+        None => (*reference, path),
+    };
+    let (from_root, jumps) = match path.strip_prefix('/') {
+        Some(jumps) => (true, jumps),
+        None => (false, path),
+    };
+
+    let jumps = jumps
+        .split('/')
+        .filter(|j| !j.is_empty())
+        .map(|j| {
+            Jump::from_str(j).map_err(|_| SelectorError::InvalidJump {
+                path: path.to_owned(),
+                jump: j.to_owned(),
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let tree = match state.desktop(reference.desktop) {
+        Some((_, desktop)) => desktop.tree(),
+        None => return Ok(None),
+    };
+
+    let mut node = t!(from_root ? tree.root() : reference.node);
+    for jump in jumps {
+        node = node.and_then(|n| match jump {
+            Jump::First => tree.get(n).and_then(Node::first_child),
+            Jump::Second => tree.get(n).and_then(Node::second_child),
+            Jump::Parent => tree.parent(n),
+            Jump::Brother => tree.brother(n),
+            Jump::Fence(dir) => tree.find_fence(n, dir),
+        });
+    }
+
+    Ok(node.map(|n| Coordinates::new(reference.monitor, reference.desktop, Some(n))))
+}
+
+/// Is there a visible [`Node`] at the given [`Coordinates`]?
+fn is_visible(state: &State, loc: &Coordinates) -> bool {
+    state.node(loc).map_or(false, |n| !n.is_hidden())
+}
+
+/// Find the closest window in the given [`Direction`], among the focused
+/// [`Desktop`](crate::tree::Desktop)s of every [`Monitor`](crate::tree::Monitor)
+///
+/// Ties are broken by the most recently focused window
+fn find_nearest_neighbor(
+    state: &State,
+    reference: &Coordinates,
+    dir: Direction,
+    sel: &NodeSelect,
+) -> Option<Coordinates> {
+    let rect = state.rectangle(reference)?;
+    let mut best: Option<(u32, usize, Coordinates)> = None;
+
+    for monitor in state.monitors() {
+        let desktop = match monitor.desk() {
+            Some(desktop) => desktop,
+            None => continue,
+        };
+        let tree = desktop.tree();
+
+        for id in tree.leaves() {
+            let loc = Coordinates::new(monitor.id(), desktop.id(), Some(id));
+            let is_reference = reference.desktop == desktop.id()
+                && reference.node.map_or(false, |r| tree.is_descendant(id, r));
+
+            if is_reference
+                || tree.get(id).and_then(Node::client).is_none()
+                || !is_visible(state, &loc)
+                || !sel.matches(state, &loc, reference)
+            {
+                continue;
+            }
+
+            let r = match state.rectangle(&loc) {
+                Some(r) if rect.on_dir_side(r, dir, state.tightness()) => r,
+                _ => continue,
+            };
+
+            let distance = rect.boundary_distance(r, dir);
+            let rank = state.history().rank(desktop.id(), id);
+            if best.map_or(true, |(d, k, _)| distance < d || (distance == d && rank < k)) {
+                best = Some((distance, rank, loc));
+            }
+        }
+    }
+
+    best.map(|(_, _, loc)| loc)
+}
+
+/// Find the next [`Node`] in the given [`CycleDir`], going through every
+/// [`Desktop`](crate::tree::Desktop) of every [`Monitor`](crate::tree::Monitor)
+/// and wrapping around. Hidden [`Node`]s are skipped unless `.hidden` is given
+fn find_closest_node(
+    state: &State,
+    reference: &Coordinates,
+    dir: CycleDir,
+    sel: &NodeSelect,
+) -> Option<Coordinates> {
+    // Every node in order, with the desktop they belong to
+    let mut all = vec![];
+    let mut ref_desktop = None;
+    for monitor in state.monitors() {
+        for desktop in monitor.desktops() {
+            if desktop.id() == reference.desktop {
+                ref_desktop = Some(all.len());
+            }
+
+            let tree = desktop.tree();
+            let mut node = tree.root().and_then(|r| tree.first_extrema(r));
+            while let Some(n) = node {
+                all.push(Coordinates::new(monitor.id(), desktop.id(), Some(n)));
+                node = tree.next_node(n);
+            }
+        }
+    }
+
+    let len = all.len();
+    if len == 0 {
+        return None;
+    }
+
+    // Without a reference node, start right before (or after) its desktop
+    let start = match all.iter().position(|loc| loc == reference) {
+        Some(idx) => idx,
+        None => match (ref_desktop?, dir) {
+            (idx, CycleDir::Next) => idx + len - 1,
+            (idx, CycleDir::Prev) => {
+                idx + all[idx..]
+                    .iter()
+                    .take_while(|loc| loc.desktop == reference.desktop)
+                    .count()
+            },
+        },
+    };
+
+    (1..=len)
+        .map(|step| match dir {
+            CycleDir::Next => all[(start + step) % len],
+            CycleDir::Prev => all[(start + len * 2 - step) % len],
+        })
+        .filter(|loc| loc != reference)
+        .filter(|loc| reference.node.is_some() || loc.desktop != reference.desktop)
+        .filter(|loc| sel.hidden == Some(true) || is_visible(state, loc))
+        .find(|loc| sel.matches(state, loc, reference))
+}
+
+/// Find a [`Node`] in the focus history
+fn find_node_in_history(
+    state: &State,
+    reference: &Coordinates,
+    dir: HistoryDir,
+    sel: &NodeSelect,
+) -> Option<Coordinates> {
+    state
+        .history()
+        .find(dir, |loc| is_history_candidate(state, loc, reference, sel))
+}
+
+/// Can the focus go back to a location of the history?
+fn is_history_candidate(
+    state: &State,
+    loc: &Coordinates,
+    reference: &Coordinates,
+    sel: &NodeSelect,
+) -> bool {
+    loc.node.is_some()
+        && !(loc.desktop == reference.desktop && loc.node == reference.node)
+        && is_visible(state, loc)
+        && sel.matches(state, loc, reference)
+}
+
+/// Move through the focus history to the [`Node`] selected by `older` or
+/// `newer`, followed by modifiers
+///
+/// Unlike the selectors of the same name, the needle of the
+/// [`History`](crate::tree::History) is moved to the found [`Node`], so that
+/// the next jump starts from it
+pub(crate) fn jump_in_history(
+    desc: &str,
+    state: &mut State,
+    reference: &Coordinates,
+) -> Result<Coordinates, SelectorError> {
+    let (head, modifiers) = split_modifiers(desc);
+    let dir = HistoryDir::from_str(head).map_err(|_| SelectorError::InvalidDescriptor {
+        kind:     "node",
+        selector: desc.to_owned(),
+        desc:     head.to_owned(),
+    })?;
+    let sel = NodeSelect::parse(modifiers).map_err(|modifier| SelectorError::InvalidModifier {
+        kind: "node",
+        selector: desc.to_owned(),
+        modifier,
+    })?;
+
+    // The candidates are checked against the state, so a copy is moved
+    let mut history = state.history().clone();
+    let found = history.jump(dir, |loc| is_history_candidate(state, loc, reference, &sel));
+    *state.history_mut() = history;
+
+    found.ok_or_else(|| SelectorError::NoMatch {
+        kind:     "node",
+        selector: desc.to_owned(),
+    })
+}
+
+/// Find the leaf with the biggest or smallest area
+fn find_node_by_area(
+    state: &State,
+    reference: &Coordinates,
+    peak: AreaPeak,
+    sel: &NodeSelect,
+) -> Option<Coordinates> {
+    let mut best: Option<(u32, Coordinates)> = None;
+
+    for monitor in state.monitors() {
+        for desktop in monitor.desktops() {
+            let tree = desktop.tree();
+            for id in tree.leaves() {
+                let loc = Coordinates::new(monitor.id(), desktop.id(), Some(id));
+                if tree.get(id).map_or(true, Node::is_vacant) || !sel.matches(state, &loc, reference)
+                {
+                    continue;
+                }
+
+                let area = state.rectangle(&loc).map_or(0, |r| r.area());
+                let better = best.map_or(true, |(a, _)| match peak {
+                    AreaPeak::Biggest => area > a,
+                    AreaPeak::Smallest => area < a,
+                });
+
+                if better {
+                    best = Some((area, loc));
+                }
+            }
+        }
+    }
+
+    best.map(|(_, loc)| loc)
+}
+
+/// Find any leaf matching the modifiers, preferring the reference
+/// [`Desktop`](crate::tree::Desktop)
+fn find_any_node(state: &State, reference: &Coordinates, sel: &NodeSelect) -> Option<Coordinates> {
+    let leaves = |monitor: Xid, desktop: &Desktop| {
+        let id = desktop.id();
+        desktop
+            .tree()
+            .leaves()
+            .map(move |n| Coordinates::new(monitor, id, Some(n)))
+            .collect::<Vec<_>>()
+    };
+
+    let local = state
+        .desktop(reference.desktop)
+        .map(|(m, d)| leaves(m.id(), d))
+        .unwrap_or_default();
+    let others = state.monitors().iter().flat_map(|m| {
+        m.desktops()
+            .iter()
+            .filter(|d| d.id() != reference.desktop)
+            .flat_map(move |d| leaves(m.id(), d))
+    });
+
+    local
+        .into_iter()
+        .chain(others)
+        .find(|loc| sel.matches(state, loc, reference))
+}
+
+/// Return the items following `start` in the given [`CycleDir`], wrapping
+/// around and stopping before `start`
+fn cycle<T: Copy>(items: &[T], start: usize, dir: CycleDir) -> impl Iterator<Item = T> + '_ {
+    let len = items.len();
+    (1..len).map(move |step| match dir {
+        CycleDir::Next => items[(start + step) % len],
+        CycleDir::Prev => items[(start + len - step) % len],
+    })
+}
+
+/// Parse the 1-based index of a `^N` descriptor
+fn parse_index(s: &str) -> Option<usize> {
+    s.parse::<usize>().ok().filter(|&n| n > 0).map(|n| n - 1)
+}
+
+// ========================== Desktop Select ==========================
+
 /// Query information about the given [`Desktop`]
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub(crate) struct DesktopSelect {
     occupied:     Option<bool>,
     focused:      Option<bool>,
@@ -47,9 +627,1177 @@ pub(crate) struct DesktopSelect {
     user_monocle: Option<bool>,
 }
 
+impl DesktopSelect {
+    /// Parse modifiers in the form of `.occupied.!urgent`, returning the first
+    /// invalid modifier on failure
+    pub(crate) fn parse(modifiers: &str) -> Result<Self, String> {
+        let mut sel = Self::default();
+
+        for modifier in modifiers.split('.').skip(1) {
+            let (name, value) = modifier
+                .strip_prefix('!')
+                .map_or((modifier, true), |name| (name, false));
+
+            let field = match name {
+                "occupied" => &mut sel.occupied,
+                "focused" => &mut sel.focused,
+                "active" => &mut sel.active,
+                "urgent" => &mut sel.urgent,
+                "local" => &mut sel.local,
+                "tiled" => &mut sel.tiled,
+                "monocle" => &mut sel.monocle,
+                "user_tiled" => &mut sel.user_tiled,
+                "user_monocle" => &mut sel.user_monocle,
+                _ => return Err(modifier.to_owned()),
+            };
+            *field = Some(value);
+        }
+
+        Ok(sel)
+    }
+
+    /// Does the [`Desktop`] at `loc` satisfy every modifier?
+    pub(crate) fn matches(&self, state: &State, loc: &Coordinates, reference: &Coordinates) -> bool {
+        let (monitor, desktop) = match state.desktop(loc.desktop) {
+            Some(found) => found,
+            None => return false,
+        };
+
+        check(self.occupied, desktop.is_occupied())
+            && check(
+                self.focused,
+                state.focused().map_or(false, |f| f.desktop == loc.desktop),
+            )
+            && check(self.active, monitor.desk_id() == Some(loc.desktop))
+            && check(self.urgent, desktop.is_urgent())
+            && check(self.local, loc.monitor == reference.monitor)
+            && check(self.tiled, desktop.layout() == LayoutType::Tiled)
+            && check(self.monocle, desktop.layout() == LayoutType::Monocle)
+            && check(self.user_tiled, desktop.user_layout() == LayoutType::Tiled)
+            && check(self.user_monocle, desktop.user_layout() == LayoutType::Monocle)
+    }
+}
+
+// ========================= Desktop Selector =========================
+
+/// Resolve a desktop selector into the [`Coordinates`] of a [`Desktop`],
+/// pointing to its focused [`Node`]
+///
+/// ```text
+/// DESKTOP_SEL := (CYCLE_DIR|any|last|newest|older|newer|
+///                [MONITOR_SEL:](focused|^<n>)|<desktop_id>|<desktop_name>)[.[!]MODIFIER]*
+/// ```
+///
+/// `^<n>` is the 1-based index of the [`Desktop`], counted across every
+/// [`Monitor`] unless scoped to one. Modifiers are the fields of
+/// [`DesktopSelect`]
+pub(crate) fn desktop_from_desc(
+    desc: &str,
+    state: &State,
+    reference: &Coordinates,
+) -> Result<Coordinates, SelectorError> {
+    let (head, modifiers) = split_modifiers(desc);
+    let sel = DesktopSelect::parse(modifiers).map_err(|modifier| {
+        SelectorError::InvalidModifier {
+            kind: "desktop",
+            selector: desc.to_owned(),
+            modifier,
+        }
+    })?;
+    let matches = |loc: &Coordinates| sel.matches(state, loc, reference);
+    let invalid = || SelectorError::InvalidDescriptor {
+        kind:     "desktop",
+        selector: desc.to_owned(),
+        desc:     head.to_owned(),
+    };
+
+    // Every desktop, in order
+    let all = state
+        .monitors()
+        .iter()
+        .flat_map(|m| {
+            m.desktops()
+                .iter()
+                .map(move |d| Coordinates::new(m.id(), d.id(), d.focus()))
+        })
+        .collect::<Vec<_>>();
+
+    let found = if let Some((mon_desc, head)) = head.rsplit_once(':') {
+        let monitor = monitor_from_desc(mon_desc, state, reference)?;
+        let scoped = all
+            .iter()
+            .filter(|loc| loc.monitor == monitor)
+            .copied()
+            .collect::<Vec<_>>();
+
+        let found = match (head, head.strip_prefix('^')) {
+            ("focused", _) => state
+                .monitor(monitor)
+                .and_then(Monitor::desk_id)
+                .and_then(|id| state.locate_desktop(id)),
+            (_, Some(n)) => scoped.get(parse_index(n).ok_or_else(invalid)?).copied(),
+            _ => return Err(invalid()),
+        };
+        found.filter(matches)
+    } else if let Ok(dir) = CycleDir::from_str(head) {
+        all.iter()
+            .position(|loc| loc.desktop == reference.desktop)
+            .and_then(|start| cycle(&all, start, dir).find(matches))
+    } else if let Ok(dir) = HistoryDir::from_str(head) {
+        find_desktop_in_history(state, reference, dir, &sel)
+    } else if let Some(n) = head.strip_prefix('^') {
+        all.get(parse_index(n).ok_or_else(invalid)?)
+            .copied()
+            .filter(matches)
+    } else {
+        match head {
+            "focused" => state
+                .focused()
+                .and_then(|loc| state.locate_desktop(loc.desktop))
+                .filter(matches),
+            "last" => find_desktop_in_history(state, reference, HistoryDir::Older, &sel),
+            "newest" => state
+                .history()
+                .find_newest(|loc| matches(loc))
+                .and_then(|loc| state.locate_desktop(loc.desktop)),
+            "any" => all
+                .iter()
+                .filter(|loc| loc.monitor == reference.monitor)
+                .chain(all.iter().filter(|loc| loc.monitor != reference.monitor))
+                .copied()
+                .find(matches),
+            _ => parse_id(head)
+                .and_then(|id| state.locate_desktop(id))
+                .or_else(|| {
+                    state.monitors().iter().find_map(|m| {
+                        m.desktops()
+                            .iter()
+                            .find(|d| d.name() == head)
+                            .map(|d| Coordinates::new(m.id(), d.id(), d.focus()))
+                    })
+                })
+                .filter(matches),
+        }
+    };
+
+    found.ok_or_else(|| SelectorError::NoMatch {
+        kind:     "desktop",
+        selector: desc.to_owned(),
+    })
+}
+
+/// Find a [`Desktop`] other than the reference in the focus history
+fn find_desktop_in_history(
+    state: &State,
+    reference: &Coordinates,
+    dir: HistoryDir,
+    sel: &DesktopSelect,
+) -> Option<Coordinates> {
+    state
+        .history()
+        .find(dir, |loc| {
+            loc.desktop != reference.desktop && sel.matches(state, loc, reference)
+        })
+        .and_then(|loc| state.locate_desktop(loc.desktop))
+}
+
+// ========================== Monitor Select ==========================
+
 /// Query information about the given [`Monitor`]
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub(crate) struct MonitorSelect {
     occupied: Option<bool>,
     focused:  Option<bool>,
 }
+
+impl MonitorSelect {
+    /// Parse modifiers in the form of `.occupied.!focused`, returning the first
+    /// invalid modifier on failure
+    pub(crate) fn parse(modifiers: &str) -> Result<Self, String> {
+        let mut sel = Self::default();
+
+        for modifier in modifiers.split('.').skip(1) {
+            let (name, value) = modifier
+                .strip_prefix('!')
+                .map_or((modifier, true), |name| (name, false));
+
+            let field = match name {
+                "occupied" => &mut sel.occupied,
+                "focused" => &mut sel.focused,
+                _ => return Err(modifier.to_owned()),
+            };
+            *field = Some(value);
+        }
+
+        Ok(sel)
+    }
+
+    /// Does the [`Monitor`] with the given ID satisfy every modifier?
+    pub(crate) fn matches(&self, state: &State, id: Xid) -> bool {
+        state.monitor(id).map_or(false, |monitor| {
+            check(self.occupied, monitor.is_occupied())
+                && check(
+                    self.focused,
+                    state.focused_monitor().map_or(false, |m| m.id() == id),
+                )
+        })
+    }
+}
+
+// ========================= Monitor Selector =========================
+
+/// Resolve a monitor selector into the ID of a [`Monitor`]
+///
+/// ```text
+/// MONITOR_SEL := (DIR|CYCLE_DIR|any|last|newest|older|newer|focused|^<n>|
+///                <monitor_id>|<monitor_name>)[.[!]MODIFIER]*
+/// ```
+///
+/// Modifiers are the fields of [`MonitorSelect`]
+pub(crate) fn monitor_from_desc(
+    desc: &str,
+    state: &State,
+    reference: &Coordinates,
+) -> Result<Xid, SelectorError> {
+    let (head, modifiers) = split_modifiers(desc);
+    let sel = MonitorSelect::parse(modifiers).map_err(|modifier| {
+        SelectorError::InvalidModifier {
+            kind: "monitor",
+            selector: desc.to_owned(),
+            modifier,
+        }
+    })?;
+    let matches = |id: &Xid| sel.matches(state, *id);
+
+    let all = state.monitors().iter().map(Monitor::id).collect::<Vec<_>>();
+
+    let found = if let Ok(dir) = Direction::from_str(head) {
+        find_nearest_monitor(state, reference.monitor, dir, &sel)
+    } else if let Ok(dir) = CycleDir::from_str(head) {
+        all.iter()
+            .position(|&id| id == reference.monitor)
+            .and_then(|start| cycle(&all, start, dir).find(matches))
+    } else if let Ok(dir) = HistoryDir::from_str(head) {
+        find_monitor_in_history(state, reference, dir, &sel)
+    } else if let Some(n) = head.strip_prefix('^') {
+        let idx = parse_index(n).ok_or_else(|| SelectorError::InvalidDescriptor {
+            kind:     "monitor",
+            selector: desc.to_owned(),
+            desc:     head.to_owned(),
+        })?;
+        all.get(idx).copied().filter(matches)
+    } else {
+        match head {
+            "focused" => state.focused_monitor().map(Monitor::id).filter(matches),
+            "last" => find_monitor_in_history(state, reference, HistoryDir::Older, &sel),
+            "newest" => state
+                .history()
+                .find_newest(|loc| matches(&loc.monitor))
+                .map(|loc| loc.monitor),
+            "any" => all.iter().copied().find(matches),
+            _ => parse_id(head)
+                .filter(|&id| state.monitor(id).is_some())
+                .or_else(|| {
+                    state
+                        .monitors()
+                        .iter()
+                        .find(|m| m.name() == head)
+                        .map(Monitor::id)
+                })
+                .filter(matches),
+        }
+    };
+
+    found.ok_or_else(|| SelectorError::NoMatch {
+        kind:     "monitor",
+        selector: desc.to_owned(),
+    })
+}
+
+/// Find the closest [`Monitor`] in the given [`Direction`]
+fn find_nearest_monitor(
+    state: &State,
+    reference: Xid,
+    dir: Direction,
+    sel: &MonitorSelect,
+) -> Option<Xid> {
+    let rect = state.monitor(reference)?.rectangle();
+
+    state
+        .monitors()
+        .iter()
+        .filter(|m| m.id() != reference)
+        .filter(|m| rect.on_dir_side(m.rectangle(), dir, state.tightness()))
+        .filter(|m| sel.matches(state, m.id()))
+        .min_by_key(|m| rect.boundary_distance(m.rectangle(), dir))
+        .map(Monitor::id)
+}
+
+/// Find a [`Monitor`] other than the reference in the focus history
+fn find_monitor_in_history(
+    state: &State,
+    reference: &Coordinates,
+    dir: HistoryDir,
+    sel: &MonitorSelect,
+) -> Option<Xid> {
+    state
+        .history()
+        .find(dir, |loc| {
+            loc.monitor != reference.monitor && sel.matches(state, loc.monitor)
+        })
+        .map(|loc| loc.monitor)
+}
+
+// =============================== Scope ==============================
+
+/// Restricts a query to a [`Monitor`], a [`Desktop`], or a [`Node`] and its
+/// descendants. Every given restriction must be satisfied
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct QueryScope {
+    monitor: Option<Xid>,
+    desktop: Option<Coordinates>,
+    node:    Option<Coordinates>,
+}
+
+impl QueryScope {
+    /// Resolve the selectors of the [`Monitor`], [`Desktop`] and [`Node`]
+    /// restricting a query
+    pub(crate) fn new(
+        state: &State,
+        reference: &Coordinates,
+        monitor: Option<&str>,
+        desktop: Option<&str>,
+        node: Option<&str>,
+    ) -> Result<Self, SelectorError> {
+        Ok(Self {
+            monitor: monitor
+                .map(|desc| monitor_from_desc(desc, state, reference))
+                .transpose()?,
+            desktop: desktop
+                .map(|desc| desktop_from_desc(desc, state, reference))
+                .transpose()?,
+            node:    node
+                .map(|desc| node_from_desc(desc, state, reference))
+                .transpose()?,
+        })
+    }
+
+    /// Is the [`Monitor`] with the given ID within the scope?
+    fn contains_monitor(&self, id: Xid) -> bool {
+        self.monitor.map_or(true, |m| m == id)
+            && self.desktop.map_or(true, |d| d.monitor == id)
+            && self.node.map_or(true, |n| n.monitor == id)
+    }
+
+    /// Is the [`Desktop`] at the given [`Coordinates`] within the scope?
+    fn contains_desktop(&self, loc: &Coordinates) -> bool {
+        self.contains_monitor(loc.monitor)
+            && self.desktop.map_or(true, |d| d.desktop == loc.desktop)
+            && self.node.map_or(true, |n| n.desktop == loc.desktop)
+    }
+
+    /// Is the [`Node`] at the given [`Coordinates`] within the scope?
+    fn contains_node(&self, state: &State, loc: &Coordinates) -> bool {
+        self.contains_desktop(loc)
+            && self.node.map_or(true, |scope| {
+                match (state.desktop(loc.desktop), loc.node, scope.node) {
+                    (Some((_, desktop)), Some(id), Some(ancestor)) =>
+                        desktop.tree().is_descendant(id, ancestor),
+                    _ => false,
+                }
+            })
+    }
+}
+
+// ============================== Listing =============================
+
+/// Split the filter of a query into a selector resolved to a single item, or
+/// modifiers applied to every item
+fn split_filter(filter: Option<&str>) -> (Option<&str>, &str) {
+    match filter {
+        Some(modifiers) if modifiers.starts_with('.') => (None, modifiers),
+        Some(desc) => (Some(desc), ""),
+        None => (None, ""),
+    }
+}
+
+/// Join the lines listed by a query, failing if there are none
+fn listed(
+    lines: Vec<String>,
+    kind: &'static str,
+    filter: Option<&str>,
+) -> Result<String, SelectorError> {
+    if lines.is_empty() {
+        return Err(SelectorError::NoMatch {
+            kind,
+            selector: filter.unwrap_or_default().to_owned(),
+        });
+    }
+
+    Ok(lines.join("\n"))
+}
+
+/// List the IDs of the [`Node`]s within the scope that match the filter, in
+/// pre-order
+///
+/// The filter is either a node selector, which lists at most one [`Node`], or
+/// modifiers like `.leaf.!floating` checked against every [`Node`]
+pub(crate) fn query_nodes(
+    state: &State,
+    reference: &Coordinates,
+    scope: &QueryScope,
+    filter: Option<&str>,
+) -> Result<String, SelectorError> {
+    let (desc, modifiers) = split_filter(filter);
+    let only = desc
+        .map(|desc| node_from_desc(desc, state, reference))
+        .transpose()?;
+    let sel = NodeSelect::parse(modifiers).map_err(|modifier| SelectorError::InvalidModifier {
+        kind: "node",
+        selector: modifiers.to_owned(),
+        modifier,
+    })?;
+
+    let mut lines = vec![];
+    for monitor in state.monitors() {
+        for desktop in monitor.desktops() {
+            let tree = desktop.tree();
+            for id in tree.root().into_iter().flat_map(|root| tree.descendants(root)) {
+                let loc = Coordinates::new(monitor.id(), desktop.id(), Some(id));
+                if scope.contains_node(state, &loc)
+                    && only.map_or(true, |o| o == loc)
+                    && sel.matches(state, &loc, reference)
+                {
+                    lines.extend(tree.get(id).map(|n| format!("0x{:08X}", n.id())));
+                }
+            }
+        }
+    }
+
+    listed(lines, "node", filter)
+}
+
+/// List the [`Desktop`]s within the scope that match the filter, by ID or by
+/// name. The filter works like the one of [`query_nodes`]
+pub(crate) fn query_desktops(
+    state: &State,
+    reference: &Coordinates,
+    scope: &QueryScope,
+    filter: Option<&str>,
+    names: bool,
+) -> Result<String, SelectorError> {
+    let (desc, modifiers) = split_filter(filter);
+    let only = desc
+        .map(|desc| desktop_from_desc(desc, state, reference))
+        .transpose()?;
+    let sel = DesktopSelect::parse(modifiers).map_err(|modifier| {
+        SelectorError::InvalidModifier {
+            kind: "desktop",
+            selector: modifiers.to_owned(),
+            modifier,
+        }
+    })?;
+
+    let lines = state
+        .monitors()
+        .iter()
+        .flat_map(|m| {
+            m.desktops()
+                .iter()
+                .map(move |d| (Coordinates::new(m.id(), d.id(), d.focus()), d))
+        })
+        .filter(|(loc, _)| {
+            scope.contains_desktop(loc)
+                && only.map_or(true, |o| o.desktop == loc.desktop)
+                && sel.matches(state, loc, reference)
+        })
+        .map(|(_, d)| t!(names ? d.name().to_owned() : format!("0x{:08X}", d.id())))
+        .collect();
+
+    listed(lines, "desktop", filter)
+}
+
+/// List the [`Monitor`]s within the scope that match the filter, by ID or by
+/// name. The filter works like the one of [`query_nodes`]
+pub(crate) fn query_monitors(
+    state: &State,
+    reference: &Coordinates,
+    scope: &QueryScope,
+    filter: Option<&str>,
+    names: bool,
+) -> Result<String, SelectorError> {
+    let (desc, modifiers) = split_filter(filter);
+    let only = desc
+        .map(|desc| monitor_from_desc(desc, state, reference))
+        .transpose()?;
+    let sel = MonitorSelect::parse(modifiers).map_err(|modifier| {
+        SelectorError::InvalidModifier {
+            kind: "monitor",
+            selector: modifiers.to_owned(),
+            modifier,
+        }
+    })?;
+
+    let lines = state
+        .monitors()
+        .iter()
+        .filter(|m| {
+            scope.contains_monitor(m.id())
+                && only.map_or(true, |o| o == m.id())
+                && sel.matches(state, m.id())
+        })
+        .map(|m| t!(names ? m.name().to_owned() : format!("0x{:08X}", m.id())))
+        .collect();
+
+    listed(lines, "monitor", filter)
+}
+
+// =============================== JSON ===============================
+
+/// A [`Node`] and its descendants, as printed by [`query_tree`]
+#[derive(Debug, Serialize)]
+struct NodeJson<'a> {
+    id:           Xid,
+    split_type:   SplitType,
+    split_ratio:  f64,
+    vacant:       bool,
+    hidden:       bool,
+    sticky:       bool,
+    private:      bool,
+    locked:       bool,
+    marked:       bool,
+    presel:       Option<Presel>,
+    rectangle:    Rectangle,
+    first_child:  Option<Box<NodeJson<'a>>>,
+    second_child: Option<Box<NodeJson<'a>>>,
+    client:       Option<&'a Client>,
+}
+
+impl<'a> NodeJson<'a> {
+    /// Describe the [`Node`] with the given ID and its descendants
+    fn new(tree: &'a Tree, id: NodeId) -> Option<Self> {
+        let node = tree.get(id)?;
+        let child = |child: Option<NodeId>| child.and_then(|c| Self::new(tree, c)).map(Box::new);
+
+        Some(Self {
+            id:           node.id(),
+            split_type:   node.split_type(),
+            split_ratio:  node.split_ratio(),
+            vacant:       node.is_vacant(),
+            hidden:       node.is_hidden(),
+            sticky:       node.is_sticky(),
+            private:      node.is_private(),
+            locked:       node.is_locked(),
+            marked:       node.is_marked(),
+            presel:       node.presel(),
+            rectangle:    node.rectangle(),
+            first_child:  child(node.first_child()),
+            second_child: child(node.second_child()),
+            client:       node.client(),
+        })
+    }
+}
+
+/// A [`Desktop`] and its tree, as printed by [`query_tree`]
+#[derive(Debug, Serialize)]
+struct DesktopJson<'a> {
+    name:            &'a str,
+    id:              Xid,
+    layout:          LayoutType,
+    user_layout:     LayoutType,
+    padding:         Padding,
+    window_gap:      isize,
+    border_width:    usize,
+    focused_node_id: Xid,
+    root:            Option<NodeJson<'a>>,
+}
+
+impl<'a> DesktopJson<'a> {
+    /// Describe a [`Desktop`]
+    fn new(desktop: &'a Desktop) -> Self {
+        let tree = desktop.tree();
+        Self {
+            name:            desktop.name(),
+            id:              desktop.id(),
+            layout:          desktop.layout(),
+            user_layout:     desktop.user_layout(),
+            padding:         desktop.padding(),
+            window_gap:      desktop.window_gap(),
+            border_width:    desktop.border_width(),
+            focused_node_id: desktop
+                .focus()
+                .and_then(|f| tree.get(f))
+                .map_or(0, Node::id),
+            root:            tree.root().and_then(|root| NodeJson::new(tree, root)),
+        }
+    }
+}
+
+/// A [`Monitor`] and its [`Desktop`]s, as printed by [`query_tree`]
+#[derive(Debug, Serialize)]
+struct MonitorJson<'a> {
+    name:               &'a str,
+    id:                 Xid,
+    randr_id:           Output,
+    wired:              bool,
+    sticky_count:       usize,
+    padding:            Padding,
+    window_gap:         isize,
+    border_width:       usize,
+    rectangle:          Rectangle,
+    focused_desktop_id: Xid,
+    desktops:           Vec<DesktopJson<'a>>,
+}
+
+impl<'a> MonitorJson<'a> {
+    /// Describe a [`Monitor`]
+    fn new(monitor: &'a Monitor) -> Self {
+        Self {
+            name:               monitor.name(),
+            id:                 monitor.id(),
+            randr_id:           monitor.randr_id(),
+            wired:              monitor.is_wired(),
+            sticky_count:       monitor.sticky_count(),
+            padding:            monitor.padding(),
+            window_gap:         monitor.window_gap(),
+            border_width:       monitor.border_width(),
+            rectangle:          monitor.rectangle(),
+            focused_desktop_id: monitor.desk_id().unwrap_or_default(),
+            desktops:           monitor.desktops().iter().map(DesktopJson::new).collect(),
+        }
+    }
+}
+
+/// An entry of the focus history, as printed by [`query_tree`]
+#[derive(Debug, Serialize)]
+struct HistoryJson {
+    monitor_id: Xid,
+    desktop_id: Xid,
+    node_id:    Xid,
+}
+
+/// Every [`Monitor`] and the focus history, as printed by [`query_tree`]
+#[derive(Debug, Serialize)]
+struct StateJson<'a> {
+    focused_monitor_id: Xid,
+    monitors:           Vec<MonitorJson<'a>>,
+    focus_history:      Vec<HistoryJson>,
+}
+
+impl<'a> StateJson<'a> {
+    /// Describe the whole [`State`]
+    fn new(state: &'a State) -> Self {
+        Self {
+            focused_monitor_id: state.focused_monitor().map_or(0, Monitor::id),
+            monitors:           state.monitors().iter().map(MonitorJson::new).collect(),
+            focus_history:      state
+                .history()
+                .iter()
+                .map(|loc| HistoryJson {
+                    monitor_id: loc.monitor,
+                    desktop_id: loc.desktop,
+                    node_id:    state.node(loc).map_or(0, Node::id),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Describe the most specific item of the scope as JSON, or the whole
+/// [`State`] if the scope is empty
+///
+/// Every object uses `snake_case` keys. Children of a [`Node`] are nested in
+/// `first_child` and `second_child`, and every ID is the one of the X-Server
+pub(crate) fn query_tree(state: &State, scope: &QueryScope) -> Result<String> {
+    let json = if let Some(loc) = scope.node {
+        let (_, desktop) = state.desktop(loc.desktop).context("the desktop does not exist")?;
+        let node = loc
+            .node
+            .and_then(|id| NodeJson::new(desktop.tree(), id))
+            .context("the node does not exist")?;
+        serde_json::to_string(&node)
+    } else if let Some(loc) = scope.desktop {
+        let (_, desktop) = state.desktop(loc.desktop).context("the desktop does not exist")?;
+        serde_json::to_string(&DesktopJson::new(desktop))
+    } else if let Some(id) = scope.monitor {
+        let monitor = state.monitor(id).context("the monitor does not exist")?;
+        serde_json::to_string(&MonitorJson::new(monitor))
+    } else {
+        serde_json::to_string(&StateJson::new(state))
+    };
+
+    json.context("failed to serialize the tree into json format")
+}
+
+// ============================== History =============================
+
+/// List the focus [`History`](crate::tree::History), from newest to oldest
+///
+/// Each line holds the IDs of the [`Monitor`], [`Desktop`] and [`Node`]. The
+/// [`Node`] is `0x00000000` when only the [`Desktop`] was focused
+pub(crate) fn query_history(state: &State) -> String {
+    state
+        .history()
+        .iter()
+        .map(|loc| {
+            let node = state.node(loc).map_or(0, Node::id);
+            format!("0x{:08X} 0x{:08X} 0x{:08X}", loc.monitor, loc.desktop, node)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Tests for the selectors and the queries built on them
+#[cfg(test)]
+mod tests {
+    use super::{
+        desktop_from_desc,
+        jump_in_history,
+        monitor_from_desc,
+        node_from_desc,
+        query_desktops,
+        query_history,
+        query_monitors,
+        query_nodes,
+        query_tree,
+        QueryScope,
+        SelectorError,
+    };
+    use crate::{
+        core::{ChildPolarity, LayoutType, Tightness, Xid},
+        geometry::{Padding, Rectangle},
+        layout::{arrange, LayoutParams},
+        monitor::client::ClientState,
+        state::State,
+        test_utils::leaf,
+        tree::{Coordinates, Desktop, Monitor, Node, SplitType},
+    };
+
+    const MONITOR: Rectangle = Rectangle::new(0, 0, 1920, 1080);
+
+    /// Create an internal node
+    fn internal(id: u32, split_type: SplitType) -> Node {
+        let mut node = Node::new(id, None, 0.5);
+        node.set_type(split_type);
+        node
+    }
+
+    /// Build a [`Monitor`] with two [`Desktop`]s. The focused one holds the
+    /// tree `(1 (2 3))`, split vertically then horizontally, with `2` focused.
+    /// The other one holds the single window `4`
+    ///
+    /// A second [`Monitor`] on the right holds a single empty [`Desktop`]
+    fn state() -> State {
+        let params = LayoutParams::new(LayoutType::Tiled, MONITOR, Padding::default(), 0, 0);
+
+        let mut first = Desktop::new(String::from("one"), 10, Padding::default(), 0, 0);
+        let tree = first.tree_mut();
+        let a = tree.insert_root(leaf(1)).unwrap();
+        let b = tree
+            .insert_at(a, leaf(2), internal(100, SplitType::Vertical), ChildPolarity::Second)
+            .unwrap();
+        let c = tree
+            .insert_at(b, leaf(3), internal(101, SplitType::Horizontal), ChildPolarity::Second)
+            .unwrap();
+        arrange(tree, &params);
+        first.set_focus(Some(b));
+
+        let mut second = Desktop::new(String::from("two"), 11, Padding::default(), 0, 0);
+        let d = second.tree_mut().insert_root(leaf(4)).unwrap();
+        arrange(second.tree_mut(), &params);
+
+        let mut monitor = Monitor::new(String::from("HDMI-0"), 1, 0, MONITOR);
+        monitor.add_desktop(first);
+        monitor.add_desktop(second);
+
+        let mut right = Monitor::new(
+            String::from("DP-1"),
+            2,
+            0,
+            Rectangle::new(1920, 0, 1920, 1080),
+        );
+        right.add_desktop(Desktop::new(String::from("three"), 12, Padding::default(), 0, 0));
+
+        let mut state = State::new(Tightness::High);
+        state.add_monitor(monitor);
+        state.add_monitor(right);
+
+        let history = state.history_mut();
+        history.add(Coordinates::new(2, 12, None));
+        history.add(Coordinates::new(1, 11, Some(d)));
+        for &node in &[a, c, b] {
+            history.add(Coordinates::new(1, 10, Some(node)));
+        }
+        state
+    }
+
+    /// Resolve a selector from the focused [`Node`], returning the ID of the
+    /// selected [`Node`]
+    fn select(state: &State, desc: &str) -> Result<Xid, SelectorError> {
+        let reference = state.focused().unwrap();
+        node_from_desc(desc, state, &reference).map(|loc| state.node(&loc).unwrap().id())
+    }
+
+    /// Jump through the history from the focused [`Node`] and focus the
+    /// selected one, like the window manager does, returning its ID
+    fn jump(state: &mut State, desc: &str) -> Result<Xid, SelectorError> {
+        let reference = state.focused().unwrap();
+        let loc = jump_in_history(desc, state, &reference)?;
+        state.desktop_mut(loc.desktop).unwrap().set_focus(loc.node);
+        Ok(state.node(&loc).unwrap().id())
+    }
+
+    /// Resolve a desktop selector from the focused [`Desktop`]
+    fn select_desktop(state: &State, desc: &str) -> Result<Xid, SelectorError> {
+        let reference = state.focused().unwrap();
+        desktop_from_desc(desc, state, &reference).map(|loc| loc.desktop)
+    }
+
+    /// Resolve the scope of a query from the focused [`Node`]
+    fn scope(
+        state: &State,
+        monitor: Option<&str>,
+        desktop: Option<&str>,
+        node: Option<&str>,
+    ) -> QueryScope {
+        let reference = state.focused().unwrap();
+        QueryScope::new(state, &reference, monitor, desktop, node).unwrap()
+    }
+
+    /// Resolve a monitor selector from the focused [`Monitor`]
+    fn select_monitor(state: &State, desc: &str) -> Result<Xid, SelectorError> {
+        let reference = state.focused().unwrap();
+        monitor_from_desc(desc, state, &reference)
+    }
+
+    #[test]
+    fn focused_and_ids() {
+        let state = state();
+        assert_eq!(select(&state, "focused"), Ok(2));
+        assert_eq!(select(&state, "0x3"), Ok(3));
+        assert_eq!(select(&state, "4"), Ok(4));
+        assert_eq!(select(&state, "0x99"), Err(SelectorError::NoMatch {
+            kind:     "node",
+            selector: String::from("0x99"),
+        }));
+    }
+
+    #[test]
+    fn directions() {
+        let state = state();
+        assert_eq!(select(&state, "west"), Ok(1));
+        assert_eq!(select(&state, "south"), Ok(3));
+        assert!(select(&state, "north").is_err());
+        assert!(select(&state, "east").is_err());
+    }
+
+    #[test]
+    fn cycle() {
+        let state = state();
+        assert_eq!(select(&state, "next"), Ok(101));
+        assert_eq!(select(&state, "next.leaf"), Ok(3));
+        assert_eq!(select(&state, "prev.leaf"), Ok(1));
+        assert_eq!(select(&state, "0x3#next.leaf"), Ok(4));
+        assert_eq!(select(&state, "0x3#next.leaf.local"), Ok(1));
+
+        let mut state = state;
+        let loc = state.locate_window(3).unwrap();
+        state.node_mut(&loc).unwrap().set_hidden(true);
+        assert_eq!(select(&state, "next.leaf"), Ok(4));
+        assert_eq!(select(&state, "next.leaf.hidden"), Ok(3));
+    }
+
+    #[test]
+    fn area() {
+        let state = state();
+        assert_eq!(select(&state, "biggest"), Ok(4));
+        assert_eq!(select(&state, "biggest.local"), Ok(1));
+        assert_eq!(select(&state, "smallest"), Ok(2));
+    }
+
+    #[test]
+    fn paths() {
+        let state = state();
+        assert_eq!(select(&state, "@/1"), Ok(1));
+        assert_eq!(select(&state, "@/second/2"), Ok(3));
+        assert_eq!(select(&state, "@parent"), Ok(101));
+        assert_eq!(select(&state, "@brother"), Ok(3));
+        assert_eq!(select(&state, "@/2/1/west"), Ok(100));
+        assert_eq!(select(&state, "@/3"), Err(SelectorError::InvalidJump {
+            path: String::from("/3"),
+            jump: String::from("3"),
+        }));
+    }
+
+    #[test]
+    fn history() {
+        let state = state();
+        assert_eq!(select(&state, "last"), Ok(3));
+        assert_eq!(select(&state, "older"), Ok(3));
+        assert_eq!(select(&state, "newest"), Ok(2));
+        assert_eq!(select(&state, "last.!local"), Ok(4));
+        assert_eq!(select(&state, "last.!local.floating"), Err(SelectorError::NoMatch {
+            kind:     "node",
+            selector: String::from("last.!local.floating"),
+        }));
+    }
+
+    #[test]
+    fn history_jumps() {
+        let mut state = state();
+        assert_eq!(jump(&mut state, "older"), Ok(3));
+        assert_eq!(jump(&mut state, "older"), Ok(1));
+        assert_eq!(jump(&mut state, "older.local"), Err(SelectorError::NoMatch {
+            kind:     "node",
+            selector: String::from("older.local"),
+        }));
+        assert_eq!(jump(&mut state, "newer"), Ok(3));
+        assert_eq!(jump(&mut state, "newer.local"), Ok(2));
+        assert!(matches!(
+            jump(&mut state, "last"),
+            Err(SelectorError::InvalidDescriptor { .. })
+        ));
+
+        // The needle is reset once a location is recorded
+        state.history_mut().add(state.focused().unwrap());
+        assert_eq!(jump(&mut state, "older"), Ok(3));
+    }
+
+    #[test]
+    fn modifiers() {
+        let mut state = state();
+        assert_eq!(select(&state, "focused.tiled.!floating"), Ok(2));
+        assert!(select(&state, "focused.floating").is_err());
+        assert_eq!(select(&state, "any.!focused.!local"), Ok(4));
+        assert_eq!(select(&state, "first_ancestor.vertical"), Ok(100));
+
+        let loc = state.locate_node(3).unwrap();
+        let client = state.node_mut(&loc).unwrap().client_mut().unwrap();
+        client.set_state(ClientState::Floating);
+        assert_eq!(select(&state, "any.floating"), Ok(3));
+    }
+
+    #[test]
+    fn paths_on_desktops() {
+        let state = state();
+        assert_eq!(select(&state, "@two:/"), Ok(4));
+        assert_eq!(select(&state, "@^1:/2/1"), Ok(2));
+        assert_eq!(select(&state, "@^1:parent"), Ok(101));
+        assert!(select(&state, "@three:/").is_err());
+    }
+
+    #[test]
+    fn desktops() {
+        let state = state();
+        assert_eq!(select_desktop(&state, "focused"), Ok(10));
+        assert_eq!(select_desktop(&state, "two"), Ok(11));
+        assert_eq!(select_desktop(&state, "0xc"), Ok(12));
+        assert_eq!(select_desktop(&state, "^2"), Ok(11));
+        assert_eq!(select_desktop(&state, "^3"), Ok(12));
+        assert!(select_desktop(&state, "^4").is_err());
+        assert_eq!(select_desktop(&state, "next"), Ok(11));
+        assert_eq!(select_desktop(&state, "prev"), Ok(12));
+        assert_eq!(select_desktop(&state, "prev.occupied"), Ok(11));
+        assert_eq!(select_desktop(&state, "last"), Ok(11));
+        assert_eq!(select_desktop(&state, "any.!occupied"), Ok(12));
+        assert_eq!(select_desktop(&state, "any.!local"), Ok(12));
+        assert_eq!(select_desktop(&state, "focused.tiled.active"), Ok(10));
+        assert!(select_desktop(&state, "focused.monocle").is_err());
+    }
+
+    #[test]
+    fn scoped_desktops() {
+        let state = state();
+        assert_eq!(select_desktop(&state, "DP-1:focused"), Ok(12));
+        assert_eq!(select_desktop(&state, "DP-1:^1"), Ok(12));
+        assert_eq!(select_desktop(&state, "focused:^2"), Ok(11));
+        assert_eq!(select_desktop(&state, "east:focused.!occupied"), Ok(12));
+        assert!(select_desktop(&state, "DP-1:^2").is_err());
+        assert_eq!(
+            select_desktop(&state, "DP-1:two"),
+            Err(SelectorError::InvalidDescriptor {
+                kind:     "desktop",
+                selector: String::from("DP-1:two"),
+                desc:     String::from("DP-1:two"),
+            })
+        );
+    }
+
+    #[test]
+    fn monitors() {
+        let state = state();
+        assert_eq!(select_monitor(&state, "focused"), Ok(1));
+        assert_eq!(select_monitor(&state, "DP-1"), Ok(2));
+        assert_eq!(select_monitor(&state, "0x2"), Ok(2));
+        assert_eq!(select_monitor(&state, "^2"), Ok(2));
+        assert_eq!(select_monitor(&state, "east"), Ok(2));
+        assert!(select_monitor(&state, "west").is_err());
+        assert_eq!(select_monitor(&state, "next"), Ok(2));
+        assert_eq!(select_monitor(&state, "prev"), Ok(2));
+        assert_eq!(select_monitor(&state, "last"), Ok(2));
+        assert_eq!(select_monitor(&state, "any.!occupied"), Ok(2));
+        assert!(select_monitor(&state, "focused.!occupied").is_err());
+        assert_eq!(
+            select_monitor(&state, "focused.empty").unwrap_err().to_string(),
+            "invalid modifier 'empty' in monitor selector 'focused.empty'"
+        );
+    }
+
+    #[test]
+    fn references() {
+        let state = state();
+        assert_eq!(select(&state, "west#east"), Ok(2));
+        assert_eq!(select(&state, "south#west"), Ok(1));
+        assert_eq!(select(&state, "0x4#focused"), Ok(2));
+    }
+
+    #[test]
+    fn errors() {
+        let state = state();
+        let err = select(&state, "nothing").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid descriptor 'nothing' in node selector 'nothing'"
+        );
+
+        let err = select(&state, "focused.tiled.bogus").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid modifier 'bogus' in node selector 'focused.tiled.bogus'"
+        );
+    }
+
+    #[test]
+    fn history_listing() {
+        let mut state = state();
+        assert_eq!(
+            query_history(&state),
+            [
+                "0x00000001 0x0000000A 0x00000002",
+                "0x00000001 0x0000000A 0x00000003",
+                "0x00000001 0x0000000A 0x00000001",
+                "0x00000001 0x0000000B 0x00000004",
+                "0x00000002 0x0000000C 0x00000000",
+            ]
+            .join("\n")
+        );
+
+        let loc = state.locate_node(3).unwrap();
+        state.remove_node(&loc);
+        state.remove_desktop(12);
+        assert_eq!(
+            query_history(&state),
+            [
+                "0x00000001 0x0000000A 0x00000002",
+                "0x00000001 0x0000000A 0x00000001",
+                "0x00000001 0x0000000B 0x00000004",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn node_listing() {
+        let state = state();
+        let reference = state.focused().unwrap();
+        let all = QueryScope::default();
+        let one = scope(&state, None, Some("one"), None);
+
+        assert_eq!(
+            query_nodes(&state, &reference, &one, None).unwrap(),
+            "0x00000064\n0x00000001\n0x00000065\n0x00000002\n0x00000003"
+        );
+        assert_eq!(
+            query_nodes(&state, &reference, &all, Some(".leaf")).unwrap(),
+            "0x00000001\n0x00000002\n0x00000003\n0x00000004"
+        );
+        assert_eq!(
+            query_nodes(&state, &reference, &all, Some("focused")).unwrap(),
+            "0x00000002"
+        );
+
+        let internal = scope(&state, None, None, Some("0x00000065"));
+        assert_eq!(
+            query_nodes(&state, &reference, &internal, None).unwrap(),
+            "0x00000065\n0x00000002\n0x00000003"
+        );
+        assert!(matches!(
+            query_nodes(&state, &reference, &internal, Some(".!leaf.!focused")),
+            Ok(ref ids) if ids == "0x00000065"
+        ));
+
+        let empty = scope(&state, None, Some("three"), None);
+        assert!(matches!(
+            query_nodes(&state, &reference, &empty, None),
+            Err(SelectorError::NoMatch { kind: "node", .. })
+        ));
+        assert!(matches!(
+            query_nodes(&state, &reference, &all, Some(".bogus")),
+            Err(SelectorError::InvalidModifier { .. })
+        ));
+    }
+
+    #[test]
+    fn desktop_and_monitor_listing() {
+        let state = state();
+        let reference = state.focused().unwrap();
+        let all = QueryScope::default();
+
+        let hdmi = scope(&state, Some("HDMI-0"), None, None);
+        assert_eq!(
+            query_desktops(&state, &reference, &hdmi, None, true).unwrap(),
+            "one\ntwo"
+        );
+        assert_eq!(
+            query_desktops(&state, &reference, &all, Some(".occupied"), false).unwrap(),
+            "0x0000000A\n0x0000000B"
+        );
+        assert_eq!(
+            query_desktops(&state, &reference, &all, Some("three"), false).unwrap(),
+            "0x0000000C"
+        );
+
+        let four = scope(&state, None, None, Some("0x00000004"));
+        assert_eq!(query_monitors(&state, &reference, &four, None, true).unwrap(), "HDMI-0");
+        assert_eq!(
+            query_monitors(&state, &reference, &all, None, false).unwrap(),
+            "0x00000001\n0x00000002"
+        );
+        assert!(matches!(
+            query_monitors(&state, &reference, &four, Some("DP-1"), false),
+            Err(SelectorError::NoMatch { kind: "monitor", .. })
+        ));
+    }
+
+    #[test]
+    fn tree_json() {
+        let state = state();
+        let parse = |scope: &QueryScope| -> serde_json::Value {
+            serde_json::from_str(&query_tree(&state, scope).unwrap()).unwrap()
+        };
+
+        let whole = parse(&QueryScope::default());
+        assert_eq!(whole["focused_monitor_id"], 1);
+        assert_eq!(whole["monitors"][1]["name"], "DP-1");
+        assert_eq!(whole["monitors"][1]["desktops"][0]["root"], serde_json::Value::Null);
+        assert_eq!(whole["focus_history"][0]["node_id"], 2);
+        assert_eq!(whole["focus_history"][4]["node_id"], 0);
+
+        let desktop = parse(&scope(&state, None, Some("one"), None));
+        assert_eq!(desktop["name"], "one");
+        assert_eq!(desktop["layout"], "tiled");
+        assert_eq!(desktop["focused_node_id"], 2);
+        assert_eq!(desktop["root"]["id"], 100);
+        assert_eq!(desktop["root"]["first_child"]["client"]["class"], "class");
+        assert_eq!(desktop["root"]["second_child"]["second_child"]["id"], 3);
+
+        let node = parse(&scope(&state, None, None, Some("0x00000065")));
+        assert_eq!(node["id"], 101);
+        assert_eq!(node["first_child"]["id"], 2);
+        assert_eq!(node["first_child"]["first_child"], serde_json::Value::Null);
+
+        let monitor = parse(&scope(&state, Some("DP-1"), None, None));
+        assert_eq!(monitor["focused_desktop_id"], 12);
+        assert_eq!(monitor["rectangle"]["point"]["x"], 1920);
+    }
+}
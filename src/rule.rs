@@ -1,17 +1,143 @@
-//! TODO:
+//! Rules applied to the [`Window`]s that are managed
 
 #![allow(clippy::missing_docs_in_private_items)]
+// The deserializers borrow nothing from their input, so `'de` is only named
+// in their `Deserializer<'de>` bound
+#![allow(single_use_lifetimes)]
 
 use crate::{
-    monitor::client::ClientState,
+    core::{Direction, Window},
     geometry::Rectangle,
+    monitor::client::ClientState,
     stack::StackLayer,
-    core::{Direction, EventQueue, Window},
+    tree::Presel,
+    x::event::XEvent,
 };
 
 use anyhow::{Context, Result};
 use attr_rs::{attr_accessor, attr_reader};
-use serde::{Deserialize, Serialize};
+use nix::fcntl::{fcntl, FcntlArg, OFlag};
+use regex::Regex;
+use serde::{de, Deserialize, Deserializer, Serialize};
+use std::{
+    collections::VecDeque,
+    fmt,
+    io::{ErrorKind, Read},
+    mem,
+    os::unix::io::{AsRawFd, RawFd},
+    process::{Child, Command, Stdio},
+    str::FromStr,
+    thread,
+};
+use tern::t;
+use thiserror::Error;
+
+// ============================== Errors ==============================
+
+/// Errors found while parsing or applying a [`Rule`]
+#[derive(Debug, Error)]
+pub(crate) enum RuleError {
+    #[error("invalid pattern '{pattern}': {source}")]
+    InvalidPattern {
+        pattern: String,
+        source:  regex::Error,
+    },
+    #[error("too many fields in '{0}', expected CLASS[:INSTANCE[:NAME]]")]
+    InvalidRule(String),
+    #[error("invalid effect '{0}', expected key=value")]
+    InvalidEffect(String),
+    #[error("unknown rule key '{0}'")]
+    UnknownKey(String),
+    #[error("invalid value '{value}' for rule key '{key}'")]
+    InvalidValue { key: String, value: String },
+    #[error("no rule matches '{0}'")]
+    NoMatch(String),
+}
+
+// ============================== Matcher =============================
+
+/// A pattern matching a property of a [`Window`]
+///
+/// A pattern surrounded by `/` is a regular expression. Otherwise it is a glob
+/// where `*` matches any text and `?` any character. A leading `!` negates the
+/// pattern
+#[derive(Debug, Clone)]
+pub(crate) struct Matcher {
+    regex:  Regex,
+    negate: bool,
+}
+
+impl Matcher {
+    /// Compile a pattern
+    pub(crate) fn parse(pattern: &str) -> Result<Self, RuleError> {
+        let (negate, body) = pattern
+            .strip_prefix('!')
+            .map_or((false, pattern), |body| (true, body));
+        let source = match body.strip_prefix('/').and_then(|b| b.strip_suffix('/')) {
+            Some(regex) => regex.to_owned(),
+            None => glob_to_regex(body),
+        };
+
+        let regex = Regex::new(&source).map_err(|source| RuleError::InvalidPattern {
+            pattern: pattern.to_owned(),
+            source,
+        })?;
+        Ok(Self { regex, negate })
+    }
+
+    /// Does the text match the pattern?
+    pub(crate) fn is_match(&self, text: &str) -> bool {
+        self.regex.is_match(text) != self.negate
+    }
+}
+
+impl PartialEq for Matcher {
+    fn eq(&self, other: &Self) -> bool {
+        self.negate == other.negate && self.regex.as_str() == other.regex.as_str()
+    }
+}
+
+/// Translate a glob into an anchored regular expression
+fn glob_to_regex(glob: &str) -> String {
+    let mut regex = String::from("^");
+    for c in glob.chars() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            c => regex.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+    regex.push('$');
+    regex
+}
+
+/// Split a pattern in the form of `CLASS:INSTANCE:NAME`. Colons within a
+/// regular expression don't split it
+fn split_pattern(pattern: &str) -> Vec<String> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut in_regex = false;
+    let mut escaped = false;
+
+    for c in pattern.chars() {
+        match c {
+            ':' if !in_regex => fields.push(mem::take(&mut field)),
+            '/' if !in_regex && (field.is_empty() || field == "!") => {
+                in_regex = true;
+                field.push(c);
+            },
+            '/' if in_regex && !escaped => {
+                in_regex = false;
+                field.push(c);
+            },
+            _ => field.push(c),
+        }
+        escaped = in_regex && c == '\\' && !escaped;
+    }
+    fields.push(field);
+
+    fields
+}
 
 // =============================== Rule ===============================
 
@@ -27,19 +153,29 @@ use serde::{Deserialize, Serialize};
 // }
 
 /// A rule for a given [`Client`]
-#[derive(Serialize, Deserialize, Debug, Default)]
+///
+/// The `class`, `instance`, `name`, `window_type` and `role` are [`Matcher`]
+/// patterns, and a missing one matches anything. The `effect` is a list of
+/// `key=value` pairs applied to a [`RuleConsequence`]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
 #[attr_accessor(temp, one_shot)]
-#[attr_reader(class, instance, name)]
+#[attr_reader(class, instance, name, window_type, role, effect)]
 pub(crate) struct Rule {
-    class:    Option<String>,
-    instance: Option<String>,
-    name:     Option<String>,
-    floating: Option<bool>,
-    size:     Option<(u16, u16)>,
-    pos:      Option<(i16, i16)>,
-    effect:   String,
-    one_shot: bool,
-    temp:     bool,
+    class:       Option<String>,
+    instance:    Option<String>,
+    name:        Option<String>,
+    window_type: Option<String>,
+    role:        Option<String>,
+    floating:    Option<bool>,
+    size:        Option<(u16, u16)>,
+    pos:         Option<(i16, i16)>,
+    effect:      String,
+    one_shot:    bool,
+    temp:        bool,
+    /// The compiled patterns of the class, instance, name, window type and
+    /// role, so that matching a [`Window`] doesn't compile them again
+    #[serde(skip)]
+    matchers:    Option<[Option<Matcher>; 5]>,
 }
 
 impl Rule {
@@ -48,17 +184,54 @@ impl Rule {
         Self::default()
     }
 
+    /// Create a [`Rule`] from a pattern in the form of
+    /// `CLASS[:INSTANCE[:NAME]]`, where `*` or an empty field matches anything
+    pub(crate) fn from_pattern(pattern: &str) -> Result<Self, RuleError> {
+        let mut fields = split_pattern(pattern)
+            .into_iter()
+            .map(|field| if field.is_empty() || field == "*" { None } else { Some(field) });
+
+        let mut rule = Self {
+            class: fields.next().flatten(),
+            instance: fields.next().flatten(),
+            name: fields.next().flatten(),
+            ..Self::default()
+        };
+
+        if fields.next().is_some() {
+            return Err(RuleError::InvalidRule(pattern.to_owned()));
+        }
+        rule.compile()?;
+
+        Ok(rule)
+    }
+
     /// Modify the [`Rule`]'s class
     pub(crate) fn set_class(&mut self, class: String) {
         self.class.replace(class);
+        self.matchers = None;
     }
 
     pub(crate) fn set_instance(&mut self, instance: String) {
         self.instance.replace(instance);
+        self.matchers = None;
     }
 
     pub(crate) fn set_name(&mut self, name: String) {
         self.name.replace(name);
+        self.matchers = None;
+    }
+
+    /// Modify the `_NET_WM_WINDOW_TYPE` the [`Rule`] matches
+    pub(crate) fn set_window_type(&mut self, window_type: String) {
+        self.window_type.replace(window_type);
+        self.matchers = None;
+    }
+
+    /// Modify the `WM_WINDOW_ROLE` the [`Rule`] matches
+    pub(crate) fn set_role(&mut self, role: String) {
+        self.role.replace(role);
+        self.matchers = None;
     }
 
     pub(crate) fn set_floating(&mut self, floating: bool) {
@@ -73,64 +246,997 @@ impl Rule {
         self.pos.replace(pos);
     }
 
-    // pub(crate) fn apply(&self, args: &mut ClientArgs) -> bool {
-    //     if let Some(floating) = self.floating {
-    //         args.flags.floating = floating;
-    //     }
-    //     if let Some(size) = self.size {
-    //         args.size = Some(size);
-    //     }
-    //     if let Some(pos) = self.pos {
-    //         args.pos.replace(pos);
-    //     }
-    //     self.temp
-    // }
+    /// Modify the `key=value` pairs applied by the [`Rule`]
+    pub(crate) fn set_effect(&mut self, effect: String) {
+        self.effect = effect;
+    }
+
+    /// Return the pattern of the [`Rule`], as given to
+    /// [`from_pattern`](Self::from_pattern)
+    pub(crate) fn pattern(&self) -> String {
+        [&self.class, &self.instance, &self.name]
+            .iter()
+            .map(|field| field.as_deref().unwrap_or("*"))
+            .collect::<Vec<_>>()
+            .join(":")
+    }
+
+    /// Compile the patterns of the class, instance, name, window type and role
+    fn compile_matchers(&self) -> Result<[Option<Matcher>; 5], RuleError> {
+        let compile = |field: &Option<String>| field.as_deref().map(Matcher::parse).transpose();
+        Ok([
+            compile(&self.class)?,
+            compile(&self.instance)?,
+            compile(&self.name)?,
+            compile(&self.window_type)?,
+            compile(&self.role)?,
+        ])
+    }
+
+    /// Compile the patterns once, keeping them for [`matches`](Self::matches)
+    pub(crate) fn compile(&mut self) -> Result<(), RuleError> {
+        self.matchers = Some(self.compile_matchers()?);
+        Ok(())
+    }
+
+    /// Compile the patterns and make sure the effect can be applied
+    pub(crate) fn validate(&mut self) -> Result<(), RuleError> {
+        self.compile()?;
+        RuleConsequence::default().apply_effect(&self.effect)
+    }
+
+    /// Does the [`Rule`] apply to the [`Window`] described by the
+    /// [`RuleConsequence`]?
+    ///
+    /// The patterns are only compiled here if [`compile`](Self::compile) wasn't
+    /// called, which [`RuleList::add`] does
+    pub(crate) fn matches(&self, csq: &RuleConsequence) -> bool {
+        let texts = [
+            &csq.class_name,
+            &csq.instance_name,
+            &csq.name,
+            &csq.window_type,
+            &csq.role,
+        ];
+        let compiled;
+        let matchers = match &self.matchers {
+            Some(matchers) => matchers,
+            None => match self.compile_matchers() {
+                Ok(matchers) => {
+                    compiled = matchers;
+                    &compiled
+                },
+                Err(_) => return false,
+            },
+        };
+
+        matchers
+            .iter()
+            .zip(texts)
+            .all(|(matcher, text)| matcher.as_ref().map_or(true, |m| m.is_match(text)))
+    }
+
+    /// Apply the [`Rule`] to a [`RuleConsequence`]
+    pub(crate) fn apply(&self, csq: &mut RuleConsequence) -> Result<(), RuleError> {
+        if let Some(floating) = self.floating {
+            csq.state = Some(t!(floating ? ClientState::Floating : ClientState::Tiled));
+        }
+        if self.size.is_some() || self.pos.is_some() {
+            let mut rect = csq.rect.unwrap_or_default();
+            if let Some((width, height)) = self.size {
+                rect.dimension.width = u32::from(width);
+                rect.dimension.height = u32::from(height);
+            }
+            if let Some((x, y)) = self.pos {
+                rect.point.x = i32::from(x);
+                rect.point.y = i32::from(y);
+            }
+            csq.rect = Some(rect);
+        }
+
+        csq.apply_effect(&self.effect)
+    }
 }
 
-mod tests {
-    use super::Rule;
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.pattern())?;
+        if let Some(window_type) = &self.window_type {
+            write!(f, " type={}", window_type)?;
+        }
+        if let Some(role) = &self.role {
+            write!(f, " role={}", role)?;
+        }
+        write!(f, " => {}", self.effect)?;
+        if self.one_shot {
+            write!(f, " (one-shot)")?;
+        }
+        Ok(())
+    }
+}
 
-    #[test]
-    fn fff() {
-        let mut rule = Rule::new();
-        rule.set_class("new".to_owned());
+// ============================= RuleList =============================
+
+/// The [`Rule`]s applied to the [`Window`]s that are managed, in the order
+/// they were added
+#[derive(Debug, Default, Clone)]
+pub(crate) struct RuleList {
+    rules: Vec<Rule>,
+}
+
+impl RuleList {
+    /// Create an empty [`RuleList`]
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return the number of [`Rule`]s
+    pub(crate) fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    /// Is the [`RuleList`] empty?
+    pub(crate) fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Add a [`Rule`] after the others, once it is validated and its patterns
+    /// are compiled
+    pub(crate) fn add(&mut self, mut rule: Rule) -> Result<(), RuleError> {
+        rule.validate()?;
+        self.rules.push(rule);
+        Ok(())
+    }
+
+    /// Remove the [`Rule`]s matching a descriptor, returning them
+    ///
+    /// The descriptor is `head`, `tail`, the 1-based index of a [`Rule`]
+    /// prefixed with `^`, or a pattern removing every [`Rule`] that has it
+    pub(crate) fn remove(&mut self, desc: &str) -> Result<Vec<Rule>, RuleError> {
+        let idx = match desc {
+            "head" => Some(0),
+            "tail" => self.rules.len().checked_sub(1),
+            _ => desc
+                .strip_prefix('^')
+                .and_then(|n| n.parse::<usize>().ok())
+                .and_then(|n| n.checked_sub(1)),
+        };
+
+        let removed = match idx {
+            Some(idx) if idx < self.rules.len() => vec![self.rules.remove(idx)],
+            Some(_) => vec![],
+            None => {
+                let pattern = Rule::from_pattern(desc)?.pattern();
+                let (removed, kept) = mem::take(&mut self.rules)
+                    .into_iter()
+                    .partition(|rule| rule.pattern() == pattern);
+                self.rules = kept;
+                removed
+            },
+        };
+
+        t!(removed.is_empty() ? Err(RuleError::NoMatch(desc.to_owned())) : Ok(removed))
+    }
+
+    /// List the [`Rule`]s, one per line
+    pub(crate) fn list(&self) -> String {
+        self.rules
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Apply every [`Rule`] matching the [`Window`] described by the
+    /// [`RuleConsequence`], in order, returning whether any matched
+    ///
+    /// One-shot [`Rule`]s are removed once they are applied
+    pub(crate) fn apply(&mut self, csq: &mut RuleConsequence) -> bool {
+        let mut matched = false;
+
+        self.rules.retain(|rule| {
+            if !rule.matches(csq) {
+                return true;
+            }
+
+            matched = true;
+            if let Err(e) = rule.apply(csq) {
+                log::warn!("failed to apply rule '{}': {}", rule, e);
+            }
+            !rule.one_shot
+        });
+
+        matched
     }
 }
 
 // ========================= RuleConsequence ==========================
 
-#[derive(Debug, Clone)]
+/// What happens to a [`Window`] once the [`Rule`]s are applied
+///
+/// The selectors are relative to the focused [`Node`]. Fields that are `None`
+/// keep the default behavior
+#[derive(Debug, Clone, PartialEq)]
+#[attr_reader(class_name, instance_name, name)]
+#[attr_accessor(
+    window_type,
+    role,
+    monitor_desc,
+    desktop_desc,
+    node_desc,
+    split_dir,
+    split_ratio,
+    layer,
+    state,
+    hidden,
+    sticky,
+    private,
+    locked,
+    marked,
+    center,
+    follow,
+    manage,
+    focus,
+    border,
+    honor_size_hints,
+    rect
+)]
 pub(crate) struct RuleConsequence {
-    class_name:    String,
-    instance_name: String,
-    name:          String,
-    monitor_desc:  String,
-    desktop_desc:  String,
-    node_desc:     String,
-    split_dir:     Direction,
-    split_ratio:   f64,
-    layer:         StackLayer,
-    state:         ClientState,
-    hidden:        bool,
-    sticky:        bool,
-    private:       bool,
-    locked:        bool,
-    marked:        bool,
-    center:        bool,
-    follow:        bool,
-    manage:        bool,
-    focus:         bool,
-    border:        bool,
-    rect:          Rectangle,
+    class_name:       String,
+    instance_name:    String,
+    name:             String,
+    window_type:      String,
+    role:             String,
+    monitor_desc:     Option<String>,
+    desktop_desc:     Option<String>,
+    node_desc:        Option<String>,
+    split_dir:        Option<Direction>,
+    split_ratio:      Option<f64>,
+    layer:            Option<StackLayer>,
+    state:            Option<ClientState>,
+    hidden:           bool,
+    sticky:           bool,
+    private:          bool,
+    locked:           bool,
+    marked:           bool,
+    center:           bool,
+    follow:           bool,
+    manage:           bool,
+    focus:            bool,
+    border:           bool,
+    honor_size_hints: Option<bool>,
+    rect:             Option<Rectangle>,
 }
 
-#[derive(Debug, Clone)]
+impl Default for RuleConsequence {
+    fn default() -> Self {
+        Self {
+            class_name:       String::new(),
+            instance_name:    String::new(),
+            name:             String::new(),
+            window_type:      String::new(),
+            role:             String::new(),
+            monitor_desc:     None,
+            desktop_desc:     None,
+            node_desc:        None,
+            split_dir:        None,
+            split_ratio:      None,
+            layer:            None,
+            state:            None,
+            hidden:           false,
+            sticky:           false,
+            private:          false,
+            locked:           false,
+            marked:           false,
+            center:           false,
+            follow:           false,
+            manage:           true,
+            focus:            true,
+            border:           true,
+            honor_size_hints: None,
+            rect:             None,
+        }
+    }
+}
+
+impl RuleConsequence {
+    /// Create the default [`RuleConsequence`] of a [`Window`]
+    pub(crate) fn new(class_name: String, instance_name: String, name: String) -> Self {
+        Self {
+            class_name,
+            instance_name,
+            name,
+            ..Self::default()
+        }
+    }
+
+    /// Apply whitespace separated `key=value` pairs
+    pub(crate) fn apply_effect(&mut self, effect: &str) -> Result<(), RuleError> {
+        for pair in effect.split_whitespace() {
+            let (key, value) = pair
+                .split_once('=')
+                .ok_or_else(|| RuleError::InvalidEffect(pair.to_owned()))?;
+            self.set(key, value)?;
+        }
+
+        Ok(())
+    }
+
+    /// Apply a single `key=value` pair
+    ///
+    /// Flags take `on` or `off`. Selectors are kept as they are and resolved
+    /// when the [`Window`] is placed
+    pub(crate) fn set(&mut self, key: &str, value: &str) -> Result<(), RuleError> {
+        let invalid = || RuleError::InvalidValue {
+            key:   key.to_owned(),
+            value: value.to_owned(),
+        };
+        let flag = || match value {
+            "on" | "true" => Ok(true),
+            "off" | "false" => Ok(false),
+            _ => Err(invalid()),
+        };
+
+        match key {
+            "monitor" => self.monitor_desc = Some(value.to_owned()),
+            "desktop" => self.desktop_desc = Some(value.to_owned()),
+            "node" => self.node_desc = Some(value.to_owned()),
+            "split_dir" =>
+                self.split_dir = Some(Direction::from_str(value).map_err(|_| invalid())?),
+            "split_ratio" => {
+                let ratio = value.parse::<f64>().map_err(|_| invalid())?;
+                if !Presel::is_valid_ratio(ratio as f32) {
+                    return Err(invalid());
+                }
+                self.split_ratio = Some(ratio);
+            },
+            "layer" => self.layer = Some(parse_layer(value).ok_or_else(invalid)?),
+            "state" => self.state = Some(parse_state(value).ok_or_else(invalid)?),
+            "rectangle" => self.rect = Some(parse_rectangle(value).ok_or_else(invalid)?),
+            "hidden" => self.hidden = flag()?,
+            "sticky" => self.sticky = flag()?,
+            "private" => self.private = flag()?,
+            "locked" => self.locked = flag()?,
+            "marked" => self.marked = flag()?,
+            "center" => self.center = flag()?,
+            "follow" => self.follow = flag()?,
+            "manage" => self.manage = flag()?,
+            "focus" => self.focus = flag()?,
+            "border" => self.border = flag()?,
+            "honor_size_hints" => self.honor_size_hints = Some(flag()?),
+            _ => return Err(RuleError::UnknownKey(key.to_owned())),
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for RuleConsequence {
+    /// Format the consequences as `key=value` pairs that [`apply_effect`] reads
+    /// back
+    ///
+    /// [`apply_effect`]: RuleConsequence::apply_effect
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut pairs = vec![];
+        let selectors = [
+            ("monitor", &self.monitor_desc),
+            ("desktop", &self.desktop_desc),
+            ("node", &self.node_desc),
+        ];
+        for (key, desc) in selectors {
+            if let Some(desc) = desc {
+                pairs.push(format!("{}={}", key, desc));
+            }
+        }
+
+        pairs.extend(self.split_dir.map(|dir| format!("split_dir={}", dir)));
+        pairs.extend(self.split_ratio.map(|ratio| format!("split_ratio={}", ratio)));
+        pairs.extend(self.layer.map(|layer| format!("layer={}", layer_name(layer))));
+        pairs.extend(self.state.map(|state| format!("state={}", state_name(state))));
+        pairs.extend(self.rect.map(|rect| format!("rectangle={}", format_rectangle(rect))));
+        pairs.extend(
+            self.honor_size_hints
+                .map(|on| format!("honor_size_hints={}", t!(on ? "on" : "off"))),
+        );
+
+        let flags = [
+            ("hidden", self.hidden),
+            ("sticky", self.sticky),
+            ("private", self.private),
+            ("locked", self.locked),
+            ("marked", self.marked),
+            ("center", self.center),
+            ("follow", self.follow),
+            ("manage", self.manage),
+            ("focus", self.focus),
+            ("border", self.border),
+        ];
+        pairs.extend(
+            flags
+                .iter()
+                .map(|(key, on)| format!("{}={}", key, t!(*on ? "on" : "off"))),
+        );
+
+        write!(f, "{}", pairs.join(" "))
+    }
+}
+
+/// Parse the name of a [`StackLayer`]
+fn parse_layer(s: &str) -> Option<StackLayer> {
+    match s {
+        "below" => Some(StackLayer::Below),
+        "normal" => Some(StackLayer::Normal),
+        "above" => Some(StackLayer::Above),
+        _ => None,
+    }
+}
+
+/// Name of a [`StackLayer`], the reverse of [`parse_layer`]
+const fn layer_name(layer: StackLayer) -> &'static str {
+    match layer {
+        StackLayer::Below => "below",
+        StackLayer::Normal => "normal",
+        StackLayer::Above => "above",
+    }
+}
+
+/// Parse the name of a [`ClientState`]
+fn parse_state(s: &str) -> Option<ClientState> {
+    match s {
+        "tiled" => Some(ClientState::Tiled),
+        "pseudo_tiled" => Some(ClientState::PsuedoTiled),
+        "floating" => Some(ClientState::Floating),
+        "fullscreen" => Some(ClientState::Fullscreen),
+        _ => None,
+    }
+}
+
+/// Name of a [`ClientState`], the reverse of [`parse_state`]
+const fn state_name(state: ClientState) -> &'static str {
+    match state {
+        ClientState::Tiled => "tiled",
+        ClientState::PsuedoTiled => "pseudo_tiled",
+        ClientState::Floating => "floating",
+        ClientState::Fullscreen => "fullscreen",
+    }
+}
+
+/// Format a [`Rectangle`] as `WxH+X+Y`, the reverse of [`parse_rectangle`]
+fn format_rectangle(rect: Rectangle) -> String {
+    format!(
+        "{}x{}{:+}{:+}",
+        rect.dimension.width, rect.dimension.height, rect.point.x, rect.point.y
+    )
+}
+
+/// Parse a [`Rectangle`] in the form of `WxH+X+Y`, where the offsets can be
+/// negative
+fn parse_rectangle(s: &str) -> Option<Rectangle> {
+    let (width, rest) = s.split_once('x')?;
+    let offset = rest.find(|c| c == '+' || c == '-')?;
+    let (height, rest) = rest.split_at(offset);
+    let offset = rest.get(1..)?.find(|c| c == '+' || c == '-')? + 1;
+    let (x, y) = rest.split_at(offset);
+
+    Some(Rectangle::new(
+        x.parse().ok()?,
+        y.parse().ok()?,
+        width.parse().ok()?,
+        height.parse().ok()?,
+    ))
+}
+
+// ============================ RuleEntry =============================
+
+/// A [`Rule`] declared in the `rules` section of the configuration file
+///
+/// Every field is checked while the file is deserialized, so that a mistake
+/// points to where it is in the file
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct RuleEntry {
+    #[serde(deserialize_with = "deserialize_pattern")]
+    class:       Option<String>,
+    #[serde(deserialize_with = "deserialize_pattern")]
+    instance:    Option<String>,
+    #[serde(deserialize_with = "deserialize_pattern")]
+    title:       Option<String>,
+    #[serde(alias = "window-type", deserialize_with = "deserialize_pattern")]
+    window_type: Option<String>,
+    #[serde(deserialize_with = "deserialize_pattern")]
+    role:        Option<String>,
+
+    monitor:          Option<String>,
+    desktop:          Option<String>,
+    node:             Option<String>,
+    #[serde(deserialize_with = "deserialize_state")]
+    state:            Option<ClientState>,
+    #[serde(deserialize_with = "deserialize_layer")]
+    layer:            Option<StackLayer>,
+    #[serde(deserialize_with = "deserialize_rectangle")]
+    rectangle:        Option<Rectangle>,
+    #[serde(alias = "split-dir", deserialize_with = "deserialize_direction")]
+    split_dir:        Option<Direction>,
+    #[serde(alias = "split-ratio", deserialize_with = "deserialize_ratio")]
+    split_ratio:      Option<f64>,
+    hidden:           Option<bool>,
+    sticky:           Option<bool>,
+    private:          Option<bool>,
+    locked:           Option<bool>,
+    marked:           Option<bool>,
+    center:           Option<bool>,
+    follow:           Option<bool>,
+    manage:           Option<bool>,
+    focus:            Option<bool>,
+    border:           Option<bool>,
+    #[serde(alias = "honor-size-hints")]
+    honor_size_hints: Option<bool>,
+    #[serde(alias = "one-shot")]
+    one_shot:         bool,
+}
+
+impl RuleEntry {
+    /// The `key=value` pairs of the consequences that are set
+    fn effect(&self) -> String {
+        let mut pairs = vec![];
+        let selectors = [
+            ("monitor", &self.monitor),
+            ("desktop", &self.desktop),
+            ("node", &self.node),
+        ];
+        for (key, desc) in selectors {
+            if let Some(desc) = desc {
+                pairs.push(format!("{}={}", key, desc));
+            }
+        }
+
+        pairs.extend(self.state.map(|state| format!("state={}", state_name(state))));
+        pairs.extend(self.layer.map(|layer| format!("layer={}", layer_name(layer))));
+        pairs.extend(self.rectangle.map(|rect| format!("rectangle={}", format_rectangle(rect))));
+        pairs.extend(self.split_dir.map(|dir| format!("split_dir={}", dir)));
+        pairs.extend(self.split_ratio.map(|ratio| format!("split_ratio={}", ratio)));
+
+        let flags = [
+            ("hidden", self.hidden),
+            ("sticky", self.sticky),
+            ("private", self.private),
+            ("locked", self.locked),
+            ("marked", self.marked),
+            ("center", self.center),
+            ("follow", self.follow),
+            ("manage", self.manage),
+            ("focus", self.focus),
+            ("border", self.border),
+            ("honor_size_hints", self.honor_size_hints),
+        ];
+        for (key, flag) in flags {
+            if let Some(on) = flag {
+                pairs.push(format!("{}={}", key, t!(on ? "on" : "off")));
+            }
+        }
+
+        pairs.join(" ")
+    }
+}
+
+impl From<RuleEntry> for Rule {
+    fn from(entry: RuleEntry) -> Self {
+        let effect = entry.effect();
+        Self {
+            class: entry.class,
+            instance: entry.instance,
+            name: entry.title,
+            window_type: entry.window_type,
+            role: entry.role,
+            effect,
+            one_shot: entry.one_shot,
+            ..Self::default()
+        }
+    }
+}
+
+/// Deserialize a string with `parse`, failing with what was `expected`
+fn deserialize_parsed<'de, D, T>(
+    deserializer: D,
+    parse: impl FnOnce(&str) -> Option<T>,
+    expected: &str,
+) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+    parse(&value)
+        .map(Some)
+        .ok_or_else(|| de::Error::invalid_value(de::Unexpected::Str(&value), &expected))
+}
+
+/// Deserialize a [`Matcher`] pattern, making sure it compiles
+fn deserialize_pattern<'de, D: Deserializer<'de>>(d: D) -> Result<Option<String>, D::Error> {
+    let pattern = String::deserialize(d)?;
+    Matcher::parse(&pattern).map_err(de::Error::custom)?;
+    Ok(Some(pattern))
+}
+
+/// Deserialize a [`ClientState`] by its name
+fn deserialize_state<'de, D: Deserializer<'de>>(d: D) -> Result<Option<ClientState>, D::Error> {
+    deserialize_parsed(d, parse_state, "tiled, pseudo_tiled, floating or fullscreen")
+}
+
+/// Deserialize a [`StackLayer`] by its name
+fn deserialize_layer<'de, D: Deserializer<'de>>(d: D) -> Result<Option<StackLayer>, D::Error> {
+    deserialize_parsed(d, parse_layer, "below, normal or above")
+}
+
+/// Deserialize a [`Rectangle`] in the form of `WxH+X+Y`
+fn deserialize_rectangle<'de, D>(d: D) -> Result<Option<Rectangle>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_parsed(d, parse_rectangle, "a rectangle in the form of WxH+X+Y")
+}
+
+/// Deserialize a [`Direction`] by its name
+fn deserialize_direction<'de, D>(d: D) -> Result<Option<Direction>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_parsed(d, |s| Direction::from_str(s).ok(), "north, south, east or west")
+}
+
+/// Deserialize a split ratio, which is strictly between 0 and 1
+fn deserialize_ratio<'de, D: Deserializer<'de>>(d: D) -> Result<Option<f64>, D::Error> {
+    let ratio = f64::deserialize(d)?;
+    if !Presel::is_valid_ratio(ratio as f32) {
+        return Err(de::Error::invalid_value(
+            de::Unexpected::Float(ratio),
+            &"a ratio between 0 and 1",
+        ));
+    }
+    Ok(Some(ratio))
+}
+
+// =========================== PendingRule ============================
+
+/// A [`Window`] waiting for the answer of the `external_rules_cmd`
+///
+/// The command is called with the window ID, its class, its instance and the
+/// current consequences. Each line it prints is an effect applied on top of
+/// them. The events received for the window are queued until it exits
+#[derive(Debug)]
 pub(crate) struct PendingRule {
-    fd:         usize,
-    win:        Window,
-    csq:        RuleConsequence,
-    event_head: EventQueue,
-    event_tail: EventQueue,
-    prev:       Box<Self>,
-    next:       Box<Self>,
+    fd:     RawFd,
+    win:    Window,
+    csq:    RuleConsequence,
+    child:  Child,
+    output: Vec<u8>,
+    events: VecDeque<XEvent>,
+}
+
+impl PendingRule {
+    /// Spawn the external rules command for a [`Window`]
+    pub(crate) fn spawn(cmd: &str, win: Window, csq: RuleConsequence) -> Result<Self> {
+        let mut child = Command::new(cmd)
+            .arg(format!("0x{:08X}", win))
+            .arg(&csq.class_name)
+            .arg(&csq.instance_name)
+            .arg(csq.to_string())
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("failed to spawn the external rules command '{}'", cmd))?;
+
+        let fd = child
+            .stdout
+            .as_ref()
+            .map(AsRawFd::as_raw_fd)
+            .context("external rules command has no stdout")?;
+
+        // The output is read as the event loop notices it, never waiting on it
+        if let Err(e) = fcntl(fd, FcntlArg::F_SETFL(OFlag::O_NONBLOCK)) {
+            child.kill().ok();
+            child.wait().ok();
+            return Err(e).context("failed to make the external rules output non-blocking");
+        }
+
+        Ok(Self {
+            fd,
+            win,
+            csq,
+            child,
+            output: vec![],
+            events: VecDeque::new(),
+        })
+    }
+
+    /// File descriptor of the command's output
+    pub(crate) const fn fd(&self) -> RawFd {
+        self.fd
+    }
+
+    /// The [`Window`] waiting for the command
+    pub(crate) const fn window(&self) -> Window {
+        self.win
+    }
+
+    /// Queue an event of the [`Window`] until the command answers
+    pub(crate) fn queue(&mut self, event: XEvent) {
+        self.events.push_back(event);
+    }
+
+    /// Read what is available of the output without blocking
+    ///
+    /// Returns whether the command is done writing
+    pub(crate) fn read(&mut self) -> bool {
+        let stdout = match self.child.stdout.as_mut() {
+            Some(stdout) => stdout,
+            None => return true,
+        };
+
+        let mut buf = [0_u8; 512];
+        loop {
+            match stdout.read(&mut buf) {
+                Ok(0) => return true,
+                Ok(n) => self.output.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == ErrorKind::Interrupted => {},
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return false,
+                Err(e) => {
+                    log::error!("failed to read the external rules output: {}", e);
+                    return true;
+                },
+            }
+        }
+    }
+
+    /// Apply the output of the command and return the [`Window`], its
+    /// consequences and the events that were queued for it
+    pub(crate) fn finish(mut self) -> (Window, RuleConsequence, VecDeque<XEvent>) {
+        // Reap the command without blocking the event loop if it is still running
+        drop(self.child.stdout.take());
+        match self.child.try_wait() {
+            Ok(Some(_)) => {},
+            Ok(None) => {
+                let mut child = self.child;
+                thread::spawn(move || child.wait());
+            },
+            Err(e) => log::error!("failed to wait for the external rules command: {}", e),
+        }
+
+        for line in String::from_utf8_lossy(&self.output).lines() {
+            if let Err(e) = self.csq.apply_effect(line) {
+                log::error!("invalid external rule for 0x{:08X}: {}", self.win, e);
+            }
+        }
+
+        (self.win, self.csq, self.events)
+    }
+}
+
+/// Tests for [`Rule`]s and [`RuleConsequence`]s
+#[cfg(test)]
+mod tests {
+    use super::{
+        parse_rectangle,
+        split_pattern,
+        Matcher,
+        Rule,
+        RuleConsequence,
+        RuleEntry,
+        RuleError,
+        RuleList,
+    };
+    use crate::{core::Direction, geometry::Rectangle, monitor::client::ClientState};
+
+    /// Create a [`Rule`] from a pattern and an effect
+    fn rule(pattern: &str, effect: &str) -> Rule {
+        let mut rule = Rule::from_pattern(pattern).unwrap();
+        rule.set_effect(effect.to_owned());
+        rule
+    }
+
+    /// Create the [`RuleConsequence`] of a [`Window`]
+    fn window(class: &str, instance: &str, name: &str) -> RuleConsequence {
+        RuleConsequence::new(class.to_owned(), instance.to_owned(), name.to_owned())
+    }
+
+    #[test]
+    fn fff() {
+        let mut rule = Rule::new();
+        rule.set_class("new".to_owned());
+    }
+
+    #[test]
+    fn matchers() {
+        let glob = Matcher::parse("Fire*").unwrap();
+        assert!(glob.is_match("Firefox"));
+        assert!(!glob.is_match("firefox"));
+        assert!(!glob.is_match("MyFirefox"));
+
+        assert!(Matcher::parse("?im").unwrap().is_match("Vim"));
+        assert!(Matcher::parse("a.b").unwrap().is_match("a.b"));
+        assert!(!Matcher::parse("a.b").unwrap().is_match("axb"));
+
+        let regex = Matcher::parse("/^(mpv|vlc)$/").unwrap();
+        assert!(regex.is_match("mpv"));
+        assert!(!regex.is_match("mpv2"));
+
+        let negated = Matcher::parse("!/term/").unwrap();
+        assert!(negated.is_match("Firefox"));
+        assert!(!negated.is_match("xterm"));
+        assert!(!Matcher::parse("!*").unwrap().is_match("anything"));
+
+        assert!(matches!(
+            Matcher::parse("/(/"),
+            Err(RuleError::InvalidPattern { .. })
+        ));
+    }
+
+    #[test]
+    fn patterns() {
+        assert_eq!(split_pattern("a:b:c"), vec!["a", "b", "c"]);
+        assert_eq!(split_pattern("/a:b/:!/c\\/:d/"), vec!["/a:b/", "!/c\\/:d/"]);
+
+        let rule = Rule::from_pattern("Firefox::/^Library$/").unwrap();
+        assert_eq!(rule.get_class().as_deref(), Some("Firefox"));
+        assert_eq!(rule.get_instance(), &None);
+        assert_eq!(rule.pattern(), "Firefox:*:/^Library$/");
+        assert!(rule.matches(&window("Firefox", "Navigator", "Library")));
+        assert!(!rule.matches(&window("Firefox", "Navigator", "Mozilla Firefox")));
+
+        assert!(Rule::from_pattern("*").unwrap().matches(&window("a", "b", "c")));
+        assert!(matches!(
+            Rule::from_pattern("a:b:c:d"),
+            Err(RuleError::InvalidRule(_))
+        ));
+    }
+
+    #[test]
+    fn effects() {
+        let mut csq = RuleConsequence::default();
+        csq.apply_effect("desktop=^2 state=floating split_dir=west split_ratio=0.3 focus=off")
+            .unwrap();
+        csq.apply_effect("rectangle=800x600-10+20 layer=above sticky=on")
+            .unwrap();
+
+        assert_eq!(csq.get_desktop_desc().as_deref(), Some("^2"));
+        assert_eq!(*csq.get_state(), Some(ClientState::Floating));
+        assert_eq!(*csq.get_split_dir(), Some(Direction::West));
+        assert_eq!(*csq.get_split_ratio(), Some(0.3));
+        assert_eq!(*csq.get_rect(), Some(Rectangle::new(-10, 20, 800, 600)));
+        assert!(!*csq.get_focus());
+        assert!(*csq.get_sticky());
+        assert!(*csq.get_manage());
+
+        assert!(matches!(
+            csq.apply_effect("state"),
+            Err(RuleError::InvalidEffect(_))
+        ));
+        assert!(matches!(
+            csq.apply_effect("color=red"),
+            Err(RuleError::UnknownKey(_))
+        ));
+        for ratio in ["1.5", "NaN", "0.99999999"] {
+            assert!(matches!(
+                csq.apply_effect(&format!("split_ratio={}", ratio)),
+                Err(RuleError::InvalidValue { .. })
+            ));
+        }
+        assert_eq!(parse_rectangle("10x20+3-4"), Some(Rectangle::new(3, -4, 10, 20)));
+        assert_eq!(parse_rectangle("10x20"), None);
+    }
+
+    #[test]
+    fn effect_round_trip() {
+        let mut csq = RuleConsequence::default();
+        assert!(csq.to_string().starts_with("hidden=off sticky=off"));
+        assert!(csq.to_string().ends_with("manage=on focus=on border=on"));
+
+        csq.apply_effect("node=@parent split_dir=north layer=below state=pseudo_tiled")
+            .unwrap();
+        csq.apply_effect("rectangle=640x480+0-30 marked=on follow=on").unwrap();
+        assert_eq!(
+            csq.to_string(),
+            "node=@parent split_dir=north layer=below state=pseudo_tiled \
+             rectangle=640x480+0-30 hidden=off sticky=off private=off locked=off \
+             marked=on center=off follow=on manage=on focus=on border=on"
+        );
+
+        let mut parsed = RuleConsequence::default();
+        parsed.apply_effect(&csq.to_string()).unwrap();
+        assert_eq!(parsed, csq);
+    }
+
+    #[test]
+    fn entries() {
+        let entries: Vec<RuleEntry> = serde_yaml::from_str(
+            r#"
+            - class: Gimp
+              window_type: dialog
+              state: floating
+              rectangle: 800x600+10-20
+              focus: false
+            - role: pop-up
+              desktop: ^3
+              split-dir: west
+              honor-size-hints: true
+              one_shot: true
+            "#,
+        )
+        .unwrap();
+
+        let mut rules = entries.into_iter().map(Rule::from).collect::<Vec<_>>();
+        assert_eq!(
+            rules[0].get_effect(),
+            "state=floating rectangle=800x600+10-20 focus=off"
+        );
+        assert_eq!(
+            rules[1].get_effect(),
+            "desktop=^3 split_dir=west honor_size_hints=on"
+        );
+        assert!(*rules[1].get_one_shot());
+        assert!(rules.iter_mut().all(|rule| rule.validate().is_ok()));
+
+        let mut dialog = window("Gimp", "gimp", "Export");
+        dialog.set_window_type(String::from("dialog"));
+        assert!(rules[0].matches(&dialog));
+        dialog.set_window_type(String::from("normal"));
+        assert!(!rules[0].matches(&dialog));
+        dialog.set_role(String::from("pop-up"));
+        assert!(rules[1].matches(&dialog));
+
+        for invalid in [
+            "- state: floated",
+            "- rectangle: 800x600",
+            "- split_ratio: 1.5",
+            "- split_ratio: .nan",
+            "- split_ratio: 0.99999999",
+            "- class: /[/",
+            "- color: red",
+        ] {
+            assert!(serde_yaml::from_str::<Vec<RuleEntry>>(invalid).is_err());
+        }
+    }
+
+    #[test]
+    fn rule_list() {
+        let mut rules = RuleList::new();
+        rules.add(rule("mpv", "state=floating")).unwrap();
+        rules.add(rule("*:*:/Picture-in-Picture/", "sticky=on layer=above")).unwrap();
+        let mut one_shot = rule("!mpv", "desktop=^3");
+        one_shot.set_one_shot(true);
+        rules.add(one_shot).unwrap();
+        assert!(rules.add(rule("mpv", "bogus=on")).is_err());
+        assert_eq!(rules.len(), 3);
+        assert!(rules.rules.iter().all(|rule| rule.matchers.is_some()));
+
+        let mut csq = RuleConsequence::new(
+            String::from("mpv"),
+            String::from("gl"),
+            String::from("Picture-in-Picture"),
+        );
+        assert!(rules.apply(&mut csq));
+        assert_eq!(*csq.get_state(), Some(ClientState::Floating));
+        assert!(*csq.get_sticky());
+        assert_eq!(csq.get_desktop_desc(), &None);
+
+        let mut csq =
+            RuleConsequence::new(String::from("st"), String::from("st"), String::from("zsh"));
+        assert!(rules.apply(&mut csq));
+        assert_eq!(csq.get_desktop_desc().as_deref(), Some("^3"));
+        assert_eq!(rules.len(), 2);
+
+        let mut csq =
+            RuleConsequence::new(String::from("st"), String::from("st"), String::from("zsh"));
+        let unchanged = csq.clone();
+        assert!(!rules.apply(&mut csq));
+        assert_eq!(csq, unchanged);
+
+        assert_eq!(
+            rules.list(),
+            "mpv:*:* => state=floating\n*:*:/Picture-in-Picture/ => sticky=on layer=above"
+        );
+        assert_eq!(rules.remove("mpv:*").unwrap().len(), 1);
+        assert!(matches!(rules.remove("^2"), Err(RuleError::NoMatch(_))));
+        assert_eq!(rules.remove("^1").unwrap().len(), 1);
+        assert!(rules.is_empty());
+    }
 }
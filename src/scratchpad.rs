@@ -0,0 +1,481 @@
+//! Named scratchpads, floating [`Window`]s shown and hidden on demand
+//!
+//! A scratchpad is declared in the configuration file with the command that
+//! spawns its [`Window`], a [`Rule`] pattern recognizing that [`Window`], and
+//! the geometry it takes on the [`Monitor`](crate::tree::Monitor) it is shown
+//! on
+
+#![allow(clippy::missing_docs_in_private_items, single_use_lifetimes)]
+
+use crate::{
+    core::Window,
+    geometry::Rectangle,
+    rule::{Rule, RuleConsequence},
+};
+
+use anyhow::{Context, Result};
+use indexmap::IndexMap;
+use serde::{de, Deserialize, Deserializer, Serialize};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    path::Path,
+    process::{Command, Stdio},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+};
+use tern::t;
+use thiserror::Error;
+
+// ============================== Errors ==============================
+
+/// Errors found while parsing or toggling a [`Scratchpad`]
+#[derive(Debug, Error)]
+pub(crate) enum ScratchpadError {
+    #[error("invalid geometry '{0}', expected W[%]xH[%][+X[%]+Y[%]]")]
+    InvalidGeometry(String),
+    #[error("there is no scratchpad named '{0}'")]
+    Unknown(String),
+}
+
+// ============================== Length ==============================
+
+/// A length in pixels, or in percent of the [`Monitor`](crate::tree::Monitor)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Length {
+    Pixels(i32),
+    Percent(f64),
+}
+
+impl Length {
+    /// Return the length in pixels, out of the `total` of the monitor
+    fn resolve(self, total: u32) -> i32 {
+        match self {
+            Self::Pixels(pixels) => pixels,
+            Self::Percent(percent) => (f64::from(total) * percent / 100.0).round() as i32,
+        }
+    }
+
+    /// Is the length negative, which is printed with its own sign?
+    fn is_negative(self) -> bool {
+        match self {
+            Self::Pixels(pixels) => pixels < 0,
+            Self::Percent(percent) => percent < 0.0,
+        }
+    }
+}
+
+impl FromStr for Length {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_suffix('%') {
+            Some(percent) => percent.parse().map(Self::Percent).map_err(|_| ()),
+            None => s.parse().map(Self::Pixels).map_err(|_| ()),
+        }
+    }
+}
+
+impl fmt::Display for Length {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Pixels(pixels) => write!(f, "{}", pixels),
+            Self::Percent(percent) => write!(f, "{}%", percent),
+        }
+    }
+}
+
+// ============================= Geometry =============================
+
+/// The floating geometry of a [`Scratchpad`], in the form of
+/// `W[%]xH[%][+X[%]+Y[%]]`
+///
+/// The offsets are from the top left corner of the
+/// [`Monitor`](crate::tree::Monitor). Without them, the [`Scratchpad`] is
+/// centered
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub(crate) struct Geometry {
+    width:  Length,
+    height: Length,
+    offset: Option<(Length, Length)>,
+}
+
+impl Geometry {
+    /// Compute the [`Rectangle`] taken on a [`Monitor`](crate::tree::Monitor)
+    /// covering `area`
+    pub(crate) fn resolve(&self, area: Rectangle) -> Rectangle {
+        let Rectangle { point, dimension } = area;
+        let width = self.width.resolve(dimension.width).max(1) as u32;
+        let height = self.height.resolve(dimension.height).max(1) as u32;
+
+        match self.offset {
+            Some((x, y)) => Rectangle::new(
+                point.x + x.resolve(dimension.width),
+                point.y + y.resolve(dimension.height),
+                width,
+                height,
+            ),
+            None => Rectangle::new(0, 0, width, height).centered_in(area),
+        }
+    }
+}
+
+impl Default for Geometry {
+    fn default() -> Self {
+        Self {
+            width:  Length::Percent(50.0),
+            height: Length::Percent(50.0),
+            offset: None,
+        }
+    }
+}
+
+impl FromStr for Geometry {
+    type Err = ScratchpadError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ScratchpadError::InvalidGeometry(s.to_owned());
+        let (width, rest) = s.split_once('x').ok_or_else(invalid)?;
+
+        let (height, offset) = match rest.find(|c| c == '+' || c == '-') {
+            Some(idx) => {
+                let (height, offsets) = rest.split_at(idx);
+                let idx = offsets
+                    .get(1..)
+                    .and_then(|o| o.find(|c| c == '+' || c == '-'))
+                    .ok_or_else(invalid)?
+                    + 1;
+                let (x, y) = offsets.split_at(idx);
+                let length = |l: &str| {
+                    l.trim_start_matches('+')
+                        .parse::<Length>()
+                        .map_err(|_| invalid())
+                };
+                (height, Some((length(x)?, length(y)?)))
+            },
+            None => (rest, None),
+        };
+
+        Ok(Self {
+            width: width.parse().map_err(|_| invalid())?,
+            height: height.parse().map_err(|_| invalid())?,
+            offset,
+        })
+    }
+}
+
+impl TryFrom<String> for Geometry {
+    type Error = ScratchpadError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Geometry> for String {
+    fn from(geometry: Geometry) -> Self {
+        geometry.to_string()
+    }
+}
+
+impl fmt::Display for Geometry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)?;
+        if let Some((x, y)) = self.offset {
+            let sign = |l: Length| t!(l.is_negative() ? "" : "+");
+            write!(f, "{}{}{}{}", sign(x), x, sign(y), y)?;
+        }
+        Ok(())
+    }
+}
+
+// ========================== ScratchpadEntry =========================
+
+/// A [`Scratchpad`] declared in the `scratchpads` section of the configuration
+/// file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ScratchpadEntry {
+    /// Shell command spawning the [`Window`]
+    pub(crate) command:  String,
+    /// The `CLASS[:INSTANCE[:NAME]]` pattern of the [`Window`]
+    #[serde(deserialize_with = "deserialize_rule")]
+    pub(crate) rule:     String,
+    /// Where the [`Window`] floats on the monitor it is shown on
+    #[serde(default)]
+    pub(crate) geometry: Geometry,
+}
+
+/// Deserialize the pattern of a [`Rule`], making sure it compiles
+fn deserialize_rule<'de, D: Deserializer<'de>>(d: D) -> Result<String, D::Error> {
+    let pattern = String::deserialize(d)?;
+    Rule::from_pattern(&pattern).map_err(de::Error::custom)?;
+    Ok(pattern)
+}
+
+// ============================ Scratchpad ============================
+
+/// A named [`Scratchpad`] and the [`Window`] it holds once it is running
+#[derive(Debug, Clone)]
+pub(crate) struct Scratchpad {
+    name:     String,
+    command:  String,
+    rule:     Rule,
+    geometry: Geometry,
+    window:   Option<Window>,
+    /// Is the command running without its [`Window`] showing up yet? Cleared
+    /// when the command exits
+    spawned:  Arc<AtomicBool>,
+}
+
+impl Scratchpad {
+    /// Create a [`Scratchpad`] from its entry in the configuration file
+    fn new(name: String, entry: &ScratchpadEntry) -> Result<Self> {
+        let rule = Rule::from_pattern(&entry.rule)
+            .context(format!("invalid rule of the scratchpad '{}'", name))?;
+        Ok(Self {
+            name,
+            command: entry.command.clone(),
+            rule,
+            geometry: entry.geometry,
+            window: None,
+            spawned: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    /// Return the name of the [`Scratchpad`]
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Return the [`Window`] of the [`Scratchpad`], if it is running
+    pub(crate) const fn window(&self) -> Option<Window> {
+        self.window
+    }
+
+    /// Return the [`Geometry`] of the [`Scratchpad`]
+    pub(crate) const fn geometry(&self) -> Geometry {
+        self.geometry
+    }
+
+    /// Is the [`Window`] described by the [`RuleConsequence`] the one of the
+    /// [`Scratchpad`]?
+    pub(crate) fn matches(&self, csq: &RuleConsequence) -> bool {
+        self.rule.matches(csq)
+    }
+
+    /// Is the command running without its [`Window`] showing up yet?
+    fn is_spawned(&self) -> bool {
+        self.spawned.load(Ordering::Acquire)
+    }
+
+    /// Spawn the command of the [`Scratchpad`] with the given shell, unless it
+    /// is already waiting for its [`Window`]
+    fn spawn(&mut self, shell: &Path) -> Result<()> {
+        if self.is_spawned() {
+            log::debug!("the scratchpad '{}' is already starting", self.name);
+            return Ok(());
+        }
+
+        log::debug!("spawning the scratchpad '{}': {}", self.name, self.command);
+        let mut child = Command::new(shell)
+            .arg("-c")
+            .arg(&self.command)
+            .stdin(Stdio::null())
+            .spawn()
+            .context(format!("failed to spawn the scratchpad '{}'", self.name))?;
+
+        // A fresh flag, so that an older command exiting doesn't clear it
+        let spawned = Arc::new(AtomicBool::new(true));
+        self.spawned = Arc::clone(&spawned);
+        thread::spawn(move || {
+            drop(child.wait());
+            spawned.store(false, Ordering::Release);
+        });
+
+        Ok(())
+    }
+}
+
+// ========================== ScratchpadList ==========================
+
+/// Every [`Scratchpad`] declared in the configuration file
+#[derive(Debug, Default, Clone)]
+pub(crate) struct ScratchpadList {
+    scratchpads: Vec<Scratchpad>,
+}
+
+impl ScratchpadList {
+    /// Create the [`Scratchpad`]s declared in the configuration file
+    pub(crate) fn new(entries: &IndexMap<String, ScratchpadEntry>) -> Self {
+        let scratchpads = entries
+            .iter()
+            .filter_map(|(name, entry)| {
+                Scratchpad::new(name.clone(), entry)
+                    .map_err(|e| log::error!("{:#}", e))
+                    .ok()
+            })
+            .collect();
+        Self { scratchpads }
+    }
+
+    /// Return the [`Scratchpad`] with the given name
+    pub(crate) fn get(&self, name: &str) -> Result<&Scratchpad, ScratchpadError> {
+        self.scratchpads
+            .iter()
+            .find(|s| s.name == name)
+            .ok_or_else(|| ScratchpadError::Unknown(name.to_owned()))
+    }
+
+    /// Return the [`Scratchpad`] with the given name mutably
+    fn get_mut(&mut self, name: &str) -> Result<&mut Scratchpad, ScratchpadError> {
+        self.scratchpads
+            .iter_mut()
+            .find(|s| s.name == name)
+            .ok_or_else(|| ScratchpadError::Unknown(name.to_owned()))
+    }
+
+    /// Return the [`Scratchpad`] holding a [`Window`]
+    pub(crate) fn find(&self, window: Window) -> Option<&Scratchpad> {
+        self.scratchpads.iter().find(|s| s.window == Some(window))
+    }
+
+    /// Give a running [`Window`] to a [`Scratchpad`]
+    pub(crate) fn bind(&mut self, name: &str, window: Window) -> Result<(), ScratchpadError> {
+        let scratchpad = self.get_mut(name)?;
+        scratchpad.window = Some(window);
+        scratchpad.spawned.store(false, Ordering::Release);
+        Ok(())
+    }
+
+    /// Spawn the command of a [`Scratchpad`], whose [`Window`] is claimed once
+    /// it is managed
+    pub(crate) fn spawn(&mut self, name: &str, shell: &Path) -> Result<()> {
+        self.get_mut(name)?.spawn(shell)
+    }
+
+    /// Give a new [`Window`] to the spawned [`Scratchpad`] it matches,
+    /// returning the [`Geometry`] it floats with
+    pub(crate) fn claim(&mut self, window: Window, csq: &RuleConsequence) -> Option<Geometry> {
+        let scratchpad = self
+            .scratchpads
+            .iter_mut()
+            .find(|s| s.is_spawned() && s.window.is_none() && s.matches(csq))?;
+        log::debug!("Window({:#0x}) is the scratchpad '{}'", window, scratchpad.name);
+        scratchpad.window = Some(window);
+        scratchpad.spawned.store(false, Ordering::Release);
+        Some(scratchpad.geometry)
+    }
+
+    /// Forget a [`Window`] that is no longer managed
+    pub(crate) fn release(&mut self, window: Window) {
+        for scratchpad in &mut self.scratchpads {
+            if scratchpad.window == Some(window) {
+                scratchpad.window = None;
+            }
+        }
+    }
+
+    /// Return the [`Window`] of every running [`Scratchpad`] by name, to be
+    /// bound again once the [`State`](crate::state::State) is restored
+    pub(crate) fn bindings(&self) -> BTreeMap<String, Window> {
+        self.scratchpads
+            .iter()
+            .filter_map(|s| s.window.map(|window| (s.name.clone(), window)))
+            .collect()
+    }
+
+    /// List the [`Scratchpad`]s and their [`Window`], one per line
+    pub(crate) fn list(&self) -> String {
+        self.scratchpads
+            .iter()
+            .map(|s| match s.window {
+                Some(window) => format!("{} 0x{:08X}", s.name, window),
+                None => format!("{} -", s.name),
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Tests for [`Geometry`]s and the [`ScratchpadList`]
+#[cfg(test)]
+mod tests {
+    use super::{Geometry, Length, ScratchpadEntry, ScratchpadList};
+    use crate::{geometry::Rectangle, rule::RuleConsequence};
+    use indexmap::IndexMap;
+    use std::sync::atomic::Ordering;
+
+    /// The area of the monitor used by the tests
+    const MONITOR: Rectangle = Rectangle::new(100, 0, 1920, 1080);
+
+    #[test]
+    fn geometry() {
+        let geometry = "50%x40%+25%+10%".parse::<Geometry>().unwrap();
+        assert_eq!(geometry.resolve(MONITOR), Rectangle::new(580, 108, 960, 432));
+        assert_eq!(geometry.to_string(), "50%x40%+25%+10%");
+
+        let geometry = "800x600-10+20".parse::<Geometry>().unwrap();
+        assert_eq!(geometry.resolve(MONITOR), Rectangle::new(90, 20, 800, 600));
+        assert_eq!(geometry.to_string(), "800x600-10+20");
+
+        let geometry = "800x50%".parse::<Geometry>().unwrap();
+        assert_eq!(geometry.resolve(MONITOR), Rectangle::new(660, 270, 800, 540));
+        assert_eq!(Geometry::default().resolve(MONITOR), Rectangle::new(580, 270, 960, 540));
+
+        for invalid in ["800", "800x", "ax600", "800x600+10", "800x600+10+y"] {
+            assert!(invalid.parse::<Geometry>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn entries() {
+        let entries: IndexMap<String, ScratchpadEntry> = serde_yaml::from_str(
+            r#"
+            term:
+              command: alacritty --class scratch
+              rule: scratch
+              geometry: 60%x50%+20%+5%
+            music:
+              command: kitty --class music ncmpcpp
+              rule: music:*:/ncmpcpp/
+            "#,
+        )
+        .unwrap();
+        assert_eq!(entries["music"].geometry, Geometry::default());
+        assert_eq!(entries["term"].geometry.width, Length::Percent(60.0));
+
+        assert!(serde_yaml::from_str::<ScratchpadEntry>("{command: st, rule: '/[/'}").is_err());
+        assert!(
+            serde_yaml::from_str::<ScratchpadEntry>("{command: st, rule: st, geometry: big}")
+                .is_err()
+        );
+
+        let mut scratchpads = ScratchpadList::new(&entries);
+        let csq = RuleConsequence::new(
+            String::from("scratch"),
+            String::from("Alacritty"),
+            String::from("zsh"),
+        );
+        // Only a spawned scratchpad claims a window
+        assert_eq!(scratchpads.claim(1, &csq), None);
+
+        scratchpads.get_mut("term").unwrap().spawned.store(true, Ordering::Release);
+        assert_eq!(scratchpads.claim(1, &csq), Some(entries["term"].geometry));
+        assert!(!scratchpads.get("term").unwrap().is_spawned());
+        assert_eq!(scratchpads.find(1).map(|s| s.name()), Some("term"));
+        assert_eq!(scratchpads.list(), "term 0x00000001\nmusic -");
+        let bindings = scratchpads.bindings();
+        assert_eq!(bindings.len(), 1);
+        assert_eq!(bindings.get("term"), Some(&1));
+
+        scratchpads.release(1);
+        assert_eq!(scratchpads.get("term").unwrap().window(), None);
+        assert!(scratchpads.get("nope").is_err());
+    }
+}
@@ -1,8 +1,12 @@
 //! The local information about the X-Server window stack
 
-use crate::core::Window;
+use crate::{
+    core::{StackMode, Window},
+    monitor::client::ClientState,
+};
 use std::collections::HashMap;
 use serde::{Serialize, Deserialize};
+use tern::t;
 
 /// The type of [`Window`] in the [`StackingList`]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
@@ -15,20 +19,22 @@ pub(crate) enum StackLayer {
     Above,
 }
 
-/// The type of layer in the [`Stack`]
-#[derive(Debug)]
+/// The type of layer in the [`Stack`], from bottom to top
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub(crate) enum StackLayer1 {
     /// A `Desktop` layer
     Desktop,
     /// A layer that is `Below` another
     Below,
+    /// The layer of the managed windows, ordered by their [`StackLayer`] and
+    /// [`ClientState`]
+    Regular,
     /// A layer that is `Above` another
     Above,
     /// A `Dock` layer
     Dock,
     /// A `Notification` layer
     Notification,
-    // Regular,
     // Free,
     // Transient,
     // Fullscreen,
@@ -44,6 +50,8 @@ pub(crate) struct StackManager {
     desktop_windows:      Vec<Window>,
     /// Windows with a [`Below`](StackLayer1::Below) layer
     below_windows:        Vec<Window>,
+    /// Windows with a [`Regular`](StackLayer1::Regular) layer
+    regular_windows:      Vec<Window>,
     /// Windows with an [`Above`](StackLayer1::Above) layer
     above_windows:        Vec<Window>,
     /// Windows with a [`Dock`](StackLayer1::Dock) layer
@@ -55,6 +63,9 @@ pub(crate) struct StackManager {
     above_other: HashMap<Window, Window>,
     /// Windows to be stacked below others
     below_other: HashMap<Window, Window>,
+
+    /// The X-Server stack as far as it is known, from bottom to top
+    stack: Vec<Window>,
 }
 
 impl Default for StackManager {
@@ -63,11 +74,13 @@ impl Default for StackManager {
             window_layers:        HashMap::with_capacity(5),
             desktop_windows:      Vec::with_capacity(12),
             below_windows:        Vec::with_capacity(12),
+            regular_windows:      Vec::with_capacity(12),
             above_windows:        Vec::with_capacity(12),
             dock_windows:         Vec::with_capacity(12),
             notification_windows: Vec::with_capacity(12),
             above_other:          HashMap::with_capacity(25),
             below_other:          HashMap::with_capacity(25),
+            stack:                Vec::with_capacity(25),
         }
     }
 }
@@ -89,4 +102,437 @@ impl StackManager {
     pub(crate) const fn below_other(&self) -> &HashMap<Window, Window> {
         &self.below_other
     }
+
+    /// Return the [`Window`]s as they are stacked, from bottom to top
+    pub(crate) fn stack(&self) -> &[Window] {
+        &self.stack
+    }
+
+    /// Return the managed [`Window`]s as they are stacked, from bottom to top,
+    /// as used by `_NET_CLIENT_LIST_STACKING`
+    pub(crate) fn clients(&self) -> Vec<Window> {
+        self.stack
+            .iter()
+            .copied()
+            .filter(|w| self.window_layers.get(w) == Some(&StackLayer1::Regular))
+            .collect()
+    }
+
+    /// Is the [`Window`] in the stack?
+    pub(crate) fn contains(&self, window: Window) -> bool {
+        self.window_layers.contains_key(&window)
+    }
+
+    /// Return the [`Window`]s of a [`StackLayer1`], from bottom to top
+    fn layer(&self, layer: StackLayer1) -> &Vec<Window> {
+        match layer {
+            StackLayer1::Desktop => &self.desktop_windows,
+            StackLayer1::Below => &self.below_windows,
+            StackLayer1::Regular => &self.regular_windows,
+            StackLayer1::Above => &self.above_windows,
+            StackLayer1::Dock => &self.dock_windows,
+            StackLayer1::Notification => &self.notification_windows,
+        }
+    }
+
+    /// Return the [`Window`]s of a [`StackLayer1`] mutably
+    fn layer_mut(&mut self, layer: StackLayer1) -> &mut Vec<Window> {
+        match layer {
+            StackLayer1::Desktop => &mut self.desktop_windows,
+            StackLayer1::Below => &mut self.below_windows,
+            StackLayer1::Regular => &mut self.regular_windows,
+            StackLayer1::Above => &mut self.above_windows,
+            StackLayer1::Dock => &mut self.dock_windows,
+            StackLayer1::Notification => &mut self.notification_windows,
+        }
+    }
+
+    // ========================== Tracking ==========================
+
+    /// Add a [`Window`] at the top of its [`StackLayer1`]
+    ///
+    /// A [`Window`] that wasn't in the stack is expected to be on top of the
+    /// X-Server stack, where new [`Window`]s are created
+    pub(crate) fn add(&mut self, window: Window, layer: StackLayer1) {
+        if let Some(layer) = self.window_layers.insert(window, layer) {
+            self.layer_mut(layer).retain(|&w| w != window);
+        }
+        self.layer_mut(layer).push(window);
+        if !self.stack.contains(&window) {
+            self.stack.push(window);
+        }
+    }
+
+    /// Remove a [`Window`] from the stack, forgetting the [`Window`]s it is
+    /// kept above or below
+    pub(crate) fn remove(&mut self, window: Window) {
+        if let Some(layer) = self.window_layers.remove(&window) {
+            self.layer_mut(layer).retain(|&w| w != window);
+        }
+        self.stack.retain(|&w| w != window);
+
+        for others in [&mut self.above_other, &mut self.below_other] {
+            others.remove(&window);
+            others.retain(|_, &mut other| other != window);
+        }
+    }
+
+    /// Move a [`Window`] to the top of its [`StackLayer1`]. It still stays
+    /// below the ones with a higher [`StackLayer`] or [`ClientState`]
+    pub(crate) fn raise(&mut self, window: Window) {
+        if let Some(&layer) = self.window_layers.get(&window) {
+            let windows = self.layer_mut(layer);
+            windows.retain(|&w| w != window);
+            windows.push(window);
+        }
+    }
+
+    /// Move a [`Window`] right above or below a sibling of the same
+    /// [`StackLayer1`], or to the top or bottom of its layer otherwise
+    pub(crate) fn stack_relative(
+        &mut self,
+        window: Window,
+        mode: StackMode,
+        sibling: Option<Window>,
+    ) {
+        if let Some(&layer) = self.window_layers.get(&window) {
+            let windows = self.layer_mut(layer);
+            windows.retain(|&w| w != window);
+            let idx = match (sibling.and_then(|s| windows.iter().position(|&w| w == s)), mode) {
+                (Some(idx), StackMode::Above) => idx + 1,
+                (Some(idx), StackMode::Below) => idx,
+                (None, StackMode::Above) => windows.len(),
+                (None, StackMode::Below) => 0,
+            };
+            windows.insert(idx, window);
+        }
+    }
+
+    /// Keep a [`Window`] above another one, like a transient above its parent
+    pub(crate) fn keep_above(&mut self, window: Window, other: Window) {
+        self.above_other.insert(window, other);
+    }
+
+    /// Keep a [`Window`] below another one
+    pub(crate) fn keep_below(&mut self, window: Window, other: Window) {
+        self.below_other.insert(window, other);
+    }
+
+    /// Return the [`Window`]s that must be below the given one
+    fn lower_than(&self, window: Window) -> impl Iterator<Item = Window> + '_ {
+        self.above_other.get(&window).copied().into_iter().chain(
+            self.below_other
+                .iter()
+                .filter(move |(_, &other)| other == window)
+                .map(|(&w, _)| w),
+        )
+    }
+
+    // ========================= Restacking =========================
+
+    /// Compute the order of the [`Window`]s, from bottom to top
+    ///
+    /// The layers follow the order of [`StackLayer1`]. Within the regular one,
+    /// the managed [`Window`]s are sorted by the [`StackLayer`] and
+    /// [`ClientState`] returned by `key`, a [`Window`] kept above another one
+    /// being at least as high. Ties keep the order in which they were raised
+    pub(crate) fn order(&self, key: impl Fn(Window) -> (StackLayer, ClientState)) -> Vec<Window> {
+        let keys = self
+            .regular_windows
+            .iter()
+            .map(|&w| {
+                let (layer, state) = key(w);
+                (w, (layer, state_level(state)))
+            })
+            .collect::<HashMap<_, _>>();
+
+        // Follow the windows that are kept below, a transient of a transient
+        // being as high as the first one
+        let rank = |window: Window| {
+            let mut best = keys[&window];
+            let mut lower = vec![window];
+            let mut seen = vec![];
+            while let Some(w) = lower.pop() {
+                if seen.contains(&w) {
+                    continue;
+                }
+                seen.push(w);
+                for l in self.lower_than(w).filter(|l| keys.contains_key(l)) {
+                    best = best.max(keys[&l]);
+                    lower.push(l);
+                }
+            }
+            best
+        };
+
+        let mut regular = self.regular_windows.clone();
+        regular.sort_by_key(|&w| rank(w));
+
+        let mut order = Vec::with_capacity(self.window_layers.len());
+        for layer in [
+            StackLayer1::Desktop,
+            StackLayer1::Below,
+            StackLayer1::Regular,
+            StackLayer1::Above,
+            StackLayer1::Dock,
+            StackLayer1::Notification,
+        ] {
+            let windows = t!(layer == StackLayer1::Regular ? &regular : self.layer(layer));
+            order.extend(self.keep_lower_first(windows));
+        }
+
+        order
+    }
+
+    /// Reorder the [`Window`]s of a layer so that each one comes after the
+    /// [`Window`]s it is kept above, right after the last of them if needed
+    fn keep_lower_first(&self, windows: &[Window]) -> Vec<Window> {
+        let mut placed = Vec::with_capacity(windows.len());
+        let mut deferred: Vec<Window> = vec![];
+        let ready = |w: Window, placed: &[Window]| {
+            self.lower_than(w)
+                .all(|l| placed.contains(&l) || !windows.contains(&l))
+        };
+
+        for &window in windows {
+            if !ready(window, &placed) {
+                deferred.push(window);
+                continue;
+            }
+            placed.push(window);
+
+            // Place the windows that were waiting for this one
+            while let Some(idx) = deferred.iter().position(|&w| ready(w, &placed)) {
+                placed.push(deferred.remove(idx));
+            }
+        }
+
+        // Only windows kept above each other in a loop are left
+        placed.extend(deferred);
+        placed
+    }
+
+    /// Compute the new order of the [`Window`]s, returning the changes that
+    /// bring the X-Server stack to it
+    ///
+    /// Each change stacks a [`Window`] right above or below a sibling. The
+    /// [`Window`]s that are already in order are left alone, so that as few
+    /// changes as possible are made
+    pub(crate) fn restack(
+        &mut self,
+        key: impl Fn(Window) -> (StackLayer, ClientState),
+    ) -> Vec<(Window, StackMode, Window)> {
+        let order = self.order(key);
+        let changes = changes(&self.stack, &order);
+        self.stack = order;
+        changes
+    }
+}
+
+/// Return the level of a [`ClientState`] within a [`StackLayer`]. Tiled
+/// windows are below floating ones, which are below fullscreen ones
+const fn state_level(state: ClientState) -> u8 {
+    match state {
+        ClientState::Tiled | ClientState::PsuedoTiled => 0,
+        ClientState::Floating => 1,
+        ClientState::Fullscreen => 2,
+    }
+}
+
+/// Return the changes turning the `current` order into the `target` one,
+/// which holds the same [`Window`]s
+///
+/// The longest sequence of [`Window`]s that are already in the right order
+/// stays in place. Every other [`Window`] is stacked above the one preceding it
+/// in the `target` order, or below the first one that stays if it has none
+fn changes(current: &[Window], target: &[Window]) -> Vec<(Window, StackMode, Window)> {
+    let positions = current
+        .iter()
+        .enumerate()
+        .map(|(idx, &w)| (w, idx))
+        .collect::<HashMap<_, _>>();
+    let sequence = target
+        .iter()
+        .map(|w| positions.get(w).copied().unwrap_or(usize::MAX))
+        .collect::<Vec<_>>();
+    let keep = longest_increasing(&sequence);
+    let first_kept = keep.iter().position(|&k| k);
+
+    let mut changes = vec![];
+    for (idx, &window) in target.iter().enumerate() {
+        if keep[idx] {
+            continue;
+        }
+        match (idx.checked_sub(1), first_kept) {
+            (Some(prev), _) => changes.push((window, StackMode::Above, target[prev])),
+            (None, Some(first)) => changes.push((window, StackMode::Below, target[first])),
+            (None, None) => {},
+        }
+    }
+
+    changes
+}
+
+/// Mark the items of one of the longest strictly increasing subsequences
+fn longest_increasing(sequence: &[usize]) -> Vec<bool> {
+    // Index of the smallest last item of the subsequences of each length
+    let mut tails: Vec<usize> = vec![];
+    let mut previous = vec![None; sequence.len()];
+
+    for (idx, &item) in sequence.iter().enumerate() {
+        let len = tails.partition_point(|&t| sequence[t] < item);
+        if len > 0 {
+            previous[idx] = Some(tails[len - 1]);
+        }
+        if len == tails.len() {
+            tails.push(idx);
+        } else {
+            tails[len] = idx;
+        }
+    }
+
+    let mut keep = vec![false; sequence.len()];
+    let mut curr = tails.last().copied();
+    while let Some(idx) = curr {
+        keep[idx] = true;
+        curr = previous[idx];
+    }
+
+    keep
+}
+
+/// Tests for ordering the [`StackManager`] and restacking
+#[cfg(test)]
+mod tests {
+    use super::{changes, longest_increasing, StackLayer, StackLayer1, StackManager};
+    use crate::{
+        core::{StackMode, Window},
+        monitor::client::ClientState,
+    };
+    use std::collections::HashMap;
+
+    /// Create a [`StackManager`] with the regular windows `1` to `4`, a dock
+    /// `10` and a desktop window `20`
+    fn stack() -> StackManager {
+        let mut stack = StackManager::new();
+        stack.add(10, StackLayer1::Dock);
+        for window in 1..=4 {
+            stack.add(window, StackLayer1::Regular);
+        }
+        stack.add(20, StackLayer1::Desktop);
+        stack
+    }
+
+    /// The [`StackLayer`] and [`ClientState`] of the regular windows
+    type Keys = HashMap<Window, (StackLayer, ClientState)>;
+
+    /// Return the key of the regular windows from a map, tiled by default
+    fn key(keys: &Keys) -> impl Fn(Window) -> (StackLayer, ClientState) + '_ {
+        move |w| {
+            keys.get(&w)
+                .copied()
+                .unwrap_or((StackLayer::Normal, ClientState::Tiled))
+        }
+    }
+
+    #[test]
+    fn order() {
+        let mut stack = stack();
+        let mut keys = HashMap::new();
+        assert_eq!(stack.order(key(&keys)), vec![20, 1, 2, 3, 4, 10]);
+
+        keys.insert(1, (StackLayer::Normal, ClientState::Fullscreen));
+        keys.insert(2, (StackLayer::Normal, ClientState::Floating));
+        keys.insert(3, (StackLayer::Below, ClientState::Floating));
+        keys.insert(4, (StackLayer::Normal, ClientState::PsuedoTiled));
+        assert_eq!(stack.order(key(&keys)), vec![20, 3, 4, 2, 1, 10]);
+
+        // Raising only reorders windows of the same level
+        keys.insert(4, (StackLayer::Normal, ClientState::Floating));
+        assert_eq!(stack.order(key(&keys)), vec![20, 3, 2, 4, 1, 10]);
+        stack.raise(2);
+        assert_eq!(stack.order(key(&keys)), vec![20, 3, 4, 2, 1, 10]);
+        stack.raise(10);
+        stack.raise(3);
+        assert_eq!(stack.order(key(&keys)), vec![20, 3, 4, 2, 1, 10]);
+    }
+
+    #[test]
+    fn transients() {
+        let mut stack = stack();
+        let mut keys = HashMap::new();
+        keys.insert(2, (StackLayer::Normal, ClientState::Floating));
+
+        // A transient is as high as its parent, and stays above it
+        stack.keep_above(1, 2);
+        assert_eq!(stack.order(key(&keys)), vec![20, 3, 4, 2, 1, 10]);
+        stack.raise(2);
+        assert_eq!(stack.order(key(&keys)), vec![20, 3, 4, 2, 1, 10]);
+
+        stack.keep_above(3, 1);
+        assert_eq!(stack.order(key(&keys)), vec![20, 4, 2, 1, 3, 10]);
+        keys.insert(4, (StackLayer::Normal, ClientState::Floating));
+        stack.keep_below(4, 3);
+        stack.raise(4);
+        assert_eq!(stack.order(key(&keys)), vec![20, 2, 1, 4, 3, 10]);
+
+        stack.remove(1);
+        assert!(stack.above_other().get(&3).is_none());
+        assert_eq!(stack.order(key(&keys)), vec![20, 2, 4, 3, 10]);
+    }
+
+    #[test]
+    fn stack_relative() {
+        let mut stack = stack();
+        let keys = HashMap::new();
+
+        stack.stack_relative(4, StackMode::Below, None);
+        assert_eq!(stack.order(key(&keys)), vec![20, 4, 1, 2, 3, 10]);
+        stack.stack_relative(1, StackMode::Above, Some(3));
+        assert_eq!(stack.order(key(&keys)), vec![20, 4, 2, 3, 1, 10]);
+        stack.stack_relative(2, StackMode::Below, Some(4));
+        assert_eq!(stack.order(key(&keys)), vec![20, 2, 4, 3, 1, 10]);
+
+        // Siblings of another layer are ignored
+        stack.stack_relative(3, StackMode::Above, Some(10));
+        assert_eq!(stack.order(key(&keys)), vec![20, 2, 4, 1, 3, 10]);
+    }
+
+    #[test]
+    fn restack() {
+        let mut stack = stack();
+        let keys = HashMap::new();
+
+        // New windows are created on top
+        assert_eq!(stack.stack(), &[10, 1, 2, 3, 4, 20]);
+        assert_eq!(stack.restack(key(&keys)), vec![
+            (20, StackMode::Below, 1),
+            (10, StackMode::Above, 4),
+        ]);
+        assert_eq!(stack.stack(), &[20, 1, 2, 3, 4, 10]);
+        assert!(stack.restack(key(&keys)).is_empty());
+
+        stack.raise(1);
+        assert_eq!(stack.restack(key(&keys)), vec![(1, StackMode::Above, 4)]);
+        assert_eq!(stack.clients(), vec![2, 3, 4, 1]);
+
+        stack.add(5, StackLayer1::Regular);
+        assert_eq!(stack.restack(key(&keys)), vec![(5, StackMode::Above, 1)]);
+        stack.remove(3);
+        assert!(stack.restack(key(&keys)).is_empty());
+        assert_eq!(stack.stack(), &[20, 2, 4, 1, 5, 10]);
+    }
+
+    #[test]
+    fn minimal_changes() {
+        assert_eq!(longest_increasing(&[3, 0, 1, 4, 2]), vec![false, true, true, false, true]);
+        assert_eq!(longest_increasing(&[]), Vec::<bool>::new());
+
+        assert!(changes(&[1, 2, 3], &[1, 2, 3]).is_empty());
+        assert_eq!(changes(&[1, 2, 3], &[3, 1, 2]), vec![(3, StackMode::Below, 1)]);
+        assert_eq!(changes(&[1, 2, 3, 4], &[2, 1, 4, 3]), vec![
+            (2, StackMode::Below, 1),
+            (4, StackMode::Above, 1),
+        ]);
+    }
 }
@@ -0,0 +1,672 @@
+//! The state of every [`Monitor`], [`Desktop`] and [`Node`] that is managed
+
+use crate::{
+    core::{AutomaticScheme, Tightness, Window, Xid},
+    geometry::Rectangle,
+    monitor::client::{Client, ClientState},
+    tree::{Coordinates, Desktop, History, Monitor, Node, NodeId, Tree},
+};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, mem};
+
+/// Every [`Monitor`] known to the window manager and where the focus is
+///
+/// This is the highest level of the tree, one level above a [`Monitor`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct State {
+    /// The [`Monitor`]s, in order
+    monitors:    Vec<Monitor>,
+    /// ID of the focused [`Monitor`]
+    focus:       Option<Xid>,
+    /// Focus history
+    history:     History,
+    /// Tightness used to find a [`Node`] in a direction
+    tightness:   Tightness,
+    /// The [`Window`] of each running scratchpad, only set in a dump
+    #[serde(default)]
+    scratchpads: BTreeMap<String, Window>,
+}
+
+impl State {
+    /// Create a new [`State`] without any [`Monitor`]
+    pub(crate) fn new(tightness: Tightness) -> Self {
+        Self {
+            monitors: vec![],
+            focus: None,
+            history: History::new(),
+            tightness,
+            scratchpads: BTreeMap::new(),
+        }
+    }
+
+    /// Return the [`Monitor`]s
+    pub(crate) fn monitors(&self) -> &[Monitor] {
+        &self.monitors
+    }
+
+    /// Return the [`Monitor`]s mutably
+    pub(crate) fn monitors_mut(&mut self) -> &mut [Monitor] {
+        &mut self.monitors
+    }
+
+    /// Return the [`Monitor`] with the given ID
+    pub(crate) fn monitor(&self, id: Xid) -> Option<&Monitor> {
+        self.monitors.iter().find(|m| m.id() == id)
+    }
+
+    /// Return the [`Monitor`] with the given ID mutably
+    pub(crate) fn monitor_mut(&mut self, id: Xid) -> Option<&mut Monitor> {
+        self.monitors.iter_mut().find(|m| m.id() == id)
+    }
+
+    /// Add a [`Monitor`], focusing it if it's the only one
+    pub(crate) fn add_monitor(&mut self, monitor: Monitor) {
+        if self.focus.is_none() {
+            self.focus = Some(monitor.id());
+        }
+        self.monitors.push(monitor);
+    }
+
+    /// Remove the [`Monitor`] with the given ID. If it was focused, the first
+    /// remaining [`Monitor`] is focused instead
+    pub(crate) fn remove_monitor(&mut self, id: Xid) -> Option<Monitor> {
+        let idx = self.monitors.iter().position(|m| m.id() == id)?;
+        let monitor = self.monitors.remove(idx);
+        if self.focus == Some(id) {
+            self.focus = self.monitors.first().map(Monitor::id);
+        }
+        self.prune_history();
+        Some(monitor)
+    }
+
+    /// Return the focused [`Monitor`]
+    pub(crate) fn focused_monitor(&self) -> Option<&Monitor> {
+        self.focus.and_then(|id| self.monitor(id))
+    }
+
+    /// Return the focused [`Monitor`] mutably
+    pub(crate) fn focused_monitor_mut(&mut self) -> Option<&mut Monitor> {
+        let id = self.focus?;
+        self.monitor_mut(id)
+    }
+
+    /// Focus the [`Monitor`] with the given ID
+    pub(crate) fn set_focus(&mut self, id: Xid) {
+        if self.monitor(id).is_some() {
+            self.focus = Some(id);
+        }
+    }
+
+    /// Return the [`Desktop`] with the given ID, along with its [`Monitor`]
+    pub(crate) fn desktop(&self, id: Xid) -> Option<(&Monitor, &Desktop)> {
+        self.monitors
+            .iter()
+            .find_map(|m| m.desktop(id).map(|d| (m, d)))
+    }
+
+    /// Return the [`Desktop`] with the given ID mutably
+    pub(crate) fn desktop_mut(&mut self, id: Xid) -> Option<&mut Desktop> {
+        self.monitors.iter_mut().find_map(|m| m.desktop_mut(id))
+    }
+
+    /// Remove the [`Desktop`] with the given ID from its [`Monitor`]
+    pub(crate) fn remove_desktop(&mut self, id: Xid) -> Option<Desktop> {
+        let desktop = self
+            .monitors
+            .iter_mut()
+            .find(|m| m.desktop(id).is_some())?
+            .remove_desktop(id);
+        self.prune_history();
+        desktop
+    }
+
+    /// Return the [`Node`] at the given [`Coordinates`]
+    pub(crate) fn node(&self, loc: &Coordinates) -> Option<&Node> {
+        let (_, desktop) = self.desktop(loc.desktop)?;
+        desktop.tree().get(loc.node?)
+    }
+
+    /// Return the [`Node`] at the given [`Coordinates`] mutably
+    pub(crate) fn node_mut(&mut self, loc: &Coordinates) -> Option<&mut Node> {
+        let node = loc.node?;
+        self.desktop_mut(loc.desktop)?.tree_mut().get_mut(node)
+    }
+
+    /// Return the [`Coordinates`] of the focused [`Monitor`], [`Desktop`] and
+    /// [`Node`]
+    pub(crate) fn focused(&self) -> Option<Coordinates> {
+        let monitor = self.focused_monitor()?;
+        let desktop = monitor.desk()?;
+        Some(Coordinates::new(monitor.id(), desktop.id(), desktop.focus()))
+    }
+
+    /// Find the [`Coordinates`] of the leaf holding the given [`Window`]
+    pub(crate) fn locate_window(&self, window: Window) -> Option<Coordinates> {
+        self.monitors.iter().find_map(|m| {
+            m.desktops().iter().find_map(|d| {
+                d.tree()
+                    .find_window(window)
+                    .map(|n| Coordinates::new(m.id(), d.id(), Some(n)))
+            })
+        })
+    }
+
+    /// Find the [`Coordinates`] of the first leaf whose [`Client`] satisfies
+    /// `pred`
+    pub(crate) fn locate_client(&self, pred: impl Fn(&Client) -> bool) -> Option<Coordinates> {
+        self.monitors.iter().find_map(|m| {
+            m.desktops().iter().find_map(|d| {
+                let tree = d.tree();
+                tree.leaves()
+                    .find(|&id| tree.get(id).and_then(Node::client).map_or(false, &pred))
+                    .map(|n| Coordinates::new(m.id(), d.id(), Some(n)))
+            })
+        })
+    }
+
+    /// Find the [`Coordinates`] of the [`Node`] with the given ID
+    pub(crate) fn locate_node(&self, id: Xid) -> Option<Coordinates> {
+        self.monitors.iter().find_map(|m| {
+            m.desktops().iter().find_map(|d| {
+                d.tree()
+                    .find_id(id)
+                    .map(|n| Coordinates::new(m.id(), d.id(), Some(n)))
+            })
+        })
+    }
+
+    /// Find the [`Coordinates`] of the [`Desktop`] with the given ID, pointing
+    /// to its focused [`Node`]
+    pub(crate) fn locate_desktop(&self, id: Xid) -> Option<Coordinates> {
+        self.desktop(id)
+            .map(|(m, d)| Coordinates::new(m.id(), d.id(), d.focus()))
+    }
+
+    /// Return the area on the screen taken by the [`Node`] at the given
+    /// [`Coordinates`], or by the [`Monitor`] if there is no [`Node`]
+    pub(crate) fn rectangle(&self, loc: &Coordinates) -> Option<Rectangle> {
+        let monitor = self.monitor(loc.monitor)?;
+        let node = match loc.node {
+            Some(_) => self.node(loc)?,
+            None => return Some(monitor.rectangle()),
+        };
+
+        Some(node.client().map_or(node.rectangle(), |c| match c.get_state() {
+            ClientState::Tiled | ClientState::PsuedoTiled => *c.get_tiled_rectangle(),
+            ClientState::Floating => *c.get_floating_rectangle(),
+            ClientState::Fullscreen => monitor.rectangle(),
+        }))
+    }
+
+    /// Remove the [`Node`] at the given [`Coordinates`] along with its
+    /// descendants
+    ///
+    /// If the focused [`Node`] of the [`Desktop`] is removed, the most recently
+    /// focused [`Node`] that remains takes its place, or the first leaf
+    pub(crate) fn remove_node(&mut self, loc: &Coordinates) -> Vec<Node> {
+        self.remove_node_with(loc, Tree::remove)
+    }
+
+    /// Remove the [`Node`] at the given [`Coordinates`] like
+    /// [`remove_node`](Self::remove_node), adjusting its brother according to
+    /// the [`AutomaticScheme`] if `adjust` is true
+    pub(crate) fn remove_node_automatic(
+        &mut self,
+        loc: &Coordinates,
+        scheme: AutomaticScheme,
+        adjust: bool,
+    ) -> Vec<Node> {
+        self.remove_node_with(loc, |tree, id| tree.remove_automatic(id, scheme, adjust))
+    }
+
+    /// Remove a [`Node`] from its [`Tree`] with `remove`, then prune the
+    /// [`History`] and fix the focus of the [`Desktop`]
+    fn remove_node_with(
+        &mut self,
+        loc: &Coordinates,
+        remove: impl FnOnce(&mut Tree, NodeId) -> Vec<Node>,
+    ) -> Vec<Node> {
+        let removed = match (loc.node, self.desktop_mut(loc.desktop)) {
+            (Some(id), Some(desktop)) => remove(desktop.tree_mut(), id),
+            _ => return vec![],
+        };
+        self.prune_history();
+        self.update_sticky_counts();
+
+        let focus = self
+            .history
+            .find_newest(|h| h.desktop == loc.desktop && h.node.is_some())
+            .and_then(|h| h.node);
+        if let Some(desktop) = self.desktop_mut(loc.desktop) {
+            if desktop.focus().map_or(false, |f| desktop.tree().get(f).is_none()) {
+                let first = desktop.tree().leaves().next();
+                desktop.set_focus(focus.or(first));
+            }
+        }
+
+        removed
+    }
+
+    /// Change the [`ClientState`] of the [`Client`] at the given
+    /// [`Coordinates`], updating which [`Node`]s take space in the layout.
+    /// Return the previous [`ClientState`] if it changed
+    pub(crate) fn change_client_state(
+        &mut self,
+        loc: &Coordinates,
+        state: ClientState,
+    ) -> Option<ClientState> {
+        let client = self.node_mut(loc)?.client_mut()?;
+        let previous = *client.get_state();
+        if !client.change_state(state) {
+            return None;
+        }
+
+        if let Some(desktop) = self.desktop_mut(loc.desktop) {
+            desktop.tree_mut().update_vacancy();
+        }
+        Some(previous)
+    }
+
+    /// Hide or show the [`Node`] at the given [`Coordinates`]
+    ///
+    /// If the focused [`Node`] of the [`Desktop`] is hidden, the most recently
+    /// focused [`Node`] that is visible takes its place, or the first visible
+    /// leaf
+    pub(crate) fn set_hidden(&mut self, loc: &Coordinates, hidden: bool) {
+        let id = match (loc.node, self.node_mut(loc)) {
+            (Some(id), Some(node)) => {
+                node.set_hidden(hidden);
+                id
+            },
+            _ => return,
+        };
+        if !hidden {
+            return;
+        }
+
+        let (_, desktop) = match self.desktop(loc.desktop) {
+            Some(desktop) => desktop,
+            None => return,
+        };
+        let tree = desktop.tree();
+        if !desktop.focus().map_or(false, |f| tree.is_descendant(f, id)) {
+            return;
+        }
+
+        let visible = |n: NodeId| {
+            !tree.is_descendant(n, id) && tree.get(n).map_or(false, |n| !n.is_hidden())
+        };
+        let focus = self
+            .history
+            .find_newest(|h| h.desktop == loc.desktop && h.node.map_or(false, visible))
+            .and_then(|h| h.node)
+            .or_else(|| tree.leaves().find(|&n| visible(n)));
+        if let Some(desktop) = self.desktop_mut(loc.desktop) {
+            desktop.set_focus(focus);
+        }
+    }
+
+    /// Remove the [`History`] entries of [`Monitor`]s, [`Desktop`]s and
+    /// [`Node`]s that no longer exist, and update the [`Monitor`] of the
+    /// [`Desktop`]s that were moved
+    pub(crate) fn prune_history(&mut self) {
+        let monitors = &self.monitors;
+        self.history.retain(|loc| {
+            let (monitor, desktop) = monitors
+                .iter()
+                .find_map(|m| m.desktop(loc.desktop).map(|d| (m, d)))?;
+            if loc.node.map_or(false, |n| desktop.tree().get(n).is_none()) {
+                return None;
+            }
+            Some(Coordinates::new(monitor.id(), loc.desktop, loc.node))
+        });
+    }
+
+    /// Return the focus [`History`]
+    pub(crate) const fn history(&self) -> &History {
+        &self.history
+    }
+
+    /// Return the focus [`History`] mutably
+    pub(crate) fn history_mut(&mut self) -> &mut History {
+        &mut self.history
+    }
+
+    /// Return the [`Tightness`] used to find a [`Node`] in a direction
+    pub(crate) const fn tightness(&self) -> Tightness {
+        self.tightness
+    }
+
+    /// Return the [`Window`] of every leaf, in tree order
+    pub(crate) fn windows(&self) -> Vec<Window> {
+        let mut windows = vec![];
+        for desktop in self.monitors.iter().flat_map(Monitor::desktops) {
+            let tree = desktop.tree();
+            windows.extend(
+                tree.leaves()
+                    .filter_map(|id| tree.get(id).and_then(Node::client))
+                    .map(|c| *c.get_window()),
+            );
+        }
+        windows
+    }
+
+    // ========================== Persistence =========================
+
+    /// Serialize the [`Monitor`]s, their [`Desktop`]s and trees, and the focus
+    /// [`History`] into JSON
+    pub(crate) fn dump(&self) -> Result<String> {
+        serde_json::to_string_pretty(self).context("failed to serialize the state")
+    }
+
+    /// Record the [`Window`] of each running scratchpad in the next dump
+    pub(crate) fn set_scratchpads(&mut self, scratchpads: BTreeMap<String, Window>) {
+        self.scratchpads = scratchpads;
+    }
+
+    /// Take the [`Window`]s of the scratchpads recorded in a dump
+    pub(crate) fn take_scratchpads(&mut self) -> BTreeMap<String, Window> {
+        mem::take(&mut self.scratchpads)
+    }
+
+    /// Rebuild a [`State`] written by [`dump`](Self::dump)
+    ///
+    /// The feedback [`Window`]s of the [`Presel`](crate::tree::Presel)s don't
+    /// outlive the connection that created them, so they are forgotten
+    pub(crate) fn load(json: &str) -> Result<Self> {
+        let mut state: Self =
+            serde_json::from_str(json).context("failed to deserialize the state")?;
+
+        for monitor in &mut state.monitors {
+            for desktop in monitor.desktops_mut() {
+                let tree = desktop.tree_mut();
+                for id in tree.preselected().collect::<Vec<_>>() {
+                    if let Some(presel) = tree.presel_mut(id) {
+                        presel.set_feedback(x11rb::NONE);
+                    }
+                }
+            }
+        }
+        state.prune_history();
+
+        Ok(state)
+    }
+
+    /// Return the [`Window`] of every swallowed terminal
+    pub(crate) fn producers(&self) -> Vec<Window> {
+        let mut producers = vec![];
+        for desktop in self.monitors.iter().flat_map(Monitor::desktops) {
+            let tree = desktop.tree();
+            producers.extend(
+                tree.leaves()
+                    .filter_map(|id| tree.get(id).and_then(Node::client))
+                    .filter_map(Client::producer)
+                    .map(|p| *p.get_window()),
+            );
+        }
+        producers
+    }
+
+    /// Forget a swallowed terminal whose [`Window`] went away, returning
+    /// whether it was found
+    pub(crate) fn forget_producer(&mut self, window: Window) -> bool {
+        for monitor in &mut self.monitors {
+            for desktop in monitor.desktops_mut() {
+                let tree = desktop.tree_mut();
+                for id in tree.leaves().collect::<Vec<_>>() {
+                    if let Some(client) = tree.get_mut(id).and_then(Node::client_mut) {
+                        if client.producer().map_or(false, |p| *p.get_window() == window) {
+                            client.take_producer();
+                            return true;
+                        }
+                    }
+                }
+            }
+        }
+
+        false
+    }
+
+    /// Remove the leaves whose [`Window`] doesn't satisfy `keep`, returning
+    /// the removed [`Node`]s
+    pub(crate) fn retain_windows(&mut self, mut keep: impl FnMut(Window) -> bool) -> Vec<Node> {
+        let mut gone = vec![];
+        for monitor in &self.monitors {
+            for desktop in monitor.desktops() {
+                let tree = desktop.tree();
+                for id in tree.leaves() {
+                    if tree
+                        .get(id)
+                        .and_then(Node::client)
+                        .map_or(false, |c| !keep(*c.get_window()))
+                    {
+                        gone.push(Coordinates::new(monitor.id(), desktop.id(), Some(id)));
+                    }
+                }
+            }
+        }
+
+        gone.iter().flat_map(|loc| self.remove_node(loc)).collect()
+    }
+
+    /// Match the [`Monitor`]s with the ones that are connected, by name
+    ///
+    /// Matched [`Monitor`]s take the geometry of the connected ones. The
+    /// [`Desktop`]s of the others are moved to the first matched [`Monitor`],
+    /// and connected [`Monitor`]s that weren't matched are added as they are
+    pub(crate) fn merge_monitors(&mut self, mut connected: Vec<Monitor>) {
+        let mut orphans = vec![];
+
+        for monitor in &mut self.monitors {
+            match connected.iter().position(|c| c.name() == monitor.name()) {
+                Some(idx) => monitor.set_rectangle(connected.remove(idx).rectangle()),
+                None => orphans.push(monitor.id()),
+            }
+        }
+
+        for monitor in connected {
+            self.add_monitor(monitor);
+        }
+
+        let target = match self
+            .monitors
+            .iter()
+            .find(|m| !orphans.contains(&m.id()))
+            .map(Monitor::id)
+        {
+            Some(target) => target,
+            None => return,
+        };
+        for id in orphans {
+            let desktops = self
+                .remove_monitor(id)
+                .map(|mut m| {
+                    let ids = m.desktops().iter().map(Desktop::id).collect::<Vec<_>>();
+                    ids.into_iter()
+                        .filter_map(|d| m.remove_desktop(d))
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default();
+
+            if let Some(monitor) = self.monitor_mut(target) {
+                for desktop in desktops {
+                    monitor.add_desktop(desktop);
+                }
+            }
+        }
+        self.prune_history();
+        self.update_sticky_counts();
+    }
+
+    /// Count the sticky [`Node`]s of every [`Monitor`] again, after they were
+    /// added, removed or moved
+    pub(crate) fn update_sticky_counts(&mut self) {
+        for monitor in &mut self.monitors {
+            monitor.update_sticky_count();
+        }
+    }
+}
+
+/// Tests for dumping and loading the [`State`]
+#[cfg(test)]
+mod tests {
+    use super::State;
+    use crate::{
+        core::{ChildPolarity, Tightness},
+        geometry::{Padding, Rectangle},
+        test_utils::leaf,
+        tree::{Coordinates, Desktop, Monitor, Node, SplitType},
+    };
+    use std::collections::BTreeMap;
+
+    /// Create a [`State`] with one [`Monitor`] holding the windows `1` and `2`
+    /// on the [`Desktop`] `10`, and an empty [`Desktop`] `11`
+    fn state() -> State {
+        let mut desktop = Desktop::new(String::from("one"), 10, Padding::default(), 0, 0);
+        let tree = desktop.tree_mut();
+        let a = tree.insert_root(leaf(1)).unwrap();
+        let mut parent = Node::new(100, None, 0.3);
+        parent.set_type(SplitType::Horizontal);
+        let b = tree
+            .insert_at(a, leaf(2), parent, ChildPolarity::Second)
+            .unwrap();
+        desktop.set_focus(Some(b));
+
+        let mut monitor = Monitor::new(
+            String::from("HDMI-0"),
+            1,
+            0,
+            Rectangle::new(0, 0, 1920, 1080),
+        );
+        monitor.add_desktop(desktop);
+        monitor.add_desktop(Desktop::new(String::from("two"), 11, Padding::default(), 0, 0));
+
+        let mut state = State::new(Tightness::High);
+        state.add_monitor(monitor);
+        state.history_mut().add(Coordinates::new(1, 10, Some(a)));
+        state.history_mut().add(Coordinates::new(1, 10, Some(b)));
+        state
+    }
+
+    #[test]
+    fn dump_and_load() {
+        let mut state = state();
+        state.set_scratchpads(BTreeMap::from([(String::from("term"), 2)]));
+        let mut loaded = State::load(&state.dump().unwrap()).unwrap();
+
+        assert_eq!(loaded.windows(), vec![1, 2]);
+        assert_eq!(loaded.focused(), state.focused());
+        assert_eq!(
+            loaded.history().iter().collect::<Vec<_>>(),
+            state.history().iter().collect::<Vec<_>>()
+        );
+
+        let (_, desktop) = loaded.desktop(10).unwrap();
+        let root = desktop.tree().get(desktop.tree().root().unwrap()).unwrap();
+        assert_eq!(root.id(), 100);
+        assert_eq!(root.split_type(), SplitType::Horizontal);
+        assert!((root.split_ratio() - 0.3).abs() < f64::EPSILON);
+
+        assert_eq!(loaded.take_scratchpads().get("term"), Some(&2));
+        assert!(loaded.take_scratchpads().is_empty());
+        assert!(State::load("{}").is_err());
+    }
+
+    #[test]
+    fn retain_windows() {
+        let mut state = state();
+        let removed = state.retain_windows(|window| window != 2);
+
+        assert_eq!(removed.first().map(Node::id), Some(2));
+        assert_eq!(state.windows(), vec![1]);
+        assert_eq!(state.focused().and_then(|f| state.node(&f)).map(Node::id), Some(1));
+        assert_eq!(state.history().len(), 1);
+    }
+
+    #[test]
+    fn merge_monitors() {
+        let mut state = state();
+        let mut unplugged = Monitor::new(String::from("DP-1"), 2, 0, Rectangle::default());
+        unplugged.add_desktop(Desktop::new(String::from("three"), 12, Padding::default(), 0, 0));
+        state.add_monitor(unplugged);
+
+        let mut hdmi = Monitor::new(String::from("HDMI-0"), 5, 0, Rectangle::new(0, 0, 800, 600));
+        hdmi.add_desktop(Desktop::new(String::from("fresh"), 50, Padding::default(), 0, 0));
+        let vga = Monitor::new(String::from("VGA-0"), 6, 0, Rectangle::new(800, 0, 800, 600));
+        state.merge_monitors(vec![hdmi, vga]);
+
+        let names = state.monitors().iter().map(Monitor::name).collect::<Vec<_>>();
+        assert_eq!(names, vec!["HDMI-0", "VGA-0"]);
+
+        let monitor = state.monitor(1).unwrap();
+        assert_eq!(monitor.rectangle(), Rectangle::new(0, 0, 800, 600));
+        let desktops = monitor.desktops().iter().map(Desktop::id).collect::<Vec<_>>();
+        assert_eq!(desktops, vec![10, 11, 12]);
+        assert_eq!(state.windows(), vec![1, 2]);
+    }
+
+    #[test]
+    fn swallowing() {
+        let mut state = state();
+        let terminal = state.locate_window(1).unwrap();
+        let client = state.node_mut(&terminal).and_then(Node::client_mut).unwrap();
+        client.set_pid(Some(42));
+        assert_eq!(state.locate_client(|c| *c.get_pid() == Some(42)), Some(terminal));
+        assert_eq!(state.locate_client(|c| *c.get_pid() == Some(7)), None);
+
+        let node = state.node_mut(&terminal).unwrap();
+        let producer = node.replace_client(leaf(3).client().cloned().unwrap()).unwrap();
+        node.client_mut().unwrap().swallow(producer);
+        assert_eq!(node.id(), 3);
+        assert_eq!(state.windows(), vec![3, 2]);
+        assert_eq!(state.producers(), vec![1]);
+        assert_eq!(state.locate_window(3), Some(terminal));
+
+        let loaded = State::load(&state.dump().unwrap()).unwrap();
+        assert_eq!(loaded.producers(), vec![1]);
+
+        assert!(state.forget_producer(1));
+        assert!(!state.forget_producer(1));
+        assert!(state.producers().is_empty());
+    }
+
+    #[test]
+    fn hiding() {
+        let mut state = state();
+        let a = state.locate_window(1).unwrap();
+        let b = state.locate_window(2).unwrap();
+
+        // Hiding the focused node falls back to the newest visible one
+        state.set_hidden(&b, true);
+        assert!(state.node(&b).unwrap().is_hidden());
+        assert_eq!(state.focused(), Some(a));
+
+        state.set_hidden(&b, false);
+        state.history_mut().add(b);
+        state.desktop_mut(10).unwrap().set_focus(b.node);
+        state.set_hidden(&a, true);
+        assert_eq!(state.focused(), Some(b));
+
+        // Nothing is focused once every node is hidden
+        state.set_hidden(&b, true);
+        assert_eq!(state.focused(), Some(Coordinates::new(1, 10, None)));
+    }
+
+    #[test]
+    fn sticky_count() {
+        let mut state = state();
+        let a = state.locate_window(1).unwrap();
+        let b = state.locate_window(2).unwrap();
+        state.node_mut(&a).unwrap().set_sticky(true);
+        state.node_mut(&b).unwrap().set_sticky(true);
+        state.update_sticky_counts();
+        assert_eq!(state.monitor(1).unwrap().sticky_count(), 2);
+
+        state.remove_node(&a);
+        assert_eq!(state.monitor(1).unwrap().sticky_count(), 1);
+
+        let loaded = State::load(&state.dump().unwrap()).unwrap();
+        assert_eq!(loaded.monitor(1).unwrap().sticky_count(), 1);
+    }
+}
@@ -1,15 +1,748 @@
 //! Information about the `fifo`
+//!
+//! Subscribers receive the [`Event`]s they asked for, one line per [`Event`],
+//! either over the socket they subscribed with or through a named FIFO
 
 #![allow(clippy::missing_docs_in_private_items)]
 
+use crate::{
+    core::{LayoutType, Xid},
+    geometry::Rectangle,
+    messages::Reply,
+    monitor::client::ClientState,
+    state::State,
+    tree::{Monitor, Node},
+    x::stream::Stream,
+};
 use anyhow::{Context, Result};
+use bitflags::bitflags;
+use nix::{sys::stat::Mode, unistd::mkfifo};
+use std::{
+    env,
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{ErrorKind, Write},
+    os::unix::fs::OpenOptionsExt,
+    path::PathBuf,
+    process,
+    str::FromStr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use tern::t;
+use thiserror::Error;
 
-#[derive(Debug, Clone)]
+/// Number of FIFOs created, used to give each one a unique name
+static FIFO_COUNT: AtomicUsize = AtomicUsize::new(0);
+/// Most bytes kept for a FIFO whose reader is slow or not there yet. The lines
+/// that don't fit are skipped
+const FIFO_BUFFER_SIZE: usize = 1 << 16;
+
+/// Errors of a subscription
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub(crate) enum SubscribeError {
+    /// The name of an event is not known
+    #[error("unknown event '{0}'")]
+    UnknownEvent(String),
+}
+
+// ============================== Events ==============================
+
+bitflags! {
+    /// The [`Event`]s a [`Subscriber`] is interested in
+    pub(crate) struct SubscriberMask: u32 {
+        /// The report line
+        const REPORT = 1 << 0;
+        /// A monitor was added
+        const MONITOR_ADD = 1 << 1;
+        /// A monitor was removed
+        const MONITOR_REMOVE = 1 << 2;
+        /// The geometry of a monitor changed
+        const MONITOR_GEOMETRY = 1 << 3;
+        /// A desktop was focused
+        const DESKTOP_FOCUS = 1 << 4;
+        /// A desktop was added
+        const DESKTOP_ADD = 1 << 5;
+        /// A desktop was renamed
+        const DESKTOP_RENAME = 1 << 6;
+        /// The layout of a desktop changed
+        const DESKTOP_LAYOUT = 1 << 7;
+        /// A node was added
+        const NODE_ADD = 1 << 8;
+        /// A node was removed
+        const NODE_REMOVE = 1 << 9;
+        /// A node was focused
+        const NODE_FOCUS = 1 << 10;
+        /// The state of a node changed
+        const NODE_STATE = 1 << 11;
+        /// The geometry of a node changed
+        const NODE_GEOMETRY = 1 << 12;
+
+        /// Every monitor event
+        const MONITOR = Self::MONITOR_ADD.bits
+            | Self::MONITOR_REMOVE.bits
+            | Self::MONITOR_GEOMETRY.bits;
+        /// Every desktop event
+        const DESKTOP = Self::DESKTOP_FOCUS.bits
+            | Self::DESKTOP_ADD.bits
+            | Self::DESKTOP_RENAME.bits
+            | Self::DESKTOP_LAYOUT.bits;
+        /// Every node event
+        const NODE = Self::NODE_ADD.bits
+            | Self::NODE_REMOVE.bits
+            | Self::NODE_FOCUS.bits
+            | Self::NODE_STATE.bits
+            | Self::NODE_GEOMETRY.bits;
+        /// Every event
+        const ALL = Self::REPORT.bits | Self::MONITOR.bits | Self::DESKTOP.bits | Self::NODE.bits;
+    }
+}
+
+impl SubscriberMask {
+    /// Combine the masks of the given event names. Without any, only the
+    /// report is subscribed to
+    pub(crate) fn parse<S: AsRef<str>>(names: &[S]) -> Result<Self, SubscribeError> {
+        if names.is_empty() {
+            return Ok(Self::REPORT);
+        }
+
+        names
+            .iter()
+            .try_fold(Self::empty(), |mask, name| Ok(mask | name.as_ref().parse::<Self>()?))
+    }
+}
+
+impl FromStr for SubscriberMask {
+    type Err = SubscribeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "all" => Self::ALL,
+            "report" => Self::REPORT,
+            "monitor" => Self::MONITOR,
+            "desktop" => Self::DESKTOP,
+            "node" => Self::NODE,
+            "monitor_add" => Self::MONITOR_ADD,
+            "monitor_remove" => Self::MONITOR_REMOVE,
+            "monitor_geometry" => Self::MONITOR_GEOMETRY,
+            "desktop_focus" => Self::DESKTOP_FOCUS,
+            "desktop_add" => Self::DESKTOP_ADD,
+            "desktop_rename" => Self::DESKTOP_RENAME,
+            "desktop_layout" => Self::DESKTOP_LAYOUT,
+            "node_add" => Self::NODE_ADD,
+            "node_remove" => Self::NODE_REMOVE,
+            "node_focus" => Self::NODE_FOCUS,
+            "node_state" => Self::NODE_STATE,
+            "node_geometry" => Self::NODE_GEOMETRY,
+            _ => return Err(SubscribeError::UnknownEvent(s.to_owned())),
+        })
+    }
+}
+
+/// Something that happened in the window manager. The IDs are the ones of the
+/// X-Server, and each [`Event`] is sent as a line formatted like `bspwm` does
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Event {
+    /// The report line
+    Report(String),
+    /// A [`Monitor`](crate::tree::Monitor) was added
+    MonitorAdd {
+        monitor:   Xid,
+        name:      String,
+        rectangle: Rectangle,
+    },
+    /// A [`Monitor`](crate::tree::Monitor) was removed
+    MonitorRemove { monitor: Xid },
+    /// The geometry of a [`Monitor`](crate::tree::Monitor) changed
+    MonitorGeometry { monitor: Xid, rectangle: Rectangle },
+    /// A [`Desktop`](crate::tree::Desktop) was focused
+    DesktopFocus { monitor: Xid, desktop: Xid },
+    /// A [`Desktop`](crate::tree::Desktop) was added
+    DesktopAdd {
+        monitor: Xid,
+        desktop: Xid,
+        name:    String,
+    },
+    /// A [`Desktop`](crate::tree::Desktop) was renamed
+    DesktopRename {
+        monitor: Xid,
+        desktop: Xid,
+        old:     String,
+        new:     String,
+    },
+    /// The layout of a [`Desktop`](crate::tree::Desktop) changed
+    DesktopLayout {
+        monitor: Xid,
+        desktop: Xid,
+        layout:  LayoutType,
+    },
+    /// A [`Node`](crate::tree::Node) was inserted at the `parent` node, which
+    /// is 0 if the tree was empty
+    NodeAdd {
+        monitor: Xid,
+        desktop: Xid,
+        parent:  Xid,
+        node:    Xid,
+    },
+    /// A [`Node`](crate::tree::Node) was removed
+    NodeRemove { monitor: Xid, desktop: Xid, node: Xid },
+    /// A [`Node`](crate::tree::Node) was focused
+    NodeFocus { monitor: Xid, desktop: Xid, node: Xid },
+    /// The [`ClientState`] of a [`Node`](crate::tree::Node) was turned on or
+    /// off
+    NodeState {
+        monitor: Xid,
+        desktop: Xid,
+        node:    Xid,
+        state:   ClientState,
+        on:      bool,
+    },
+    /// The geometry of a [`Node`](crate::tree::Node) changed
+    NodeGeometry {
+        monitor:   Xid,
+        desktop:   Xid,
+        node:      Xid,
+        rectangle: Rectangle,
+    },
+}
+
+impl Event {
+    /// Return the [`SubscriberMask`] matching the [`Event`]
+    pub(crate) const fn mask(&self) -> SubscriberMask {
+        match self {
+            Self::Report(_) => SubscriberMask::REPORT,
+            Self::MonitorAdd { .. } => SubscriberMask::MONITOR_ADD,
+            Self::MonitorRemove { .. } => SubscriberMask::MONITOR_REMOVE,
+            Self::MonitorGeometry { .. } => SubscriberMask::MONITOR_GEOMETRY,
+            Self::DesktopFocus { .. } => SubscriberMask::DESKTOP_FOCUS,
+            Self::DesktopAdd { .. } => SubscriberMask::DESKTOP_ADD,
+            Self::DesktopRename { .. } => SubscriberMask::DESKTOP_RENAME,
+            Self::DesktopLayout { .. } => SubscriberMask::DESKTOP_LAYOUT,
+            Self::NodeAdd { .. } => SubscriberMask::NODE_ADD,
+            Self::NodeRemove { .. } => SubscriberMask::NODE_REMOVE,
+            Self::NodeFocus { .. } => SubscriberMask::NODE_FOCUS,
+            Self::NodeState { .. } => SubscriberMask::NODE_STATE,
+            Self::NodeGeometry { .. } => SubscriberMask::NODE_GEOMETRY,
+        }
+    }
+}
+
+/// Format a [`Rectangle`] as `WxH+X+Y`
+fn geometry(rect: &Rectangle) -> String {
+    format!(
+        "{}x{}+{}+{}",
+        rect.dimension.width, rect.dimension.height, rect.point.x, rect.point.y
+    )
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Report(line) => write!(f, "{}", line),
+            Self::MonitorAdd { monitor, name, rectangle } => write!(
+                f,
+                "monitor_add 0x{:08X} {} {}",
+                monitor,
+                name,
+                geometry(rectangle)
+            ),
+            Self::MonitorRemove { monitor } => write!(f, "monitor_remove 0x{:08X}", monitor),
+            Self::MonitorGeometry { monitor, rectangle } =>
+                write!(f, "monitor_geometry 0x{:08X} {}", monitor, geometry(rectangle)),
+            Self::DesktopFocus { monitor, desktop } =>
+                write!(f, "desktop_focus 0x{:08X} 0x{:08X}", monitor, desktop),
+            Self::DesktopAdd { monitor, desktop, name } =>
+                write!(f, "desktop_add 0x{:08X} 0x{:08X} {}", monitor, desktop, name),
+            Self::DesktopRename { monitor, desktop, old, new } => write!(
+                f,
+                "desktop_rename 0x{:08X} 0x{:08X} {} {}",
+                monitor, desktop, old, new
+            ),
+            Self::DesktopLayout { monitor, desktop, layout } => write!(
+                f,
+                "desktop_layout 0x{:08X} 0x{:08X} {}",
+                monitor,
+                desktop,
+                match layout {
+                    LayoutType::Tiled => "tiled",
+                    LayoutType::Monocle => "monocle",
+                }
+            ),
+            Self::NodeAdd { monitor, desktop, parent, node } => write!(
+                f,
+                "node_add 0x{:08X} 0x{:08X} 0x{:08X} 0x{:08X}",
+                monitor, desktop, parent, node
+            ),
+            Self::NodeRemove { monitor, desktop, node } =>
+                write!(f, "node_remove 0x{:08X} 0x{:08X} 0x{:08X}", monitor, desktop, node),
+            Self::NodeFocus { monitor, desktop, node } =>
+                write!(f, "node_focus 0x{:08X} 0x{:08X} 0x{:08X}", monitor, desktop, node),
+            Self::NodeState { monitor, desktop, node, state, on } => write!(
+                f,
+                "node_state 0x{:08X} 0x{:08X} 0x{:08X} {} {}",
+                monitor,
+                desktop,
+                node,
+                match state {
+                    ClientState::Tiled => "tiled",
+                    ClientState::PsuedoTiled => "pseudo_tiled",
+                    ClientState::Floating => "floating",
+                    ClientState::Fullscreen => "fullscreen",
+                },
+                t!(*on ? "on" : "off")
+            ),
+            Self::NodeGeometry { monitor, desktop, node, rectangle } => write!(
+                f,
+                "node_geometry 0x{:08X} 0x{:08X} 0x{:08X} {}",
+                monitor,
+                desktop,
+                node,
+                geometry(rectangle)
+            ),
+        }
+    }
+}
+
+// ============================== Report ==============================
+
+/// Return the report line, which describes every [`Monitor`] like `bspwm`
+/// does. It starts with `prefix`, and its fields are separated by colons:
+///
+/// - `M<name>`/`m<name>`: a focused/unfocused [`Monitor`]
+/// - `O`/`o`, `F`/`f`, `U`/`u` followed by the name: an occupied, free or urgent
+///   [`Desktop`](crate::tree::Desktop), in uppercase if it is the one shown on
+///   its [`Monitor`]
+/// - `L<T|M>`: the layout of the shown [`Desktop`](crate::tree::Desktop)
+/// - `T<T|P|F|=|@>`: the state of its focused [`Node`], `@` for an internal one
+/// - `G<flags>`: the flags of the focused [`Node`], among `S`ticky,
+///   `P`rivate, `L`ocked and `M`arked
+pub(crate) fn report(state: &State, prefix: &str) -> String {
+    let focused = state.focused_monitor().map(Monitor::id);
+    let mut fields = vec![];
+
+    for monitor in state.monitors() {
+        let active = Some(monitor.id()) == focused;
+        fields.push(format!("{}{}", t!(active ? 'M' : 'm'), monitor.name()));
+
+        for desktop in monitor.desktops() {
+            let kind = if desktop.is_urgent() {
+                'u'
+            } else if desktop.is_occupied() {
+                'o'
+            } else {
+                'f'
+            };
+            let shown = monitor.desk_id() == Some(desktop.id());
+            fields.push(format!(
+                "{}{}",
+                t!(shown ? kind.to_ascii_uppercase() : kind),
+                desktop.name()
+            ));
+        }
+
+        let desktop = match monitor.desk() {
+            Some(desktop) => desktop,
+            None => continue,
+        };
+        fields.push(format!(
+            "L{}",
+            match desktop.layout() {
+                LayoutType::Tiled => 'T',
+                LayoutType::Monocle => 'M',
+            }
+        ));
+
+        if let Some(node) = desktop.focus().and_then(|f| desktop.tree().get(f)) {
+            let state = node.client().map_or('@', |c| match c.get_state() {
+                ClientState::Tiled => 'T',
+                ClientState::PsuedoTiled => 'P',
+                ClientState::Floating => 'F',
+                ClientState::Fullscreen => '=',
+            });
+            let flags = [
+                (node.is_sticky(), 'S'),
+                (node.is_private(), 'P'),
+                (node.is_locked(), 'L'),
+                (node.is_marked(), 'M'),
+            ]
+            .iter()
+            .filter_map(|&(on, flag)| on.then(|| flag))
+            .collect::<String>();
+
+            fields.push(format!("T{}", state));
+            fields.push(format!("G{}", flags));
+        }
+    }
+
+    format!("{}{}", prefix, fields.join(":"))
+}
+
+// ============================ Subscriber ============================
+
+/// Where the [`Event`]s of a [`Subscriber`] are written to
+enum Sink {
+    /// The socket the subscription was made on. Each line is sent as a
+    /// successful [`Reply`]
+    Stream(Stream),
+    /// A named FIFO, written to line by line
+    Fifo(Fifo),
+}
+
+/// A named FIFO, opened for writing once a reader shows up and removed when it
+/// is dropped
+struct Fifo {
+    /// Path of the FIFO
+    path:    PathBuf,
+    /// The write end, once a reader opened the FIFO
+    file:    Option<File>,
+    /// The lines that couldn't be written yet
+    pending: Vec<u8>,
+}
+
+impl Fifo {
+    /// Create a new named FIFO in the temporary directory
+    fn new() -> Result<Self> {
+        let path = env::temp_dir().join(format!(
+            "lwm-fifo-{}-{}",
+            process::id(),
+            FIFO_COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        mkfifo(&path, Mode::S_IRUSR | Mode::S_IWUSR)
+            .context(format!("failed to create FIFO {}", path.display()))?;
+
+        Ok(Self { path, file: None, pending: vec![] })
+    }
+
+    /// Write a line, or keep it until the reader shows up or has room for it.
+    /// Returns whether the reader is still there or hasn't shown up yet
+    fn write_line(&mut self, line: &str) -> bool {
+        if self.pending.len() + line.len() < FIFO_BUFFER_SIZE {
+            self.pending.extend_from_slice(line.as_bytes());
+            self.pending.push(b'\n');
+        } else {
+            log::debug!("skipping an event, the reader of {} is too slow", self.path.display());
+        }
+
+        if self.file.is_none() {
+            self.file = match OpenOptions::new()
+                .write(true)
+                .custom_flags(libc::O_NONBLOCK)
+                .open(&self.path)
+            {
+                Ok(file) => Some(file),
+                // Nobody opened the FIFO for reading yet
+                Err(e) if e.raw_os_error() == Some(libc::ENXIO) => return true,
+                Err(e) => {
+                    log::warn!("failed to open FIFO {}: {}", self.path.display(), e);
+                    return false;
+                },
+            };
+        }
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => return true,
+        };
+
+        while !self.pending.is_empty() {
+            match file.write(&self.pending) {
+                Ok(0) => return false,
+                Ok(written) => drop(self.pending.drain(..written)),
+                // The rest is written along with the next line
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return true,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {},
+                // `EPIPE` once the reader is gone
+                Err(e) => {
+                    log::debug!("dropping the reader of {}: {}", self.path.display(), e);
+                    return false;
+                },
+            }
+        }
+
+        true
+    }
+}
+
+impl Drop for Fifo {
+    fn drop(&mut self) {
+        drop(fs::remove_file(&self.path));
+    }
+}
+
+/// A client that subscribed to [`Event`]s
+pub(crate) struct Subscriber {
+    /// Where the [`Event`]s are written to
+    sink:  Sink,
+    /// The [`Event`]s the [`Subscriber`] is interested in
+    field: SubscriberMask,
+    /// Number of [`Event`]s left to send, if bounded
+    count: Option<usize>,
+}
+
+impl Subscriber {
+    /// Create a [`Subscriber`] that receives its [`Event`]s on a [`Stream`]
+    pub(crate) const fn stream(stream: Stream, field: SubscriberMask, count: Option<usize>) -> Self {
+        Self { sink: Sink::Stream(stream), field, count }
+    }
+
+    /// Create a [`Subscriber`] that receives its [`Event`]s through a new
+    /// named FIFO, which can be found with [`fifo_path`](Self::fifo_path)
+    pub(crate) fn fifo(field: SubscriberMask, count: Option<usize>) -> Result<Self> {
+        Ok(Self { sink: Sink::Fifo(Fifo::new()?), field, count })
+    }
+
+    /// Return the path of the FIFO, if the [`Subscriber`] uses one
+    pub(crate) fn fifo_path(&self) -> Option<&PathBuf> {
+        match &self.sink {
+            Sink::Stream(_) => None,
+            Sink::Fifo(fifo) => Some(&fifo.path),
+        }
+    }
+
+    /// Is the [`Subscriber`] interested in any of the given [`SubscriberMask`]?
+    pub(crate) const fn wants(&self, mask: SubscriberMask) -> bool {
+        self.field.intersects(mask)
+    }
+
+    /// Send an [`Event`] if it is one of interest. Returns whether the
+    /// [`Subscriber`] should be kept
+    pub(crate) fn send(&mut self, event: &Event) -> bool {
+        if !self.wants(event.mask()) {
+            return true;
+        }
+
+        let line = event.to_string();
+        let sent = match &mut self.sink {
+            Sink::Stream(stream) => stream.send(&Reply::success(line)).unwrap_or(false),
+            Sink::Fifo(fifo) => fifo.write_line(&line),
+        };
+
+        match &mut self.count {
+            Some(count) => {
+                *count = count.saturating_sub(1);
+                sent && *count > 0
+            },
+            None => sent,
+        }
+    }
+}
+
+/// Every [`Subscriber`], in the order they subscribed
+#[derive(Default)]
 pub(crate) struct SubscriberList {
-    // file: Stream,
-    fifo_path: String,
-    field:     usize,
-    count:     usize,
-    prev:      Box<Self>,
-    next:      Box<Self>,
+    /// The [`Subscriber`]s
+    subscribers: Vec<Subscriber>,
+}
+
+impl SubscriberList {
+    /// Create an empty [`SubscriberList`]
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return the number of [`Subscriber`]s
+    pub(crate) fn len(&self) -> usize {
+        self.subscribers.len()
+    }
+
+    /// Are there no [`Subscriber`]s?
+    pub(crate) fn is_empty(&self) -> bool {
+        self.subscribers.is_empty()
+    }
+
+    /// Add a [`Subscriber`]
+    pub(crate) fn add(&mut self, subscriber: Subscriber) {
+        self.subscribers.push(subscriber);
+    }
+
+    /// Is any [`Subscriber`] interested in the given [`SubscriberMask`]?
+    pub(crate) fn wants(&self, mask: SubscriberMask) -> bool {
+        self.subscribers.iter().any(|s| s.wants(mask))
+    }
+
+    /// Send an [`Event`] to every interested [`Subscriber`], dropping the ones
+    /// that are gone or have received their count
+    pub(crate) fn emit(&mut self, event: &Event) {
+        log::trace!("emitting event: {}", event);
+        let mut idx = 0;
+        while idx < self.subscribers.len() {
+            if self.subscribers[idx].send(event) {
+                idx += 1;
+            } else {
+                drop(self.subscribers.remove(idx));
+            }
+        }
+    }
+}
+
+/// Tests for the [`SubscriberMask`], the formatting of [`Event`]s, the
+/// [`report`] and the [`SubscriberList`]
+#[cfg(test)]
+mod tests {
+    use super::{report, Event, SubscribeError, Subscriber, SubscriberList, SubscriberMask};
+    use crate::{
+        core::{LayoutType, Tightness},
+        geometry::{Padding, Rectangle},
+        messages::{read_frame, Reply},
+        monitor::client::ClientState,
+        state::State,
+        test_utils::leaf,
+        tree::{Desktop, Monitor, Node},
+        x::stream::Stream,
+    };
+    use std::{
+        fs::OpenOptions,
+        io::{BufRead, BufReader},
+        os::unix::{fs::OpenOptionsExt, net::UnixStream},
+    };
+
+    /// Return a [`Event::NodeFocus`] for the given node
+    fn focus(node: u32) -> Event {
+        Event::NodeFocus { monitor: 1, desktop: 2, node }
+    }
+
+    #[test]
+    fn masks() {
+        let none: [&str; 0] = [];
+        assert_eq!(SubscriberMask::parse(&none), Ok(SubscriberMask::REPORT));
+        assert_eq!(
+            SubscriberMask::parse(&["node_add", "desktop"]),
+            Ok(SubscriberMask::NODE_ADD | SubscriberMask::DESKTOP)
+        );
+        assert!(SubscriberMask::ALL.contains(SubscriberMask::NODE | SubscriberMask::REPORT));
+        assert_eq!(
+            SubscriberMask::parse(&["node", "nodes"]),
+            Err(SubscribeError::UnknownEvent(String::from("nodes")))
+        );
+    }
+
+    #[test]
+    fn lines() {
+        assert_eq!(
+            Event::NodeAdd { monitor: 1, desktop: 2, parent: 0, node: 0x0040_0003 }.to_string(),
+            "node_add 0x00000001 0x00000002 0x00000000 0x00400003"
+        );
+        assert_eq!(
+            Event::NodeState {
+                monitor: 1,
+                desktop: 2,
+                node:    3,
+                state:   ClientState::PsuedoTiled,
+                on:      true,
+            }
+            .to_string(),
+            "node_state 0x00000001 0x00000002 0x00000003 pseudo_tiled on"
+        );
+        assert_eq!(
+            Event::MonitorGeometry { monitor: 1, rectangle: Rectangle::new(1920, 0, 1280, 1024) }
+                .to_string(),
+            "monitor_geometry 0x00000001 1280x1024+1920+0"
+        );
+        assert_eq!(Event::Report(String::from("WMHDMI-0:OI")).to_string(), "WMHDMI-0:OI");
+    }
+
+    #[test]
+    fn report_line() {
+        let mut one = Desktop::new(String::from("one"), 10, Padding::default(), 0, 0);
+        let id = one.tree_mut().insert_root(leaf(1)).unwrap();
+        one.set_focus(Some(id));
+
+        let mut two = Desktop::new(String::from("two"), 11, Padding::default(), 0, 0);
+        let id = two.tree_mut().insert_root(leaf(2)).unwrap();
+        two.tree_mut()
+            .get_mut(id)
+            .and_then(Node::client_mut)
+            .unwrap()
+            .set_urgent(true);
+
+        let mut left = Monitor::new(String::from("HDMI-0"), 1, 0, Rectangle::new(0, 0, 800, 600));
+        left.add_desktop(one);
+        left.add_desktop(two);
+        left.add_desktop(Desktop::new(String::from("three"), 12, Padding::default(), 0, 0));
+
+        let mut empty = Desktop::new(String::from("four"), 13, Padding::default(), 0, 0);
+        empty.set_layout(LayoutType::Monocle);
+        let mut right = Monitor::new(String::from("DP-1"), 2, 0, Rectangle::new(800, 0, 800, 600));
+        right.add_desktop(empty);
+
+        let mut state = State::new(Tightness::High);
+        state.add_monitor(left);
+        state.add_monitor(right);
+
+        assert_eq!(
+            report(&state, "W"),
+            "WMHDMI-0:Oone:utwo:fthree:LT:TT:G:mDP-1:Ffour:LM"
+        );
+
+        state.set_focus(2);
+        let loc = state.locate_window(1).unwrap();
+        state
+            .node_mut(&loc)
+            .and_then(Node::client_mut)
+            .unwrap()
+            .set_state(ClientState::Floating);
+        assert_eq!(
+            report(&state, ""),
+            "mHDMI-0:Oone:utwo:fthree:LT:TF:G:MDP-1:Ffour:LM"
+        );
+    }
+
+    #[test]
+    fn stream_count() {
+        let (wm, mut client) = UnixStream::pair().unwrap();
+        let mut list = SubscriberList::new();
+        list.add(Subscriber::stream(
+            Stream::new(wm),
+            SubscriberMask::NODE_FOCUS,
+            Some(2),
+        ));
+
+        list.emit(&Event::Report(String::from("ignored")));
+        list.emit(&focus(3));
+        assert_eq!(list.len(), 1);
+        list.emit(&focus(4));
+        assert!(list.is_empty());
+
+        let first: Reply = read_frame(&mut client).unwrap();
+        let second: Reply = read_frame(&mut client).unwrap();
+        assert!(first.is_success());
+        assert_eq!(first.payload, "node_focus 0x00000001 0x00000002 0x00000003");
+        assert_eq!(second.payload, "node_focus 0x00000001 0x00000002 0x00000004");
+        assert!(read_frame::<_, Reply>(&mut client).is_err());
+    }
+
+    #[test]
+    fn fifo() {
+        let mut list = SubscriberList::new();
+        let subscriber = Subscriber::fifo(SubscriberMask::NODE, None).unwrap();
+        let path = subscriber.fifo_path().unwrap().clone();
+        list.add(subscriber);
+
+        assert!(list.wants(SubscriberMask::NODE_FOCUS));
+        assert!(!list.wants(SubscriberMask::REPORT));
+        // The line is kept until a reader shows up
+        list.emit(&focus(5));
+        assert_eq!(list.len(), 1);
+
+        let fifo = OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(&path)
+            .unwrap();
+        let mut reader = BufReader::new(fifo);
+        list.emit(&focus(6));
+
+        let mut lines = String::new();
+        reader.read_line(&mut lines).unwrap();
+        reader.read_line(&mut lines).unwrap();
+        assert_eq!(
+            lines,
+            "node_focus 0x00000001 0x00000002 0x00000005\nnode_focus 0x00000001 0x00000002 \
+             0x00000006\n"
+        );
+
+        // The subscriber and its FIFO go away with the reader
+        drop(reader);
+        list.emit(&focus(7));
+        assert!(list.is_empty());
+        assert!(!path.exists());
+    }
 }
@@ -0,0 +1,18 @@
+//! Fixtures shared by the tests of several modules
+
+use crate::{monitor::client::Client, tree::Node};
+
+/// Create a leaf holding a [`Client`] with the given window
+pub(crate) fn leaf(window: u32) -> Node {
+    Node::new(
+        window,
+        Some(Client::new(
+            window,
+            format!("window{}", window),
+            String::from("class"),
+            String::from("instance"),
+            1,
+        )),
+        0.5,
+    )
+}
@@ -1,12 +1,21 @@
 //! Interacting with the [`Window`] tree
 
 use crate::{
-    core::{Direction, LayoutType, Output, Window, Xid},
-    geometry::{Padding, Rectangle},
-    monitor::client::Client,
+    core::{
+        AutomaticScheme, ChildPolarity, Direction, HistoryDir, Idx, LayoutType, Output,
+        Rotation, Window, Xid,
+    },
+    geometry::{Padding, Rectangle, Strut},
+    monitor::client::{Client, ClientState},
+    query::{desktop_from_desc, monitor_from_desc, node_from_desc, SelectorError},
+    state::State,
 };
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 use serde::{Deserialize, Serialize};
+use tern::t;
+
+/// Index of a [`Node`] within a [`Tree`]
+pub(crate) type NodeId = Idx;
 
 /// The type of [`Window`] split
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
@@ -17,6 +26,16 @@ pub(crate) enum SplitType {
     Vertical,
 }
 
+impl SplitType {
+    /// Return the [`SplitType`] perpendicular to this one
+    pub(crate) const fn opposite(self) -> Self {
+        match self {
+            Self::Horizontal => Self::Vertical,
+            Self::Vertical => Self::Horizontal,
+        }
+    }
+}
+
 /// Mode of splitting a [`Node`]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub(crate) enum SplitMode {
@@ -27,7 +46,7 @@ pub(crate) enum SplitMode {
 }
 
 /// Constraints given to the [`Node`]
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub(crate) struct Constraint {
     /// Minimum width
     min_width:  u16,
@@ -55,35 +74,274 @@ impl Presel {
             feedback:    x11rb::NONE,
         }
     }
+
+    /// Return the ratio of the preselection split
+    pub(crate) const fn split_ratio(&self) -> f32 {
+        self.split_ratio
+    }
+
+    /// Return the [`Direction`] of the preselection split
+    pub(crate) const fn split_dir(&self) -> Direction {
+        self.split_dir
+    }
+
+    /// Return the feedback [`Window`], which is [`x11rb::NONE`] if not created
+    pub(crate) const fn feedback(&self) -> Window {
+        self.feedback
+    }
+
+    /// Change the feedback [`Window`]
+    pub(crate) fn set_feedback(&mut self, feedback: Window) {
+        self.feedback = feedback;
+    }
+
+    /// Is the ratio strictly between 0 and 1?
+    ///
+    /// Ratios are checked once narrowed to an `f32`, which is what the
+    /// [`Presel`] holds, as a ratio just below 1 can round up to it. NaN is
+    /// refused as well
+    pub(crate) fn is_valid_ratio(ratio: f32) -> bool {
+        (0.0..1.0).contains(&ratio) && ratio > 0.0
+    }
+
+    /// Return the [`SplitType`] of the internal node created by this [`Presel`]
+    /// and the [`ChildPolarity`] of the node that is inserted
+    pub(crate) const fn split(&self) -> (SplitType, ChildPolarity) {
+        match self.split_dir {
+            Direction::West => (SplitType::Vertical, ChildPolarity::First),
+            Direction::East => (SplitType::Vertical, ChildPolarity::Second),
+            Direction::North => (SplitType::Horizontal, ChildPolarity::First),
+            Direction::South => (SplitType::Horizontal, ChildPolarity::Second),
+        }
+    }
 }
 
-/// Overall information about the X11 environment
-#[derive(Debug, Clone)]
+/// Location of a [`Node`] within the window manager
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub(crate) struct Coordinates {
-    /// Current [`Monitor`]
-    pub(crate) monitor: Monitor,
-    /// Current [`Desktop`]
-    pub(crate) desktop: Desktop,
-    /// Current [`Node`]
-    pub(crate) node:    Node,
+    /// ID of the [`Monitor`]
+    pub(crate) monitor: Xid,
+    /// ID of the [`Desktop`]
+    pub(crate) desktop: Xid,
+    /// The [`Node`] within the [`Desktop`]'s [`Tree`], if any
+    pub(crate) node:    Option<NodeId>,
 }
 
-/// History tracker about the X11 environment
-#[derive(Debug, Clone)]
-pub(crate) struct History {
-    /// Current [`Monitor`], [`Desktop`], and [`Node`]
+impl Coordinates {
+    /// Create new [`Coordinates`]
+    pub(crate) const fn new(monitor: Xid, desktop: Xid, node: Option<NodeId>) -> Self {
+        Self { monitor, desktop, node }
+    }
+}
+
+/// A single entry of the [`History`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct HistoryEntry {
+    /// The focused [`Monitor`], [`Desktop`], and [`Node`]
     loc:    Coordinates,
-    /// Is this the latest item in [`History`]? TODO: what's this?
+    /// Is this the most recent entry for this location?
     latest: bool,
-    /// Previous [`Monitor`], [`Desktop`], and [`Node`]
-    prev:   Box<Self>,
-    /// Next [`Monitor`], [`Desktop`], and [`Node`]
-    next:   Box<Self>,
+}
+
+/// Focus history of the window manager, from oldest to newest
+///
+/// The history is bounded: once its capacity is reached, the oldest entries
+/// are dropped
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct History {
+    /// Entries of the history
+    entries:  Vec<HistoryEntry>,
+    /// Starting point of history traversals. The newest entry if `None`
+    needle:   Option<usize>,
+    /// Maximum number of entries
+    capacity: usize,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::with_capacity(Self::CAPACITY)
+    }
+}
+
+impl History {
+    /// Default maximum number of entries
+    pub(crate) const CAPACITY: usize = 256;
+
+    /// Create a new, empty [`History`]
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a new, empty [`History`] holding at most `capacity` entries
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
+        Self {
+            entries: vec![],
+            needle: None,
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// Return the number of entries
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Is the [`History`] empty?
+    pub(crate) fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Record a newly focused location
+    ///
+    /// Older entries pointing to the same location are no longer considered
+    /// the latest, so that they are skipped when traversing
+    pub(crate) fn add(&mut self, loc: Coordinates) {
+        self.needle = None;
+
+        let same = |e: &HistoryEntry| match loc.node {
+            Some(_) => e.loc.desktop == loc.desktop && e.loc.node == loc.node,
+            None => e.loc.desktop == loc.desktop,
+        };
+
+        if self.entries.last().map_or(false, |e| same(e)) {
+            return;
+        }
+
+        for entry in self.entries.iter_mut().filter(|e| same(e)) {
+            entry.latest = false;
+        }
+
+        self.entries.push(HistoryEntry { loc, latest: true });
+
+        if self.entries.len() > self.capacity {
+            let excess = self.entries.len() - self.capacity;
+            self.entries.drain(..excess);
+        }
+    }
+
+    /// Remove every entry for which `keep` returns `None`, replacing the others
+    /// by the returned location
+    ///
+    /// This is used to prune the entries of removed [`Node`]s, [`Desktop`]s
+    /// and [`Monitor`]s, and to follow a [`Desktop`] moved to another
+    /// [`Monitor`]
+    pub(crate) fn retain(&mut self, mut keep: impl FnMut(&Coordinates) -> Option<Coordinates>) {
+        self.needle = None;
+        let mut entries: Vec<HistoryEntry> = Vec::with_capacity(self.entries.len());
+
+        for entry in &self.entries {
+            if let Some(loc) = keep(&entry.loc) {
+                // Consecutive entries can end up being the same
+                if entries.last().map_or(true, |e| e.loc != loc) {
+                    entries.push(HistoryEntry { loc, latest: false });
+                }
+            }
+        }
+
+        // Only the newest entry of a location is the latest
+        for idx in (0..entries.len()).rev() {
+            let loc = entries[idx].loc;
+            entries[idx].latest = !entries[idx + 1..].iter().any(|e| e.loc == loc);
+        }
+
+        self.entries = entries;
+    }
+
+    /// Return the index of the first entry in the given [`HistoryDir`]
+    /// matching `pred`
+    fn position(
+        &self,
+        dir: HistoryDir,
+        mut pred: impl FnMut(&Coordinates) -> bool,
+    ) -> Option<usize> {
+        let start = self
+            .needle
+            .or_else(|| self.entries.len().checked_sub(1))?;
+
+        let mut matches = |(_, e): &(usize, &HistoryEntry)| e.latest && pred(&e.loc);
+        let found = match dir {
+            HistoryDir::Older => self.entries[..=start]
+                .iter()
+                .enumerate()
+                .rev()
+                .find(&mut matches),
+            HistoryDir::Newer => self
+                .entries
+                .iter()
+                .enumerate()
+                .skip(start)
+                .find(&mut matches),
+        };
+
+        found.map(|(idx, _)| idx)
+    }
+
+    /// Find the first location in the given [`HistoryDir`] matching `pred`
+    ///
+    /// The search begins at the needle, which is the newest entry unless
+    /// moved by a traversal
+    pub(crate) fn find(
+        &self,
+        dir: HistoryDir,
+        pred: impl FnMut(&Coordinates) -> bool,
+    ) -> Option<Coordinates> {
+        self.position(dir, pred).map(|idx| self.entries[idx].loc)
+    }
+
+    /// Find the first location in the given [`HistoryDir`] matching `pred`,
+    /// and move the needle to it
+    ///
+    /// Focusing the returned location must not be recorded with
+    /// [`History::add`], so that repeated jumps keep going back in time
+    pub(crate) fn jump(
+        &mut self,
+        dir: HistoryDir,
+        pred: impl FnMut(&Coordinates) -> bool,
+    ) -> Option<Coordinates> {
+        let idx = self.position(dir, pred)?;
+        self.needle = Some(idx);
+        Some(self.entries[idx].loc)
+    }
+
+    /// Find the newest location matching `pred`
+    pub(crate) fn find_newest(
+        &self,
+        mut pred: impl FnMut(&Coordinates) -> bool,
+    ) -> Option<Coordinates> {
+        self.entries
+            .iter()
+            .rev()
+            .find(|e| e.latest && pred(&e.loc))
+            .map(|e| e.loc)
+    }
+
+    /// Return how recently a [`Node`] was focused, `0` being the most recent
+    pub(crate) fn rank(&self, desktop: Xid, node: NodeId) -> usize {
+        self.entries
+            .iter()
+            .rev()
+            .filter(|e| e.latest)
+            .position(|e| e.loc.desktop == desktop && e.loc.node == Some(node))
+            .unwrap_or(usize::MAX)
+    }
+
+    /// Return an iterator over the locations of the history, from newest to
+    /// oldest, skipping those that were focused again since
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &Coordinates> + '_ {
+        self.entries
+            .iter()
+            .rev()
+            .filter(|e| e.latest)
+            .map(|e| &e.loc)
+    }
 }
 
 // =============================== Node ===============================
 
-/// A single leaf in the tree
+/// A single node in the [`Tree`]
+///
+/// A leaf holds a [`Client`], while an internal node describes how its two
+/// children split the area it covers
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) struct Node {
     /// `id` of the [`Node`]
@@ -93,7 +351,7 @@ pub(crate) struct Node {
     /// Ratio of the [`Split`]
     split_ratio:  f64,
     /// Preselection information
-    presel:       Presel,
+    presel:       Option<Presel>,
     /// [`Window`] dimensions
     rectangle:    Rectangle,
     /// [`Constraint`]s of this [`Node`]
@@ -111,21 +369,184 @@ pub(crate) struct Node {
     /// Is the current [`Node`] marked?
     marked:       bool,
     /// First child [`Node`] of current [`Node`]
-    first_child:  usize,
+    first_child:  Option<NodeId>,
     /// Second child [`Node`] of current [`Node`]
-    second_child: usize,
+    second_child: Option<NodeId>,
     /// Parent [`Node`] of current [`Node`]
-    parent:       Option<(usize, bool)>,
+    parent:       Option<NodeId>,
     /// Master [`Client`] running this [`Node`]
-    client:       Client,
+    client:       Option<Client>,
 }
 
 impl Node {
+    /// Create a new, unlinked [`Node`]
+    pub(crate) fn new(id: Xid, client: Option<Client>, split_ratio: f64) -> Self {
+        Self {
+            id,
+            split_type: SplitType::Vertical,
+            split_ratio,
+            presel: None,
+            rectangle: Rectangle::zeroed(),
+            constraints: Constraint::default(),
+            vacant: false,
+            hidden: false,
+            sticky: false,
+            private: false,
+            locked: false,
+            marked: false,
+            first_child: None,
+            second_child: None,
+            parent: None,
+            client,
+        }
+    }
+
+    /// Return the `id` of the [`Node`]
+    pub(crate) const fn id(&self) -> Xid {
+        self.id
+    }
+
+    /// Return the [`SplitType`] of the [`Node`]
+    pub(crate) const fn split_type(&self) -> SplitType {
+        self.split_type
+    }
+
     /// Change the [`SplitType`] of the [`Node`]
     pub(crate) fn set_type(&mut self, type_: SplitType) {
         self.split_type = type_;
     }
 
+    /// Return the split ratio of the [`Node`]
+    pub(crate) const fn split_ratio(&self) -> f64 {
+        self.split_ratio
+    }
+
+    /// Change the split ratio of the [`Node`]
+    pub(crate) fn set_ratio(&mut self, ratio: f64) {
+        self.split_ratio = ratio;
+    }
+
+    /// Return the [`Rectangle`] the [`Node`] covers
+    pub(crate) const fn rectangle(&self) -> Rectangle {
+        self.rectangle
+    }
+
+    /// Change the [`Rectangle`] the [`Node`] covers
+    pub(crate) fn set_rectangle(&mut self, rectangle: Rectangle) {
+        self.rectangle = rectangle;
+    }
+
+    /// Return the [`Presel`] of the [`Node`]
+    pub(crate) const fn presel(&self) -> Option<Presel> {
+        self.presel
+    }
+
+    /// Return the [`Client`] of the [`Node`]
+    pub(crate) const fn client(&self) -> Option<&Client> {
+        self.client.as_ref()
+    }
+
+    /// Return a mutable reference to the [`Client`] of the [`Node`]
+    pub(crate) fn client_mut(&mut self) -> Option<&mut Client> {
+        self.client.as_mut()
+    }
+
+    /// Hand the [`Node`] over to another [`Client`], returning the previous one
+    ///
+    /// The `id` of a leaf is the [`Window`] of its [`Client`], so it follows
+    pub(crate) fn replace_client(&mut self, client: Client) -> Option<Client> {
+        self.id = *client.get_window();
+        self.client.replace(client)
+    }
+
+    /// Return the parent of the [`Node`]
+    pub(crate) const fn parent(&self) -> Option<NodeId> {
+        self.parent
+    }
+
+    /// Return the first child of the [`Node`]
+    pub(crate) const fn first_child(&self) -> Option<NodeId> {
+        self.first_child
+    }
+
+    /// Return the second child of the [`Node`]
+    pub(crate) const fn second_child(&self) -> Option<NodeId> {
+        self.second_child
+    }
+
+    /// Is the [`Node`] a leaf?
+    pub(crate) const fn is_leaf(&self) -> bool {
+        self.first_child.is_none() && self.second_child.is_none()
+    }
+
+    /// Is the [`Node`] a tiled (or pseudo-tiled) leaf?
+    pub(crate) fn is_tiled(&self) -> bool {
+        self.client.as_ref().map_or(false, |c| {
+            matches!(c.get_state(), ClientState::Tiled | ClientState::PsuedoTiled)
+        })
+    }
+
+    /// Does the leaf take no space in the layout, being hidden or holding a
+    /// [`Client`] that isn't tiled?
+    fn takes_no_space(&self) -> bool {
+        self.hidden || (self.client.is_some() && !self.is_tiled())
+    }
+
+    /// Is the [`Node`] hidden?
+    pub(crate) const fn is_hidden(&self) -> bool {
+        self.hidden
+    }
+
+    /// Change whether the [`Node`] is hidden
+    pub(crate) fn set_hidden(&mut self, hidden: bool) {
+        self.hidden = hidden;
+    }
+
+    /// Is the [`Node`] sticky?
+    pub(crate) const fn is_sticky(&self) -> bool {
+        self.sticky
+    }
+
+    /// Change whether the [`Node`] is sticky
+    pub(crate) fn set_sticky(&mut self, sticky: bool) {
+        self.sticky = sticky;
+    }
+
+    /// Is the [`Node`] private?
+    pub(crate) const fn is_private(&self) -> bool {
+        self.private
+    }
+
+    /// Change whether the [`Node`] is private
+    pub(crate) fn set_private(&mut self, private: bool) {
+        self.private = private;
+    }
+
+    /// Is the [`Node`] locked?
+    pub(crate) const fn is_locked(&self) -> bool {
+        self.locked
+    }
+
+    /// Change whether the [`Node`] is locked
+    pub(crate) fn set_locked(&mut self, locked: bool) {
+        self.locked = locked;
+    }
+
+    /// Is the [`Node`] marked?
+    pub(crate) const fn is_marked(&self) -> bool {
+        self.marked
+    }
+
+    /// Change whether the [`Node`] is marked
+    pub(crate) fn set_marked(&mut self, marked: bool) {
+        self.marked = marked;
+    }
+
+    /// Is the [`Node`] vacant?
+    pub(crate) const fn is_vacant(&self) -> bool {
+        self.vacant
+    }
+
     // /// Modify the [`Node`]'s constraints
     // fn update_constraints(&mut self) {
     //     if self.split_type == SplitType::Vertical {
@@ -139,11 +560,660 @@ impl Node {
         serde_json::to_string(&self).context("failed to serialize `Node` into json format")
     }
 
-    /// Create a [`Node`] from a description
-    pub(crate) fn from_desc(desc: &str, reference: &Coordinates, dest: &Coordinates) -> Self {
-        // dest.node = NULL;
+    /// Find the [`Coordinates`] of the [`Node`] matching a selector, relative
+    /// to a reference. See [`node_from_desc`] for the grammar
+    pub(crate) fn from_desc(
+        desc: &str,
+        state: &State,
+        reference: &Coordinates,
+    ) -> Result<Coordinates, SelectorError> {
+        node_from_desc(desc, state, reference)
+    }
+}
+
+// =============================== Tree ===============================
+
+/// An arena owning every [`Node`] of a binary space partitioning tree
+///
+/// Nodes refer to each other through their [`NodeId`], which stays valid
+/// until the [`Node`] is removed. Freed slots are reused by later insertions,
+/// much like the [`Stack`](crate::x::utils::Stack)
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub(crate) struct Tree {
+    /// Every [`Node`] in the tree, `None` if the slot has been freed
+    nodes: Vec<Option<Node>>,
+    /// Index of slots freed from the tree
+    free:  Vec<NodeId>,
+    /// The root [`Node`] of the tree
+    root:  Option<NodeId>,
+}
+
+impl Tree {
+    /// Create a new, empty [`Tree`]
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return the root of the [`Tree`]
+    pub(crate) const fn root(&self) -> Option<NodeId> {
+        self.root
+    }
+
+    /// Get the number of [`Node`]s in the [`Tree`]
+    pub(crate) fn len(&self) -> usize {
+        self.nodes.len() - self.free.len()
+    }
+
+    /// Is the [`Tree`] empty?
+    pub(crate) const fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Get a reference to a [`Node`]
+    pub(crate) fn get(&self, id: NodeId) -> Option<&Node> {
+        self.nodes.get(id).and_then(Option::as_ref)
+    }
+
+    /// Get a mutable reference to a [`Node`]
+    pub(crate) fn get_mut(&mut self, id: NodeId) -> Option<&mut Node> {
+        self.nodes.get_mut(id).and_then(Option::as_mut)
+    }
+
+    /// Store a [`Node`] in a free slot, returning its [`NodeId`]
+    fn alloc(&mut self, node: Node) -> NodeId {
+        if let Some(id) = self.free.pop() {
+            self.nodes[id] = Some(node);
+            id
+        } else {
+            self.nodes.push(Some(node));
+            self.nodes.len() - 1
+        }
+    }
+
+    /// Free the slot of a [`Node`], returning the [`Node`]
+    fn dealloc(&mut self, id: NodeId) -> Option<Node> {
+        let node = self.nodes.get_mut(id)?.take()?;
+        self.free.push(id);
+        Some(node)
+    }
+
+    /// Replace the child `old` of `parent` with `new`. If `parent` is `None`,
+    /// then `new` becomes the root
+    fn replace_child(&mut self, parent: Option<NodeId>, old: NodeId, new: NodeId) {
+        match parent.and_then(|p| self.get_mut(p)) {
+            Some(p) =>
+                if p.first_child == Some(old) {
+                    p.first_child = Some(new);
+                } else {
+                    p.second_child = Some(new);
+                },
+            None => self.root = Some(new),
+        }
+
+        if let Some(n) = self.get_mut(new) {
+            n.parent = parent;
+        }
+    }
+
+    /// Insert a [`Node`] as the root of an empty [`Tree`]
+    ///
+    /// If the tree already has a root, nothing is inserted
+    pub(crate) fn insert_root(&mut self, mut node: Node) -> Option<NodeId> {
+        if self.root.is_some() {
+            return None;
+        }
+
+        node.parent = None;
+        node.first_child = None;
+        node.second_child = None;
+
+        let id = self.alloc(node);
+        self.root = Some(id);
+        Some(id)
+    }
+
+    /// Insert `node` next to the leaf `at`
+    ///
+    /// The internal node `parent` takes the place of `at` in the [`Tree`] and
+    /// becomes the parent of both. Its split type and ratio decide how the
+    /// area of `at` is divided. The [`ChildPolarity`] decides whether `node`
+    /// becomes the first or second child.
+    ///
+    /// Returns the [`NodeId`] of the inserted `node`
+    pub(crate) fn insert_at(
+        &mut self,
+        at: NodeId,
+        mut node: Node,
+        mut parent: Node,
+        polarity: ChildPolarity,
+    ) -> Option<NodeId> {
+        let grandparent = self.get(at)?.parent;
+
+        node.first_child = None;
+        node.second_child = None;
+        parent.rectangle = self.get(at)?.rectangle;
+
+        let node_id = self.alloc(node);
+        let parent_id = self.alloc(parent);
+
+        let (first, second) = match polarity {
+            ChildPolarity::First => (node_id, at),
+            ChildPolarity::Second => (at, node_id),
+        };
+
+        self.replace_child(grandparent, at, parent_id);
+
+        if let Some(p) = self.get_mut(parent_id) {
+            p.first_child = Some(first);
+            p.second_child = Some(second);
+        }
+
+        for child in [first, second] {
+            if let Some(c) = self.get_mut(child) {
+                c.parent = Some(parent_id);
+            }
+        }
+
+        Some(node_id)
+    }
+
+    /// Remove a [`Node`] along with all of its descendants
+    ///
+    /// The brother of the removed [`Node`] is promoted into the place of
+    /// their parent, which is removed as well. Returns every removed [`Node`],
+    /// with the requested one first
+    pub(crate) fn remove(&mut self, id: NodeId) -> Vec<Node> {
+        if self.get(id).is_none() {
+            return vec![];
+        }
+
+        let subtree = self.descendants(id).collect::<Vec<_>>();
+
+        match self.parent(id) {
+            Some(parent) => {
+                let grandparent = self.parent(parent);
+                if let Some(brother) = self.brother(id) {
+                    self.replace_child(grandparent, parent, brother);
+                }
+                self.dealloc(parent);
+            },
+            None => self.root = None,
+        }
+
+        subtree
+            .into_iter()
+            .filter_map(|n| self.dealloc(n))
+            .map(|mut n| {
+                n.parent = None;
+                n
+            })
+            .collect()
+    }
+
+    /// Find the leaf holding the given [`Window`]
+    pub(crate) fn find_window(&self, window: Window) -> Option<NodeId> {
+        self.root.and_then(|root| {
+            self.descendants(root).find(|&id| {
+                self.get(id)
+                    .and_then(Node::client)
+                    .map_or(false, |c| *c.get_window() == window)
+            })
+        })
+    }
 
-        todo!()
+    /// Find the [`Node`] with the given `id`
+    pub(crate) fn find_id(&self, xid: Xid) -> Option<NodeId> {
+        self.root.and_then(|root| {
+            self.descendants(root)
+                .find(|&id| self.get(id).map_or(false, |n| n.id == xid))
+        })
+    }
+
+    // ========================= Insertion ==========================
+
+    /// Insert `node` into the [`Tree`] at the focused [`Node`]
+    ///
+    /// If the focused [`Node`] has a [`Presel`], the `node` lands in the
+    /// preselected area and the [`Presel`] is consumed and returned, so that
+    /// its feedback [`Window`] can be destroyed. Otherwise, the
+    /// [`AutomaticScheme`] is used, like [`insert_automatic`](Self::insert_automatic)
+    pub(crate) fn insert_node(
+        &mut self,
+        focus: Option<NodeId>,
+        node: Node,
+        mut parent: Node,
+        scheme: AutomaticScheme,
+        polarity: ChildPolarity,
+    ) -> Option<(NodeId, Option<Presel>)> {
+        let presel = focus
+            .and_then(|f| self.get_mut(f))
+            .and_then(|f| f.presel.take());
+
+        match (focus, presel) {
+            (Some(focus), Some(presel)) => {
+                let (split_type, polarity) = presel.split();
+                parent.split_type = split_type;
+                parent.split_ratio = f64::from(presel.split_ratio);
+
+                self.insert_at(focus, node, parent, polarity)
+                    .map(|id| (id, Some(presel)))
+            },
+            _ => self
+                .insert_automatic(focus, node, parent, scheme, polarity)
+                .map(|id| (id, None)),
+        }
+    }
+
+    // ======================== Preselection ========================
+
+    /// Preselect the area of a [`Node`] in the given [`Direction`]
+    ///
+    /// A new [`Presel`] using `ratio` is created if there isn't one already
+    pub(crate) fn presel_dir(
+        &mut self,
+        id: NodeId,
+        dir: Direction,
+        ratio: f32,
+    ) -> Option<&Presel> {
+        let node = self.get_mut(id)?;
+        let presel = node.presel.get_or_insert_with(|| Presel::new(ratio));
+        presel.split_dir = dir;
+        Some(presel)
+    }
+
+    /// Change the ratio of the preselection of a [`Node`]
+    ///
+    /// A new [`Presel`] is created if there isn't one already
+    pub(crate) fn presel_ratio(&mut self, id: NodeId, ratio: f32) -> Result<&Presel> {
+        if !Presel::is_valid_ratio(ratio) {
+            return Err(anyhow!("invalid preselection ratio: {}", ratio));
+        }
+
+        let node = self
+            .get_mut(id)
+            .context(format!("failed to find node to preselect: {}", id))?;
+        let presel = node.presel.get_or_insert_with(|| Presel::new(ratio));
+        presel.split_ratio = ratio;
+        Ok(presel)
+    }
+
+    /// Cancel the preselection of a [`Node`], returning it
+    pub(crate) fn presel_cancel(&mut self, id: NodeId) -> Option<Presel> {
+        self.get_mut(id)?.presel.take()
+    }
+
+    /// Cancel the preselection of every [`Node`] in the [`Tree`], returning them
+    pub(crate) fn presel_cancel_all(&mut self) -> Vec<Presel> {
+        self.nodes
+            .iter_mut()
+            .flatten()
+            .filter_map(|n| n.presel.take())
+            .collect()
+    }
+
+    /// Return the [`Node`]s that have a [`Presel`]
+    pub(crate) fn preselected(&self) -> impl Iterator<Item = NodeId> + '_ {
+        self.root
+            .into_iter()
+            .flat_map(move |root| self.descendants(root))
+            .filter(move |&id| self.get(id).map_or(false, |n| n.presel.is_some()))
+    }
+
+    /// Return a mutable reference to the [`Presel`] of a [`Node`]
+    pub(crate) fn presel_mut(&mut self, id: NodeId) -> Option<&mut Presel> {
+        self.get_mut(id)?.presel.as_mut()
+    }
+
+    // ====================== Automatic Scheme ======================
+
+    /// Insert `node` into the [`Tree`] at the focused [`Node`], using the
+    /// [`AutomaticScheme`] to choose where it goes and how it is split
+    ///
+    /// `parent` is the internal node that will be created by the insertion.
+    /// If there is no focused [`Node`], the root is used instead. Returns the
+    /// [`NodeId`] of the inserted `node`
+    pub(crate) fn insert_automatic(
+        &mut self,
+        focus: Option<NodeId>,
+        node: Node,
+        mut parent: Node,
+        scheme: AutomaticScheme,
+        polarity: ChildPolarity,
+    ) -> Option<NodeId> {
+        let focus = match focus.or(self.root) {
+            Some(focus) => focus,
+            None => return self.insert_root(node),
+        };
+
+        let p = self.parent(focus);
+        let single_tiled =
+            self.get(focus).map_or(false, Node::is_tiled) && self.tiled_count() == 1;
+
+        match p {
+            Some(p) if scheme == AutomaticScheme::Spiral && !single_tiled => {
+                // Wrap the parent of the focused node, continuing the spiral
+                let (split_type, split_ratio) = {
+                    let p = self.get(p)?;
+                    (p.split_type, p.split_ratio)
+                };
+                parent.split_type = split_type;
+                parent.split_ratio = split_ratio;
+
+                let (polarity, rotation) = t!(self.is_first_child(focus)
+                    ? (ChildPolarity::First, Rotation::Quarter)
+                    : (ChildPolarity::Second, Rotation::ThreeQuarters));
+
+                let vacant = node.takes_no_space();
+                let id = self.insert_at(p, node, parent, polarity)?;
+                if !vacant {
+                    self.rotate(p, rotation);
+                }
+
+                Some(id)
+            },
+            _ => {
+                parent.split_type = match p {
+                    Some(p) if scheme == AutomaticScheme::Alternate && !single_tiled => {
+                        // Find the closest ancestor whose children both take space
+                        let q = self
+                            .ancestors(focus)
+                            .find(|&q| {
+                                self.get(q).map_or(false, |q| {
+                                    [q.first_child, q.second_child]
+                                        .iter()
+                                        .flatten()
+                                        .all(|&c| self.get(c).map_or(false, |c| !c.vacant))
+                                })
+                            })
+                            .unwrap_or(p);
+
+                        self.get(q)?.split_type.opposite()
+                    },
+                    _ => Self::longest_side(self.get(focus)?.rectangle),
+                };
+
+                self.insert_at(focus, node, parent, polarity)
+            },
+        }
+    }
+
+    /// Remove a [`Node`] like [`remove`](Self::remove), re-splitting its
+    /// brother according to the [`AutomaticScheme`] if `adjust` is true
+    pub(crate) fn remove_automatic(
+        &mut self,
+        id: NodeId,
+        scheme: AutomaticScheme,
+        adjust: bool,
+    ) -> Vec<Node> {
+        let adjustment = self.parent(id).and_then(|p| {
+            let brother = self.brother(id)?;
+            let rect = self.get(p)?.rectangle;
+            let grandparent = self.parent(p).and_then(|g| self.get(g)).map(Node::split_type);
+            Some((brother, rect, grandparent, self.is_first_child(id)))
+        });
+
+        let removed = self.remove(id);
+
+        if let (true, Some((brother, rect, grandparent, was_first))) = (adjust, adjustment) {
+            match (scheme, grandparent) {
+                (AutomaticScheme::Spiral, _) => self.rotate(
+                    brother,
+                    t!(was_first ? Rotation::ThreeQuarters : Rotation::Quarter),
+                ),
+                (AutomaticScheme::Alternate, Some(split_type)) =>
+                    if let Some(b) = self.get_mut(brother) {
+                        b.split_type = split_type.opposite();
+                    },
+                _ =>
+                    if let Some(b) = self.get_mut(brother) {
+                        b.split_type = Self::longest_side(rect);
+                    },
+            }
+        }
+
+        removed
+    }
+
+    /// Return the [`SplitType`] that divides the longest side of a [`Rectangle`]
+    fn longest_side(rect: Rectangle) -> SplitType {
+        t!(rect.dimension.width > rect.dimension.height
+            ? SplitType::Vertical
+            : SplitType::Horizontal)
+    }
+
+    /// Rotate the subtree rooted at the [`Node`] clockwise
+    pub(crate) fn rotate(&mut self, id: NodeId, rotation: Rotation) {
+        let internal = self.descendants(id).filter(|&n| !self.is_leaf(n)).collect::<Vec<_>>();
+
+        for n in internal {
+            if let Some(node) = self.get_mut(n) {
+                let swap = matches!(
+                    (rotation, node.split_type),
+                    (Rotation::Quarter, SplitType::Horizontal)
+                        | (Rotation::ThreeQuarters, SplitType::Vertical)
+                        | (Rotation::Half, _)
+                );
+
+                if swap {
+                    std::mem::swap(&mut node.first_child, &mut node.second_child);
+                    node.split_ratio = 1.0 - node.split_ratio;
+                }
+
+                if rotation != Rotation::Half {
+                    node.split_type = node.split_type.opposite();
+                }
+            }
+        }
+    }
+
+    /// Return the number of tiled leaves in the [`Tree`]
+    pub(crate) fn tiled_count(&self) -> usize {
+        self.leaves()
+            .filter(|&id| self.get(id).map_or(false, Node::is_tiled))
+            .count()
+    }
+
+    /// Mark the hidden leaves and the ones that aren't tiled as vacant, along
+    /// with the internal [`Node`]s whose children are both vacant, so that they
+    /// take no space in the layout
+    pub(crate) fn update_vacancy(&mut self) {
+        if let Some(root) = self.root {
+            self.update_vacancy_of(root);
+        }
+    }
+
+    /// Update the vacancy of a [`Node`] and its descendants, returning whether
+    /// the [`Node`] is vacant
+    fn update_vacancy_of(&mut self, id: NodeId) -> bool {
+        let (first, second, leaf_vacant) = match self.get(id) {
+            Some(node) => (node.first_child, node.second_child, node.takes_no_space()),
+            None => return true,
+        };
+        let vacant = match (first, second) {
+            (Some(first), Some(second)) => {
+                // Both sides are visited, so no short-circuit
+                let first = self.update_vacancy_of(first);
+                let second = self.update_vacancy_of(second);
+                first && second
+            },
+            _ => leaf_vacant,
+        };
+
+        if let Some(node) = self.get_mut(id) {
+            node.vacant = vacant;
+        }
+        vacant
+    }
+
+    // ========================== Walking ===========================
+
+    /// Return the parent of a [`Node`]
+    pub(crate) fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.get(id)?.parent
+    }
+
+    /// Return the other child of the parent of a [`Node`]
+    pub(crate) fn brother(&self, id: NodeId) -> Option<NodeId> {
+        let parent = self.get(self.parent(id)?)?;
+        if parent.first_child == Some(id) {
+            parent.second_child
+        } else {
+            parent.first_child
+        }
+    }
+
+    /// Is the [`Node`] the first child of its parent?
+    pub(crate) fn is_first_child(&self, id: NodeId) -> bool {
+        self.parent(id)
+            .and_then(|p| self.get(p))
+            .map_or(false, |p| p.first_child == Some(id))
+    }
+
+    /// Is the [`Node`] the second child of its parent?
+    pub(crate) fn is_second_child(&self, id: NodeId) -> bool {
+        self.parent(id)
+            .and_then(|p| self.get(p))
+            .map_or(false, |p| p.second_child == Some(id))
+    }
+
+    /// Is the [`Node`] a leaf?
+    pub(crate) fn is_leaf(&self, id: NodeId) -> bool {
+        self.get(id).map_or(false, Node::is_leaf)
+    }
+
+    /// Is `id` a descendant of (or equal to) `ancestor`?
+    pub(crate) fn is_descendant(&self, id: NodeId, ancestor: NodeId) -> bool {
+        id == ancestor || self.ancestors(id).any(|a| a == ancestor)
+    }
+
+    /// Return an iterator over the ancestors of a [`Node`], starting with its
+    /// parent
+    pub(crate) fn ancestors(&self, id: NodeId) -> Ancestors<'_> {
+        Ancestors { tree: self, curr: self.parent(id) }
+    }
+
+    /// Return a pre-order iterator over a [`Node`] and its descendants
+    pub(crate) fn descendants(&self, id: NodeId) -> Descendants<'_> {
+        Descendants {
+            tree:  self,
+            stack: t!(self.get(id).is_some() ? vec![id] : vec![]),
+        }
+    }
+
+    /// Return an iterator over the leaves of the [`Tree`], from left to right
+    pub(crate) fn leaves(&self) -> impl Iterator<Item = NodeId> + '_ {
+        self.root
+            .into_iter()
+            .flat_map(move |root| self.descendants(root))
+            .filter(move |&id| self.is_leaf(id))
+    }
+
+    /// Return the first leaf found when only following first children
+    pub(crate) fn first_extrema(&self, id: NodeId) -> Option<NodeId> {
+        let mut curr = id;
+        while let Some(child) = self.get(curr)?.first_child {
+            curr = child;
+        }
+        Some(curr)
+    }
+
+    /// Return the last leaf found when only following second children
+    pub(crate) fn second_extrema(&self, id: NodeId) -> Option<NodeId> {
+        let mut curr = id;
+        while let Some(child) = self.get(curr)?.second_child {
+            curr = child;
+        }
+        Some(curr)
+    }
+
+    /// Return the [`Node`] following this one in an in-order traversal
+    pub(crate) fn next_node(&self, id: NodeId) -> Option<NodeId> {
+        if let Some(second) = self.get(id)?.second_child {
+            return self.first_extrema(second);
+        }
+
+        let mut curr = id;
+        while self.is_second_child(curr) {
+            curr = self.parent(curr)?;
+        }
+        t!(self.is_first_child(curr) ? self.parent(curr) : None)
+    }
+
+    /// Return the [`Node`] preceding this one in an in-order traversal
+    pub(crate) fn prev_node(&self, id: NodeId) -> Option<NodeId> {
+        if let Some(first) = self.get(id)?.first_child {
+            return self.second_extrema(first);
+        }
+
+        let mut curr = id;
+        while self.is_first_child(curr) {
+            curr = self.parent(curr)?;
+        }
+        t!(self.is_second_child(curr) ? self.parent(curr) : None)
+    }
+
+    /// Return the closest ancestor whose split separates the [`Node`] from the
+    /// area in the given [`Direction`]
+    pub(crate) fn find_fence(&self, id: NodeId, dir: Direction) -> Option<NodeId> {
+        let n = self.get(id)?.rectangle;
+        let (n_right, n_bottom) = (
+            n.point.x + n.dimension.width as i32,
+            n.point.y + n.dimension.height as i32,
+        );
+
+        self.ancestors(id).find(|&a| {
+            self.get(a).map_or(false, |a| {
+                let p = a.rectangle;
+                match (dir, a.split_type) {
+                    (Direction::North, SplitType::Horizontal) => p.point.y < n.point.y,
+                    (Direction::West, SplitType::Vertical) => p.point.x < n.point.x,
+                    (Direction::South, SplitType::Horizontal) =>
+                        p.point.y + p.dimension.height as i32 > n_bottom,
+                    (Direction::East, SplitType::Vertical) =>
+                        p.point.x + p.dimension.width as i32 > n_right,
+                    _ => false,
+                }
+            })
+        })
+    }
+}
+
+/// An iterator over the ancestors of a [`Node`]
+pub(crate) struct Ancestors<'a> {
+    /// A reference to the tree
+    tree: &'a Tree,
+    /// The current node in the iterator
+    curr: Option<NodeId>,
+}
+
+impl<'a> Iterator for Ancestors<'a> {
+    type Item = NodeId;
+
+    fn next(&mut self) -> Option<NodeId> {
+        let curr = self.curr?;
+        self.curr = self.tree.parent(curr);
+        Some(curr)
+    }
+}
+
+/// A pre-order iterator over a [`Node`] and its descendants
+pub(crate) struct Descendants<'a> {
+    /// A reference to the tree
+    tree:  &'a Tree,
+    /// Nodes that have yet to be visited
+    stack: Vec<NodeId>,
+}
+
+impl<'a> Iterator for Descendants<'a> {
+    type Item = NodeId;
+
+    fn next(&mut self) -> Option<NodeId> {
+        let curr = self.stack.pop()?;
+        if let Some(node) = self.tree.get(curr) {
+            self.stack.extend(node.second_child);
+            self.stack.extend(node.first_child);
+        }
+        Some(curr)
     }
 }
 
@@ -152,7 +1222,7 @@ impl Node {
 /// The current [`Desktop`]
 ///
 /// One level above a [`Node`] and one level below a [`Monitor`]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) struct Desktop {
     /// Desktop's name
     name:         String,
@@ -160,23 +1230,132 @@ pub(crate) struct Desktop {
     id:           Xid,
     /// The layout of the desktop
     layout:       LayoutType,
-    /// The user's layout of the desktop TODO: fill out
+    /// The layout chosen by the user, which `single_monocle` falls back to
     user_layout:  LayoutType,
-    /// Root [`Node`] of the desktop
-    root:         Node,
+    /// The [`Node`]s of the desktop
+    tree:         Tree,
     /// Focused [`Node`] of the desktop
-    focus:        Node,
+    focus:        Option<NodeId>,
     /// Padding information about the desktop
     padding:      Padding,
     /// Current window gap settings
     window_gap:   isize,
     /// Current border width settings
     border_width: usize,
+}
+
+impl Desktop {
+    /// Create a new, empty [`Desktop`]
+    pub(crate) fn new(
+        name: String,
+        id: Xid,
+        padding: Padding,
+        window_gap: isize,
+        border_width: usize,
+    ) -> Self {
+        Self {
+            name,
+            id,
+            layout: LayoutType::Tiled,
+            user_layout: LayoutType::Tiled,
+            tree: Tree::new(),
+            focus: None,
+            padding,
+            window_gap,
+            border_width,
+        }
+    }
+
+    /// Return the name of the [`Desktop`]
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
 
-    /// Previous [`Desktop`]
-    prev: Box<Self>,
-    /// Next [`Desktop`]
-    next: Box<Self>,
+    /// Rename the [`Desktop`]
+    pub(crate) fn set_name(&mut self, name: String) {
+        self.name = name;
+    }
+
+    /// Return the ID of the [`Desktop`]
+    pub(crate) const fn id(&self) -> Xid {
+        self.id
+    }
+
+    /// Return the [`LayoutType`] of the [`Desktop`]
+    pub(crate) const fn layout(&self) -> LayoutType {
+        self.layout
+    }
+
+    /// Return the [`LayoutType`] chosen by the user
+    pub(crate) const fn user_layout(&self) -> LayoutType {
+        self.user_layout
+    }
+
+    /// Change the [`LayoutType`] of the [`Desktop`], as chosen by the user
+    pub(crate) fn set_layout(&mut self, layout: LayoutType) {
+        self.layout = layout;
+        self.user_layout = layout;
+    }
+
+    /// Return the [`Tree`] of the [`Desktop`]
+    pub(crate) const fn tree(&self) -> &Tree {
+        &self.tree
+    }
+
+    /// Return a mutable reference to the [`Tree`] of the [`Desktop`]
+    pub(crate) fn tree_mut(&mut self) -> &mut Tree {
+        &mut self.tree
+    }
+
+    /// Return the focused [`Node`] of the [`Desktop`]
+    pub(crate) const fn focus(&self) -> Option<NodeId> {
+        self.focus
+    }
+
+    /// Change the focused [`Node`] of the [`Desktop`]
+    pub(crate) fn set_focus(&mut self, focus: Option<NodeId>) {
+        self.focus = focus;
+    }
+
+    /// Return the [`Padding`] of the [`Desktop`]
+    pub(crate) const fn padding(&self) -> Padding {
+        self.padding
+    }
+
+    /// Return the window gap of the [`Desktop`]
+    pub(crate) const fn window_gap(&self) -> isize {
+        self.window_gap
+    }
+
+    /// Return the border width of the [`Desktop`]
+    pub(crate) const fn border_width(&self) -> usize {
+        self.border_width
+    }
+
+    /// Does the [`Desktop`] hold any [`Node`]?
+    pub(crate) const fn is_occupied(&self) -> bool {
+        !self.tree.is_empty()
+    }
+
+    /// Does the [`Desktop`] hold an urgent [`Client`]?
+    pub(crate) fn is_urgent(&self) -> bool {
+        self.tree.leaves().any(|id| {
+            self.tree
+                .get(id)
+                .and_then(Node::client)
+                .map_or(false, |c| *c.get_urgent())
+        })
+    }
+
+    /// Find the [`Coordinates`] of the [`Desktop`] matching a selector,
+    /// relative to a reference. See [`desktop_from_desc`] for the grammar
+    pub(crate) fn from_desc(
+        desc: &str,
+        state: &State,
+        reference: &Coordinates,
+    ) -> Result<Coordinates, SelectorError> {
+        desktop_from_desc(desc, state, reference)
+    }
 }
 
 // ============================== Monitor =============================
@@ -184,7 +1363,7 @@ pub(crate) struct Desktop {
 /// The current [`Monitor`]
 ///
 /// One level above a [`Desktop`] and one level below the overall tree
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) struct Monitor {
     /// Monitor's name
     name:         String,
@@ -200,21 +1379,662 @@ pub(crate) struct Monitor {
     sticky_count: usize,
     /// Padding information about the monitor
     padding:      Padding,
+    /// Space reserved by the docks at each side of the monitor
+    #[serde(default)]
+    reserved:     Padding,
     /// Current window gap settings
     window_gap:   isize,
     /// Current border width settings
     border_width: usize,
-    /// TODO: fill out
+    /// Area of the screen covered by the monitor
     rectangle:    Rectangle,
-    /// Current [`Desktop`]
-    desk:         Desktop,
-    /// The first [`Desktop`] in the list
-    desk_head:    Desktop,
-    /// The last [`Desktop`] in the list
-    desk_tail:    Desktop,
-
-    /// Previous [`Monitor`]
-    prev: Box<Self>,
-    /// Next [`Monitor`]
-    next: Box<Self>,
+    /// The [`Desktop`]s of the monitor, in order
+    desktops:     Vec<Desktop>,
+    /// ID of the focused [`Desktop`]
+    desk:         Option<Xid>,
+}
+
+impl Monitor {
+    /// Create a new [`Monitor`] without any [`Desktop`]
+    pub(crate) fn new(name: String, id: Xid, randr_id: Output, rectangle: Rectangle) -> Self {
+        Self {
+            name,
+            id,
+            randr_id,
+            root: x11rb::NONE,
+            wired: true,
+            sticky_count: 0,
+            padding: Padding::default(),
+            reserved: Padding::default(),
+            window_gap: 0,
+            border_width: 0,
+            rectangle,
+            desktops: vec![],
+            desk: None,
+        }
+    }
+
+    /// Return the name of the [`Monitor`]
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Rename the [`Monitor`]
+    pub(crate) fn set_name(&mut self, name: String) {
+        self.name = name;
+    }
+
+    /// Return the ID of the [`Monitor`]
+    pub(crate) const fn id(&self) -> Xid {
+        self.id
+    }
+
+    /// Return the `randr` ID of the [`Monitor`]
+    pub(crate) const fn randr_id(&self) -> Output {
+        self.randr_id
+    }
+
+    /// Return the root [`Window`] of the [`Monitor`]
+    pub(crate) const fn root(&self) -> Window {
+        self.root
+    }
+
+    /// Is the [`Monitor`] wired?
+    pub(crate) const fn is_wired(&self) -> bool {
+        self.wired
+    }
+
+    /// Return the number of sticky [`Node`]s on the [`Monitor`]
+    pub(crate) const fn sticky_count(&self) -> usize {
+        self.sticky_count
+    }
+
+    /// Count the sticky leaves of the [`Desktop`]s of the [`Monitor`] again
+    pub(crate) fn update_sticky_count(&mut self) {
+        self.sticky_count = self
+            .desktops
+            .iter()
+            .map(|d| {
+                let tree = d.tree();
+                tree.leaves()
+                    .filter(|&id| tree.get(id).map_or(false, Node::is_sticky))
+                    .count()
+            })
+            .sum();
+    }
+
+    /// Return the [`Padding`] of the [`Monitor`]
+    pub(crate) const fn padding(&self) -> Padding {
+        self.padding
+    }
+
+    /// Return the window gap of the [`Monitor`]
+    pub(crate) const fn window_gap(&self) -> isize {
+        self.window_gap
+    }
+
+    /// Return the border width of the [`Monitor`]
+    pub(crate) const fn border_width(&self) -> usize {
+        self.border_width
+    }
+
+    /// Return the area covered by the [`Monitor`]
+    pub(crate) const fn rectangle(&self) -> Rectangle {
+        self.rectangle
+    }
+
+    /// Change the area covered by the [`Monitor`]
+    pub(crate) fn set_rectangle(&mut self, rectangle: Rectangle) {
+        self.rectangle = rectangle;
+    }
+
+    /// Return the space reserved by the docks at each side of the [`Monitor`]
+    pub(crate) const fn reserved(&self) -> Padding {
+        self.reserved
+    }
+
+    /// Reserve the space taken by the [`Strut`]s, which are relative to the
+    /// `screen`. Return whether the reserved space changed
+    pub(crate) fn reserve(&mut self, struts: &[Strut], screen: Rectangle) -> bool {
+        let mut reserved = Padding::default();
+        for strut in struts {
+            let taken = strut.reserved(self.rectangle, screen);
+            let side = match strut.side {
+                Direction::North => &mut reserved.top,
+                Direction::East => &mut reserved.right,
+                Direction::South => &mut reserved.bottom,
+                Direction::West => &mut reserved.left,
+            };
+            *side = (*side).max(taken);
+        }
+
+        let changed = reserved != self.reserved;
+        self.reserved = reserved;
+        changed
+    }
+
+    /// Return the area of the [`Monitor`] that isn't reserved by the docks
+    pub(crate) fn work_area(&self) -> Rectangle {
+        let rect = self.rectangle;
+        let reserved = self.reserved;
+        Rectangle::new(
+            rect.point.x + reserved.left as i32,
+            rect.point.y + reserved.top as i32,
+            rect.dimension
+                .width
+                .saturating_sub(reserved.left + reserved.right)
+                .max(1),
+            rect.dimension
+                .height
+                .saturating_sub(reserved.top + reserved.bottom)
+                .max(1),
+        )
+    }
+
+    /// Return the [`Desktop`]s of the [`Monitor`]
+    pub(crate) fn desktops(&self) -> &[Desktop] {
+        &self.desktops
+    }
+
+    /// Return the [`Desktop`]s of the [`Monitor`] mutably
+    pub(crate) fn desktops_mut(&mut self) -> &mut [Desktop] {
+        &mut self.desktops
+    }
+
+    /// Return the [`Desktop`] with the given ID
+    pub(crate) fn desktop(&self, id: Xid) -> Option<&Desktop> {
+        self.desktops.iter().find(|d| d.id == id)
+    }
+
+    /// Return the [`Desktop`] with the given ID mutably
+    pub(crate) fn desktop_mut(&mut self, id: Xid) -> Option<&mut Desktop> {
+        self.desktops.iter_mut().find(|d| d.id == id)
+    }
+
+    /// Return the focused [`Desktop`]
+    pub(crate) fn desk(&self) -> Option<&Desktop> {
+        self.desk.and_then(|id| self.desktop(id))
+    }
+
+    /// Return the focused [`Desktop`] mutably
+    pub(crate) fn desk_mut(&mut self) -> Option<&mut Desktop> {
+        let id = self.desk?;
+        self.desktop_mut(id)
+    }
+
+    /// Return the ID of the focused [`Desktop`]
+    pub(crate) const fn desk_id(&self) -> Option<Xid> {
+        self.desk
+    }
+
+    /// Focus the [`Desktop`] with the given ID
+    pub(crate) fn set_desk(&mut self, id: Xid) {
+        if self.desktop(id).is_some() {
+            self.desk = Some(id);
+        }
+    }
+
+    /// Add a [`Desktop`] to the end of the [`Monitor`], focusing it if it's
+    /// the only one
+    pub(crate) fn add_desktop(&mut self, desktop: Desktop) {
+        if self.desk.is_none() {
+            self.desk = Some(desktop.id);
+        }
+        self.desktops.push(desktop);
+    }
+
+    /// Remove the [`Desktop`] with the given ID. If it was focused, the first
+    /// remaining [`Desktop`] is focused instead
+    pub(crate) fn remove_desktop(&mut self, id: Xid) -> Option<Desktop> {
+        let idx = self.desktops.iter().position(|d| d.id == id)?;
+        let desktop = self.desktops.remove(idx);
+        if self.desk == Some(id) {
+            self.desk = self.desktops.first().map(Desktop::id);
+        }
+        Some(desktop)
+    }
+
+    /// Is any [`Desktop`] of the [`Monitor`] occupied?
+    pub(crate) fn is_occupied(&self) -> bool {
+        self.desktops.iter().any(Desktop::is_occupied)
+    }
+
+    /// Find the ID of the [`Monitor`] matching a selector, relative to a
+    /// reference. See [`monitor_from_desc`] for the grammar
+    pub(crate) fn from_desc(
+        desc: &str,
+        state: &State,
+        reference: &Coordinates,
+    ) -> Result<Xid, SelectorError> {
+        monitor_from_desc(desc, state, reference)
+    }
+}
+
+/// Tests for [`Tree`]
+#[cfg(test)]
+mod tests {
+    use super::{
+        AutomaticScheme, ChildPolarity, Coordinates, History, Monitor, Node, NodeId, Rectangle,
+        Rotation, SplitType, Tree,
+    };
+    use crate::{
+        core::{Direction, HistoryDir, LayoutType},
+        geometry::{Padding, Strut},
+        layout::{arrange, LayoutParams},
+        test_utils::leaf,
+    };
+
+    /// Create an internal node
+    fn internal(id: u32) -> Node {
+        Node::new(id, None, 0.5)
+    }
+
+    /// Build the tree `((1 2) 3)`
+    fn three_leaves() -> (Tree, [NodeId; 3]) {
+        let mut tree = Tree::new();
+        let a = tree.insert_root(leaf(1)).unwrap();
+        let c = tree
+            .insert_at(a, leaf(3), internal(100), ChildPolarity::Second)
+            .unwrap();
+        let b = tree
+            .insert_at(a, leaf(2), internal(101), ChildPolarity::Second)
+            .unwrap();
+        (tree, [a, b, c])
+    }
+
+    #[test]
+    fn insert_root_once() {
+        let mut tree = Tree::new();
+        assert!(tree.is_empty());
+
+        let root = tree.insert_root(leaf(1)).unwrap();
+        assert_eq!(tree.root(), Some(root));
+        assert!(tree.insert_root(leaf(2)).is_none());
+        assert_eq!(tree.len(), 1);
+    }
+
+    #[test]
+    fn insert_at_leaf() {
+        let (tree, [a, b, c]) = three_leaves();
+        assert_eq!(tree.len(), 5);
+
+        let root = tree.root().unwrap();
+        assert_eq!(tree.get(root).unwrap().id(), 100);
+        assert_eq!(tree.parent(c), Some(root));
+        assert!(tree.is_second_child(c));
+
+        let parent = tree.parent(a).unwrap();
+        assert_eq!(tree.get(parent).unwrap().id(), 101);
+        assert!(tree.is_first_child(parent));
+        assert_eq!(tree.brother(a), Some(b));
+        assert_eq!(tree.brother(b), Some(a));
+        assert_eq!(tree.leaves().collect::<Vec<_>>(), vec![a, b, c]);
+    }
+
+    #[test]
+    fn insert_with_first_polarity() {
+        let mut tree = Tree::new();
+        let a = tree.insert_root(leaf(1)).unwrap();
+        let b = tree
+            .insert_at(a, leaf(2), internal(100), ChildPolarity::First)
+            .unwrap();
+
+        assert!(tree.is_first_child(b));
+        assert_eq!(tree.leaves().collect::<Vec<_>>(), vec![b, a]);
+    }
+
+    #[test]
+    fn remove_promotes_brother() {
+        let (mut tree, [a, b, c]) = three_leaves();
+        let parent = tree.parent(a).unwrap();
+
+        let removed = tree.remove(b);
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].id(), 2);
+        assert!(tree.get(b).is_none());
+        assert!(tree.get(parent).is_none());
+
+        // `a` takes the place of its former parent
+        let root = tree.root().unwrap();
+        assert_eq!(tree.parent(a), Some(root));
+        assert!(tree.is_first_child(a));
+        assert_eq!(tree.brother(a), Some(c));
+        assert_eq!(tree.len(), 3);
+    }
+
+    #[test]
+    fn remove_until_empty() {
+        let (mut tree, [a, b, c]) = three_leaves();
+
+        tree.remove(c);
+        assert_eq!(tree.leaves().collect::<Vec<_>>(), vec![a, b]);
+
+        tree.remove(a);
+        assert_eq!(tree.root(), Some(b));
+        assert_eq!(tree.parent(b), None);
+
+        tree.remove(b);
+        assert!(tree.is_empty());
+        assert_eq!(tree.len(), 0);
+    }
+
+    #[test]
+    fn remove_subtree() {
+        let (mut tree, [a, b, c]) = three_leaves();
+        let parent = tree.parent(a).unwrap();
+
+        let removed = tree.remove(parent);
+        assert_eq!(
+            removed.iter().map(Node::id).collect::<Vec<_>>(),
+            vec![101, 1, 2]
+        );
+        assert_eq!(tree.root(), Some(c));
+        assert_eq!(tree.len(), 1);
+    }
+
+    #[test]
+    fn reuse_freed_slots() {
+        let (mut tree, [a, b, c]) = three_leaves();
+        tree.remove(b);
+
+        let d = tree
+            .insert_at(c, leaf(4), internal(102), ChildPolarity::Second)
+            .unwrap();
+        assert!(d <= 4);
+        assert_eq!(tree.len(), 5);
+        assert_eq!(tree.leaves().collect::<Vec<_>>(), vec![a, c, d]);
+    }
+
+    #[test]
+    fn find_by_window() {
+        let (tree, [a, b, c]) = three_leaves();
+
+        assert_eq!(tree.find_window(1), Some(a));
+        assert_eq!(tree.find_window(2), Some(b));
+        assert_eq!(tree.find_window(3), Some(c));
+        assert_eq!(tree.find_window(4), None);
+        assert_eq!(tree.find_id(101), tree.parent(a));
+    }
+
+    #[test]
+    fn walk_tree() {
+        let (tree, [a, b, c]) = three_leaves();
+        let root = tree.root().unwrap();
+        let parent = tree.parent(a).unwrap();
+
+        assert_eq!(tree.ancestors(a).collect::<Vec<_>>(), vec![parent, root]);
+        assert_eq!(
+            tree.descendants(root).collect::<Vec<_>>(),
+            vec![root, parent, a, b, c]
+        );
+        assert!(tree.is_descendant(b, root));
+        assert!(tree.is_descendant(b, parent));
+        assert!(!tree.is_descendant(c, parent));
+        assert_eq!(tree.first_extrema(root), Some(a));
+        assert_eq!(tree.second_extrema(root), Some(c));
+    }
+
+    /// Insert a new leaf at the focused node, laying the tree out afterwards
+    fn insert(
+        tree: &mut Tree,
+        focus: Option<NodeId>,
+        window: u32,
+        scheme: AutomaticScheme,
+    ) -> NodeId {
+        let id = tree
+            .insert_automatic(
+                focus,
+                leaf(window),
+                internal(window + 100),
+                scheme,
+                ChildPolarity::Second,
+            )
+            .unwrap();
+
+        let params = LayoutParams::new(
+            LayoutType::Tiled,
+            Rectangle::new(0, 0, 1920, 1080),
+            Padding::default(),
+            0,
+            0,
+        );
+        arrange(tree, &params);
+        id
+    }
+
+    /// Return the [`SplitType`] of the node
+    fn split_type(tree: &Tree, id: NodeId) -> SplitType {
+        tree.get(id).unwrap().split_type()
+    }
+
+    #[test]
+    fn longest_side_scheme() {
+        let mut tree = Tree::new();
+        let a = insert(&mut tree, None, 1, AutomaticScheme::LongestSide);
+        let b = insert(&mut tree, Some(a), 2, AutomaticScheme::LongestSide);
+        assert_eq!(split_type(&tree, tree.root().unwrap()), SplitType::Vertical);
+
+        // `b` is now taller than it is wide
+        let c = insert(&mut tree, Some(b), 3, AutomaticScheme::LongestSide);
+        assert_eq!(split_type(&tree, tree.parent(c).unwrap()), SplitType::Horizontal);
+        assert_eq!(tree.leaves().collect::<Vec<_>>(), vec![a, b, c]);
+    }
+
+    #[test]
+    fn alternate_scheme() {
+        let mut tree = Tree::new();
+        let a = insert(&mut tree, None, 1, AutomaticScheme::Alternate);
+        let b = insert(&mut tree, Some(a), 2, AutomaticScheme::Alternate);
+        let c = insert(&mut tree, Some(b), 3, AutomaticScheme::Alternate);
+        let d = insert(&mut tree, Some(c), 4, AutomaticScheme::Alternate);
+
+        assert_eq!(split_type(&tree, tree.root().unwrap()), SplitType::Vertical);
+        assert_eq!(split_type(&tree, tree.parent(c).unwrap()), SplitType::Horizontal);
+        assert_eq!(split_type(&tree, tree.parent(d).unwrap()), SplitType::Vertical);
+        assert_eq!(tree.leaves().collect::<Vec<_>>(), vec![a, b, c, d]);
+    }
+
+    #[test]
+    fn spiral_scheme() {
+        let mut tree = Tree::new();
+        let a = insert(&mut tree, None, 1, AutomaticScheme::Spiral);
+        let b = insert(&mut tree, Some(a), 2, AutomaticScheme::Spiral);
+        let old_root = tree.root().unwrap();
+
+        let c = insert(&mut tree, Some(b), 3, AutomaticScheme::Spiral);
+        let root = tree.root().unwrap();
+
+        // The new node wraps the parent of the focused node, which is rotated
+        assert_eq!(tree.parent(old_root), Some(root));
+        assert_eq!(tree.brother(c), Some(old_root));
+        assert_eq!(split_type(&tree, root), SplitType::Vertical);
+        assert_eq!(split_type(&tree, old_root), SplitType::Horizontal);
+        assert_eq!(tree.leaves().collect::<Vec<_>>(), vec![b, a, c]);
+    }
+
+    #[test]
+    fn initial_polarity() {
+        let mut tree = Tree::new();
+        let a = tree.insert_root(leaf(1)).unwrap();
+        let b = tree
+            .insert_automatic(
+                Some(a),
+                leaf(2),
+                internal(100),
+                AutomaticScheme::LongestSide,
+                ChildPolarity::First,
+            )
+            .unwrap();
+
+        assert_eq!(tree.leaves().collect::<Vec<_>>(), vec![b, a]);
+    }
+
+    #[test]
+    fn rotate_tree() {
+        let (mut tree, [a, b, c]) = three_leaves();
+        let root = tree.root().unwrap();
+        let parent = tree.parent(a).unwrap();
+        tree.get_mut(root).unwrap().set_type(SplitType::Horizontal);
+        tree.get_mut(root).unwrap().set_ratio(0.25);
+
+        tree.rotate(root, Rotation::Quarter);
+        assert_eq!(split_type(&tree, root), SplitType::Vertical);
+        assert!((tree.get(root).unwrap().split_ratio() - 0.75).abs() < f64::EPSILON);
+        assert_eq!(split_type(&tree, parent), SplitType::Horizontal);
+        assert_eq!(tree.leaves().collect::<Vec<_>>(), vec![c, a, b]);
+
+        tree.rotate(root, Rotation::Half);
+        assert_eq!(split_type(&tree, root), SplitType::Vertical);
+        assert_eq!(tree.leaves().collect::<Vec<_>>(), vec![b, a, c]);
+    }
+
+    #[test]
+    fn removal_adjustment() {
+        let mut tree = Tree::new();
+        let a = insert(&mut tree, None, 1, AutomaticScheme::LongestSide);
+        let b = insert(&mut tree, Some(a), 2, AutomaticScheme::LongestSide);
+        let c = insert(&mut tree, Some(b), 3, AutomaticScheme::LongestSide);
+        let brother = tree.parent(b).unwrap();
+        assert_eq!(split_type(&tree, brother), SplitType::Horizontal);
+
+        // The brother now covers the whole wide area
+        tree.remove_automatic(a, AutomaticScheme::LongestSide, true);
+        assert_eq!(tree.root(), Some(brother));
+        assert_eq!(split_type(&tree, brother), SplitType::Vertical);
+
+        tree.remove_automatic(c, AutomaticScheme::LongestSide, true);
+        assert_eq!(tree.leaves().collect::<Vec<_>>(), vec![b]);
+    }
+
+    #[test]
+    fn removal_adjustment_alternate() {
+        let mut tree = Tree::new();
+        let a = insert(&mut tree, None, 1, AutomaticScheme::Alternate);
+        let b = insert(&mut tree, Some(a), 2, AutomaticScheme::Alternate);
+        let c = insert(&mut tree, Some(b), 3, AutomaticScheme::Alternate);
+        let d = insert(&mut tree, Some(c), 4, AutomaticScheme::Alternate);
+        let brother = tree.parent(d).unwrap();
+
+        tree.remove_automatic(b, AutomaticScheme::Alternate, true);
+        assert_eq!(tree.parent(brother), tree.root());
+        assert_eq!(split_type(&tree, brother), SplitType::Horizontal);
+
+        // Without adjustment, the brother is left as is
+        tree.remove_automatic(a, AutomaticScheme::Alternate, false);
+        assert_eq!(tree.root(), Some(brother));
+        assert_eq!(split_type(&tree, brother), SplitType::Horizontal);
+    }
+
+    /// Return the [`Coordinates`] of a [`Node`] on a single [`Desktop`]
+    fn at(node: NodeId) -> Coordinates {
+        Coordinates::new(1, 10, Some(node))
+    }
+
+    /// Return the [`Node`]s of the [`History`], from newest to oldest
+    fn nodes(history: &History) -> Vec<NodeId> {
+        history.iter().filter_map(|loc| loc.node).collect()
+    }
+
+    #[test]
+    fn history_latest() {
+        let mut history = History::new();
+        history.add(at(1));
+        history.add(at(2));
+        history.add(at(2));
+        assert_eq!(history.len(), 2);
+
+        history.add(at(1));
+        assert_eq!(history.len(), 3);
+        assert_eq!(nodes(&history), vec![1, 2]);
+        assert_eq!(history.rank(10, 1), 0);
+        assert_eq!(history.rank(10, 2), 1);
+        assert_eq!(history.rank(10, 3), usize::MAX);
+    }
+
+    #[test]
+    fn history_bounded() {
+        let mut history = History::with_capacity(3);
+        for node in 1..=5 {
+            history.add(at(node));
+        }
+        assert_eq!(history.len(), 3);
+        assert_eq!(nodes(&history), vec![5, 4, 3]);
+    }
+
+    #[test]
+    fn history_jump() {
+        let mut history = History::new();
+        for node in 1..=3 {
+            history.add(at(node));
+        }
+
+        // Finding does not move the needle
+        let curr = at(3);
+        assert_eq!(history.find(HistoryDir::Older, |l| *l != curr), Some(at(2)));
+        assert_eq!(history.find(HistoryDir::Older, |l| *l != curr), Some(at(2)));
+
+        let curr = history.jump(HistoryDir::Older, |l| *l != curr).unwrap();
+        assert_eq!(curr, at(2));
+        let curr = history.jump(HistoryDir::Older, |l| *l != curr).unwrap();
+        assert_eq!(curr, at(1));
+        assert_eq!(history.jump(HistoryDir::Older, |l| *l != curr), None);
+        let curr = history.jump(HistoryDir::Newer, |l| *l != curr).unwrap();
+        assert_eq!(curr, at(2));
+
+        // Recording a new location resets the needle
+        history.add(at(4));
+        assert_eq!(history.find(HistoryDir::Newer, |_| true), Some(at(4)));
+    }
+
+    #[test]
+    fn history_prune() {
+        let mut history = History::new();
+        for &node in &[1, 2, 1, 3] {
+            history.add(at(node));
+        }
+
+        // Both entries of `1` become consecutive and are merged
+        history.retain(|loc| Some(*loc).filter(|l| l.node != Some(2)));
+        assert_eq!(history.len(), 2);
+        assert_eq!(nodes(&history), vec![3, 1]);
+
+        // Entries follow a desktop moved to another monitor
+        history.retain(|loc| Some(Coordinates::new(2, loc.desktop, loc.node)));
+        assert!(history.iter().all(|loc| loc.monitor == 2));
+
+        history.retain(|_| None);
+        assert!(history.is_empty());
+    }
+
+    #[test]
+    fn work_area() {
+        let screen = Rectangle::new(0, 0, 3200, 1080);
+        let mut left = Monitor::new(String::from("left"), 1, 1, Rectangle::new(0, 0, 1920, 1080));
+        let mut right =
+            Monitor::new(String::from("right"), 2, 2, Rectangle::new(1920, 0, 1280, 1024));
+
+        let struts = [
+            // A bar at the top of the left monitor
+            Strut::new(10, Direction::North, 30, 0, 1919),
+            // A full strut below the right monitor, which is shorter
+            Strut::new(11, Direction::South, 20, 0, u32::MAX),
+            // A dock at the right of the right monitor
+            Strut::new(12, Direction::East, 100, 0, 1023),
+            // A smaller bar on top of the first one
+            Strut::new(13, Direction::North, 10, 100, 200),
+        ];
+
+        assert!(left.reserve(&struts, screen));
+        assert_eq!(left.reserved(), Padding::new(30, 0, 20, 0));
+        assert_eq!(left.work_area(), Rectangle::new(0, 30, 1920, 1030));
+
+        assert!(right.reserve(&struts, screen));
+        assert_eq!(right.reserved(), Padding::new(0, 100, 0, 0));
+        assert_eq!(right.work_area(), Rectangle::new(1920, 0, 1180, 1024));
+
+        // Nothing changes until the struts do
+        assert!(!right.reserve(&struts, screen));
+        assert!(right.reserve(&struts[..2], screen));
+        assert_eq!(right.work_area(), right.rectangle());
+    }
 }
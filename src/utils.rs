@@ -1,6 +1,6 @@
 //! Various helper-utilities
 
-use crate::{cli::Opts, config::Config};
+use crate::{cli::Opts, config::Config, core::Pid};
 use anyhow::{Context, Result};
 use clap::crate_name;
 use flexi_logger::{
@@ -22,6 +22,7 @@ use flexi_logger::{
     WriteMode,
 };
 use log::LevelFilter;
+use psutil::process::Process;
 use serde::{de, Deserialize};
 use shellexpand::LookupError;
 use std::{
@@ -171,6 +172,29 @@ pub(crate) fn initialize_logging(config: &Config, args: &Opts) -> Result<PathBuf
     Ok(log_dir)
 }
 
+/// Return the parent of a process
+pub(crate) fn process_parent(pid: Pid) -> Option<Pid> {
+    Process::new(pid)
+        .and_then(|process| process.ppid())
+        .ok()
+        .flatten()
+}
+
+/// Return the ancestors of a process, from its parent up to `init`
+pub(crate) fn process_ancestors(pid: Pid) -> Vec<Pid> {
+    let mut ancestors = vec![];
+    let mut pid = pid;
+    // The process tree is walked while it can change, so a cycle stops it too
+    while let Some(parent) = process_parent(pid) {
+        if parent <= 1 || ancestors.contains(&parent) {
+            break;
+        }
+        ancestors.push(parent);
+        pid = parent;
+    }
+    ancestors
+}
+
 /// [`Deserialize`] something that has a shell variable
 #[allow(single_use_lifetimes)]
 pub(crate) fn deserialize_shellexpand<'de, D>(d: D) -> Result<Option<PathBuf>, D::Error>
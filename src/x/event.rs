@@ -44,6 +44,10 @@ pub(crate) enum XEvent {
     KeyRelease,
     /// A mouse button was pressed
     MouseEvent(MouseEvent),
+    /// A mouse button was released while the pointer was grabbed
+    MouseRelease(MouseEvent),
+    /// The pointer moved while it was grabbed
+    MouseMotion(MouseEvent),
     /// A client message was received
     ClientMessage(ClientMessageEvent),
     /// Received a randr notification
@@ -55,6 +59,28 @@ pub(crate) enum XEvent {
     Unknown(u8),
 }
 
+impl XEvent {
+    /// Return the client [`Window`] the event is about, if there is one
+    pub(crate) const fn window(&self) -> Option<Window> {
+        match self {
+            Self::ConfigureNotify(event) => Some(event.id),
+            Self::ConfigureRequest(data) => Some(data.id),
+            Self::MapRequest(window, _)
+            | Self::MapNotify(window)
+            | Self::UnmapNotify(window)
+            | Self::DestroyNotify(window)
+            | Self::KeyPress(window, _) => Some(*window),
+            Self::EnterNotify(event, _) | Self::LeaveNotify(event, _) => Some(event.id),
+            Self::ReparentNotify(event) => Some(event.child),
+            Self::PropertyNotify(event) => Some(event.id),
+            Self::MouseEvent(event) | Self::MouseRelease(event) | Self::MouseMotion(event) =>
+                Some(event.id),
+            Self::ClientMessage(event) => Some(event.window),
+            Self::KeyRelease | Self::RandrNotify | Self::ScreenChange | Self::Unknown(_) => None,
+        }
+    }
+}
+
 /// Data associated with a configure event
 #[derive(Debug, Clone, Copy)]
 pub(crate) struct ConfigureEvent {
@@ -177,6 +203,14 @@ impl ClientMessageData {
     pub(crate) fn is_u32(&self) -> bool {
         matches!(self, Self::U32(_))
     }
+
+    /// Return the data if it is in U32 format
+    pub(crate) const fn as_u32(&self) -> Option<&[u32; 5]> {
+        match self {
+            Self::U32(data) => Some(data),
+            Self::U8(_) | Self::U16(_) => None,
+        }
+    }
 }
 
 use std::convert::TryFrom;
@@ -108,7 +108,7 @@ impl WmSizeHints {
 // - Increments
 
 /// Structure representing a `WM_SIZE_HINTS` property
-#[derive(Debug, Copy, Clone, PartialOrd, Serialize, Deserialize)]
+#[derive(Debug, Default, Copy, Clone, PartialOrd, Serialize, Deserialize)]
 pub(crate) struct SizeHints {
     /// User flags
     pub(crate) by_user:          bool,
@@ -156,6 +156,50 @@ impl PartialEq for SizeHints {
 /// Cannot implement [`Eq`] for [`f64`]
 impl Eq for SizeHints {}
 
+impl SizeHints {
+    /// Return the largest [`Dimension`] that the hints allow within the given
+    /// one, which excludes the border of the [`Window`]
+    ///
+    /// As the ICCCM asks, the base size is taken away before the aspect ratios
+    /// and the resize increments are applied. The minimum size wins over the
+    /// rest, so the result may be larger than what was given
+    pub(crate) fn constrain(&self, dimension: Dimension) -> Dimension {
+        let width = self.max_width.map_or(dimension.width, |max| dimension.width.min(max));
+        let height = self
+            .max_height
+            .map_or(dimension.height, |max| dimension.height.min(max));
+
+        let base_width = self.base_width.unwrap_or(0);
+        let base_height = self.base_height.unwrap_or(0);
+        let mut w = u64::from(width.saturating_sub(base_width));
+        let mut h = u64::from(height.saturating_sub(base_height));
+
+        // The ratios are a width over a height, only ever shrinking a side
+        if let Some((num, den)) = self.max_ratio_vulgar.and_then(Ratio::terms) {
+            if w * den > h * num {
+                w = h * num / den;
+            }
+        }
+        if let Some((num, den)) = self.min_ratio_vulgar.and_then(Ratio::terms) {
+            if w * den < h * num {
+                h = w * den / num;
+            }
+        }
+
+        if let Some(inc) = self.inc_width {
+            w -= w % u64::from(inc);
+        }
+        if let Some(inc) = self.inc_height {
+            h -= h % u64::from(inc);
+        }
+
+        Dimension::new(
+            (base_width + w as u32).max(self.min_width.unwrap_or(1)).max(1),
+            (base_height + h as u32).max(self.min_height.unwrap_or(1)).max(1),
+        )
+    }
+}
+
 // ============================== Hints ===============================
 
 /// TODO: document
@@ -244,3 +288,119 @@ impl From<IcccmWindowState> for u32 {
 //     pub(crate) wm_protocols: Option<Vec<XAtom>>,
 //     pub(crate) wm_state: Option<WindowState>,
 // }
+
+/// Tests for applying the [`SizeHints`] of a [`Window`]
+#[cfg(test)]
+mod tests {
+    use super::SizeHints;
+    use crate::geometry::{Dimension, Ratio};
+
+    /// Create [`SizeHints`] with only the given base size and increments, like
+    /// those of a terminal
+    fn cells(base: u32, inc_width: u32, inc_height: u32) -> SizeHints {
+        SizeHints {
+            base_width: Some(base),
+            base_height: Some(base),
+            min_width: Some(base),
+            min_height: Some(base),
+            inc_width: Some(inc_width),
+            inc_height: Some(inc_height),
+            ..SizeHints::default()
+        }
+    }
+
+    #[test]
+    fn no_hints() {
+        let hints = SizeHints::default();
+        assert_eq!(hints.constrain(Dimension::new(640, 480)), Dimension::new(640, 480));
+        assert_eq!(hints.constrain(Dimension::new(0, 0)), Dimension::new(1, 1));
+    }
+
+    #[test]
+    fn minimum_and_maximum() {
+        let hints = SizeHints {
+            min_width: Some(200),
+            min_height: Some(100),
+            max_width: Some(500),
+            max_height: Some(400),
+            ..SizeHints::default()
+        };
+        assert_eq!(hints.constrain(Dimension::new(800, 600)), Dimension::new(500, 400));
+        assert_eq!(hints.constrain(Dimension::new(100, 50)), Dimension::new(200, 100));
+        assert_eq!(hints.constrain(Dimension::new(300, 300)), Dimension::new(300, 300));
+
+        // The minimum wins over a maximum that contradicts it
+        let hints = SizeHints {
+            min_width: Some(300),
+            max_width: Some(200),
+            ..SizeHints::default()
+        };
+        assert_eq!(hints.constrain(Dimension::new(250, 10)), Dimension::new(300, 10));
+    }
+
+    #[test]
+    fn increments() {
+        // The base size isn't part of the cells
+        assert_eq!(cells(2, 7, 15).constrain(Dimension::new(500, 300)), Dimension::new(499, 287));
+        assert_eq!(cells(2, 7, 15).constrain(Dimension::new(499, 287)), Dimension::new(499, 287));
+
+        let hints = SizeHints {
+            base_width: Some(4),
+            base_height: Some(2),
+            inc_width: Some(10),
+            inc_height: Some(20),
+            ..SizeHints::default()
+        };
+        assert_eq!(hints.constrain(Dimension::new(807, 605)), Dimension::new(804, 602));
+
+        // Too small for a single cell
+        assert_eq!(cells(2, 7, 15).constrain(Dimension::new(1, 1)), Dimension::new(2, 2));
+    }
+
+    #[test]
+    fn aspect_ratios() {
+        let wide = SizeHints {
+            max_ratio_vulgar: Some(Ratio::new(16, 9)),
+            ..SizeHints::default()
+        };
+        assert_eq!(wide.constrain(Dimension::new(1000, 100)), Dimension::new(177, 100));
+        assert_eq!(wide.constrain(Dimension::new(100, 1000)), Dimension::new(100, 1000));
+
+        let tall = SizeHints {
+            min_ratio_vulgar: Some(Ratio::new(1, 2)),
+            ..SizeHints::default()
+        };
+        assert_eq!(tall.constrain(Dimension::new(100, 1000)), Dimension::new(100, 200));
+        assert_eq!(tall.constrain(Dimension::new(1000, 100)), Dimension::new(1000, 100));
+
+        // A fixed ratio, checked without the base size
+        let fixed = SizeHints {
+            base_width: Some(10),
+            base_height: Some(20),
+            min_ratio_vulgar: Some(Ratio::new(4, 3)),
+            max_ratio_vulgar: Some(Ratio::new(4, 3)),
+            ..SizeHints::default()
+        };
+        assert_eq!(fixed.constrain(Dimension::new(810, 820)), Dimension::new(810, 620));
+        assert_eq!(fixed.constrain(Dimension::new(1010, 320)), Dimension::new(410, 320));
+
+        // Ratios that aren't positive are ignored
+        let invalid = SizeHints {
+            min_ratio_vulgar: Some(Ratio::new(0, 0)),
+            max_ratio_vulgar: Some(Ratio::new(-1, 2)),
+            ..SizeHints::default()
+        };
+        assert_eq!(invalid.constrain(Dimension::new(640, 480)), Dimension::new(640, 480));
+    }
+
+    #[test]
+    fn everything() {
+        // The maximum is applied before the cells, which stay whole
+        let hints = SizeHints {
+            max_width: Some(300),
+            max_ratio_vulgar: Some(Ratio::new(2, 1)),
+            ..cells(2, 7, 15)
+        };
+        assert_eq!(hints.constrain(Dimension::new(1000, 100)), Dimension::new(198, 92));
+    }
+}
@@ -1,6 +1,11 @@
 //! Interacting directly with the X11 server
 
-use crate::{core::Window, geometry::Dimension, x::xconnection::Atoms};
+use crate::{
+    core::Window,
+    geometry::Dimension,
+    messages::{self, Reply},
+    x::xconnection::Atoms,
+};
 use anyhow::{Context, Result};
 use attr_rs::attr_reader;
 use nix::poll::{poll, PollFd, PollFlags};
@@ -10,7 +15,7 @@ use std::{
     io::{self, Read, Write},
     net::Shutdown,
     os::unix::{
-        io::AsRawFd,
+        io::{AsRawFd, RawFd},
         net::{UnixListener, UnixStream},
     },
     sync::Arc,
@@ -79,24 +84,21 @@ impl Stream {
     }
 
     /// Receive data from the stream
-    pub(crate) fn recieve<T: DeserializeOwned>(&mut self) -> (bool, Option<T>) {
+    ///
+    /// Frames over [`MAX_FRAME_SIZE`](messages::MAX_FRAME_SIZE) and data that
+    /// can't be deserialized are errors, after which the stream is unusable
+    pub(crate) fn recieve<T: DeserializeOwned>(&mut self) -> Result<(bool, Option<T>)> {
         let done = self.get_bytes();
         if !self.reading && self.data.len() >= 4 {
-            self.length = bincode::deserialize::<u32>(self.data.drain(..4).as_ref())
-                .expect("failed to deserialize data") as usize;
+            self.length = messages::check_frame_length(self.data.drain(..4).as_ref())?;
             self.reading = true;
         }
         if self.reading && self.data.len() >= self.length {
             self.reading = false;
-            (
-                done,
-                Some(
-                    bincode::deserialize(self.data.drain(..self.length).as_ref())
-                        .expect("failed to deserialize data"),
-                ),
-            )
+            let item = messages::decode_frame(self.data.drain(..self.length).as_ref())?;
+            Ok((done, Some(item)))
         } else {
-            (done, None)
+            Ok((done, None))
         }
     }
 } // ]]] === Stream ===
@@ -120,9 +122,9 @@ pub(crate) struct Aux {
 
     /// Connection to a Unix socket
     listener: UnixListener,
-    /// TODO:
+    /// Clients connected to the socket that have not sent a full request yet
     streams:  Vec<Stream>,
-    /// TODO:
+    /// File descriptors of the X-Server, the socket and the [`Stream`]s
     poll_fds: Vec<PollFd>,
     /// Name of the socket
     socket:   String,
@@ -131,7 +133,7 @@ pub(crate) struct Aux {
 impl Aux {
     /// Create a new [`Aux`]
     pub(crate) fn new(conn: RustConnection, screen_num: usize) -> Result<Self> {
-        let socket = format!("/tmp/lwm-{}.sock", whoami::username());
+        let socket = messages::socket_path();
         drop(fs::remove_file(&socket));
         let listener = UnixListener::bind(&socket).context("failed to bind socket listener")?;
         listener
@@ -173,10 +175,52 @@ impl Aux {
         Ok(aux)
     }
 
-    /// Wait for file descriptor to become available
-    pub(crate) fn wait_for_updates(&mut self) {
+    /// Wait for the X-Server, the socket, a connected [`Stream`] or one of the
+    /// `extra` file descriptors to become readable
+    pub(crate) fn wait_for_updates(&mut self, extra: &[RawFd]) {
+        self.poll_fds.truncate(2);
+        self.poll_fds.extend(
+            self.streams
+                .iter()
+                .map(AsRawFd::as_raw_fd)
+                .chain(extra.iter().copied())
+                .map(|fd| PollFd::new(fd, PollFlags::POLLIN)),
+        );
         poll(&mut self.poll_fds, -1).ok();
     }
+
+    /// Accept new connections on the socket, and return every request that has
+    /// been fully received along with the [`Stream`] it came from
+    ///
+    /// Reading never blocks: partial requests are kept until the rest arrives,
+    /// and [`Stream`]s closed before sending a full request are dropped. So are
+    /// the ones sending a malformed request, after being told why
+    pub(crate) fn receive_requests<T: DeserializeOwned>(&mut self) -> Vec<(Stream, T)> {
+        while let Ok((stream, _)) = self.listener.accept() {
+            if let Err(e) = stream.set_nonblocking(true) {
+                log::warn!("failed to set non-blocking on `UnixStream`: {}", e);
+                continue;
+            }
+            self.streams.push(Stream::new(stream));
+        }
+
+        let mut requests = vec![];
+        let mut idx = 0;
+        while idx < self.streams.len() {
+            match self.streams[idx].recieve::<T>() {
+                Ok((_, Some(request))) => requests.push((self.streams.swap_remove(idx), request)),
+                Ok((true, None)) => drop(self.streams.swap_remove(idx)),
+                Ok((false, None)) => idx += 1,
+                Err(e) => {
+                    log::warn!("dropping a malformed request: {:#}", e);
+                    let mut stream = self.streams.swap_remove(idx);
+                    drop(stream.send(&Reply::failure(format!("{:#}", e))));
+                },
+            }
+        }
+
+        requests
+    }
 } // ]]] === Aux ===
 
 impl Drop for Aux {
@@ -3,10 +3,13 @@
 use crate::{
     config::{Config, GlobalSettings},
     core::{
+        decoration::to_hex,
         Atom,
         Button,
+        Direction,
         MotionRecorder,
         Pid,
+        StackMode,
         Window,
         WindowClass,
         WindowMap,
@@ -16,15 +19,30 @@ use crate::{
         META_WINDOW_IC,
         MISSING_VALUE,
         MOTION_RECORDER_IC,
+        PRESEL_FEEDBACK_IC,
         TITLEBAR_HEIGHT,
     },
     error::Error,
     geometry::{Dimension, Extents, Point, Ratio, Rectangle, Strut},
+    layout::presel_rectangle,
     lwm_fatal,
     monitor::client::IcccmProps,
+    tree::Presel,
     x::{
         property::{Hints, IcccmWindowState, SizeHints},
-        stream::Aux,
+        event::{
+            ClientMessageData as XClientMessageData,
+            ClientMessageEvent as XClientMessageEvent,
+            ConfigureEvent,
+            ConfigureRequestData,
+            KeypressEvent,
+            MouseEvent,
+            PointerEvent,
+            PropertyEvent,
+            ReparentEvent,
+            XEvent,
+        },
+        stream::{Aux, Stream},
         utils::Stack,
     },
     WM_NAME,
@@ -41,7 +59,7 @@ use std::{
     io::{self, Read, Write},
     net::Shutdown,
     os::unix::{
-        io::AsRawFd,
+        io::{AsRawFd, RawFd},
         net::{UnixListener, UnixStream},
     },
     process,
@@ -195,6 +213,9 @@ atom_manager! {
         // Wanting to close a window muse send this request
         _NET_CLOSE_WINDOW,
 
+        // Restack a window relative to a sibling, on behalf of a pager
+        _NET_RESTACK_WINDOW,
+
         // no
         _NET_MOVERESIZE_WINDOW,
         _NET_WM_MOVERESIZE,
@@ -471,7 +492,7 @@ impl XConnection {
     // ======================== Initialize ======================== [[[
 
     /// Initialize the window manager
-    fn init<S: AsRef<str>>(&self, wm_name: &str, desktop_names: &[S]) -> Result<()> {
+    pub(crate) fn init<S: AsRef<str>>(&self, wm_name: &str, desktop_names: &[S]) -> Result<()> {
         self.init_metawindow()?;
         self.aux()
             .configure_window(
@@ -617,7 +638,8 @@ impl XConnection {
         self.aux()
             .change_window_attributes(
                 window,
-                &ChangeWindowAttributesAux::default().event_mask(EventMask::STRUCTURE_NOTIFY),
+                &ChangeWindowAttributesAux::default()
+                    .event_mask(EventMask::STRUCTURE_NOTIFY | EventMask::PROPERTY_CHANGE),
             )
             .context(format!(
                 "failed to `change_window_attributes` Window({:#0x})",
@@ -1180,6 +1202,90 @@ impl XConnection {
         Ok(wid)
     }
 
+    /// Create the [`Window`] displaying the feedback of a [`Presel`]
+    pub(crate) fn create_presel_feedback(&self, rect: Rectangle) -> Result<Window> {
+        let wid = self.generate_id().context("failed to generate an ID")?;
+        log::debug!("creating a presel feedback: Window({:#0x})", wid);
+        let color = to_hex(&self.config.presel_feedback_color)?;
+        let aux = CreateWindowAux::new()
+            .override_redirect(1)
+            .save_under(1)
+            .background_pixel(color);
+
+        self.aux()
+            .create_window(
+                x11rb::COPY_DEPTH_FROM_PARENT,
+                wid,
+                self.root(),
+                rect.point.x as i16,
+                rect.point.y as i16,
+                rect.dimension.width as u16,
+                rect.dimension.height as u16,
+                0,
+                xproto::WindowClass::INPUT_OUTPUT,
+                0,
+                &aux,
+            )
+            .context(format!("failed to create Window({:#0x})", wid))?
+            .check()
+            .context(format!("failed check creating Window({:#0x})", wid))?;
+
+        self.set_icccm_window_class(wid, &*PRESEL_FEEDBACK_IC)?;
+
+        Ok(wid)
+    }
+
+    /// Draw the feedback of a [`Presel`] over the area of the preselected node
+    ///
+    /// The feedback [`Window`] is created the first time it is drawn and is
+    /// only mapped when `shown` is true. Nothing is drawn if `presel_feedback`
+    /// is disabled
+    pub(crate) fn draw_presel_feedback(
+        &self,
+        presel: &mut Presel,
+        node_rect: Rectangle,
+        window_gap: i32,
+        shown: bool,
+    ) -> Result<()> {
+        if !self.config.presel_feedback {
+            return Ok(());
+        }
+
+        let rect = presel_rectangle(node_rect, presel, window_gap);
+
+        if presel.feedback() == x11rb::NONE {
+            presel.set_feedback(self.create_presel_feedback(rect)?);
+        } else {
+            log::debug!("moving presel feedback Window({:#0x}): {}", presel.feedback(), rect);
+            self.aux()
+                .configure_window(presel.feedback(), &rect.to_aux(0).border_width(None))
+                .context(format!(
+                    "failed to move presel feedback Window({:#0x})",
+                    presel.feedback()
+                ))?
+                .check()
+                .context(format!(
+                    "failed to check moving presel feedback Window({:#0x})",
+                    presel.feedback()
+                ))?;
+        }
+
+        if shown {
+            self.map_window(presel.feedback())?;
+        }
+
+        Ok(())
+    }
+
+    /// Destroy the feedback [`Window`] of a [`Presel`], if it has one
+    pub(crate) fn destroy_presel_feedback(&self, presel: &Presel) -> Result<()> {
+        if presel.feedback() != x11rb::NONE {
+            self.destroy_window(presel.feedback())?;
+        }
+
+        Ok(())
+    }
+
     /// Focus the given [`Window`]
     pub(crate) fn focus_window(&self, window: Window) -> Result<()> {
         log::debug!("focusing Window({:#0x})", window);
@@ -1417,6 +1523,15 @@ impl XConnection {
         Ok(())
     }
 
+    /// Place every [`Window`] from the result of [`arrange`](crate::layout::arrange)
+    pub(crate) fn apply_layout(&self, placements: &[(Window, Rectangle)]) -> Result<()> {
+        for &(window, rect) in placements {
+            self.place_window(window, rect)?;
+        }
+
+        Ok(())
+    }
+
     // ========================== Update ========================== [[[
 
     /// Update the [`Window`]s managed by the window-manager
@@ -1734,6 +1849,26 @@ impl XConnection {
         })
     }
 
+    /// Get a [`Window`]'s `WM_WINDOW_ROLE` property
+    pub(crate) fn get_window_role(&self, window: Window) -> String {
+        log::debug!("requesting Window({:#0x})'s `WM_WINDOW_ROLE` property", window);
+        self.aux()
+            .get_property(
+                false,
+                window,
+                self.atoms().WM_WINDOW_ROLE,
+                AtomEnum::STRING,
+                0,
+                u32::MAX,
+            )
+            .map_or(String::from(MISSING_VALUE), |cookie| {
+                cookie.reply().map_or(String::from(MISSING_VALUE), |reply| {
+                    str::from_utf8(&reply.value8().map_or(vec![], Iterator::collect))
+                        .map_or(String::from(MISSING_VALUE), ToOwned::to_owned)
+                })
+            })
+    }
+
     /// Get a [`WindowState`] from an [`Atom`]
     pub(crate) fn get_window_state_from_atom(&self, atom: Atom) -> Option<WindowState> {
         self.win_states.get(&atom).copied()
@@ -2120,6 +2255,205 @@ impl XConnection {
 
     // ]]] === Base Wrappers ===
 
+    // ========================== Events ========================== [[[
+
+    /// Block until the X-Server, the socket or one of the `extra` file
+    /// descriptors has something to process
+    pub(crate) fn wait_for_updates(&mut self, extra: &[RawFd]) {
+        self.conn.wait_for_updates(extra);
+    }
+
+    /// Accept new connections on the socket and return the complete requests
+    /// along with the [`Stream`] used to reply
+    pub(crate) fn receive_requests<T: DeserializeOwned>(&mut self) -> Vec<(Stream, T)> {
+        self.conn.receive_requests()
+    }
+
+    /// Return the next [`XEvent`] without blocking
+    ///
+    /// Events that are not tracked are skipped
+    pub(crate) fn next_event(&self) -> Option<XEvent> {
+        while let Some(event) = self.poll_for_event() {
+            if let Some(event) = self.translate_event(event) {
+                return Some(event);
+            }
+        }
+
+        None
+    }
+
+    /// Translate an [`Event`] from the X-Server into an [`XEvent`]
+    fn translate_event(&self, event: Event) -> Option<XEvent> {
+        let root = self.root();
+
+        Some(match event {
+            Event::MapRequest(e) => XEvent::MapRequest(
+                e.window,
+                self.get_window_attributes(e.window)
+                    .map_or(false, |attr| attr.override_redirect),
+            ),
+            Event::MapNotify(e) => XEvent::MapNotify(e.window),
+            Event::UnmapNotify(e) => XEvent::UnmapNotify(e.window),
+            Event::DestroyNotify(e) => XEvent::DestroyNotify(e.window),
+            Event::ConfigureNotify(e) => XEvent::ConfigureNotify(ConfigureEvent {
+                id:      e.window,
+                geom:    Rectangle::new(
+                    e.x.into(),
+                    e.y.into(),
+                    e.width.into(),
+                    e.height.into(),
+                ),
+                is_root: e.window == root,
+            }),
+            Event::ConfigureRequest(e) => {
+                let has = |flag: xproto::ConfigWindow| e.value_mask & u16::from(flag) != 0;
+
+                XEvent::ConfigureRequest(ConfigureRequestData {
+                    id:         e.window,
+                    parent:     e.parent,
+                    sibling:    t!(has(xproto::ConfigWindow::SIBLING) ? Some(e.sibling) : None),
+                    x:          t!(has(xproto::ConfigWindow::X) ? Some(e.x.into()) : None),
+                    y:          t!(has(xproto::ConfigWindow::Y) ? Some(e.y.into()) : None),
+                    width:      t!(has(xproto::ConfigWindow::WIDTH) ? Some(e.width.into()) : None),
+                    height:     t!(has(xproto::ConfigWindow::HEIGHT) ? Some(e.height.into()) : None),
+                    stack_mode: match e.stack_mode {
+                        _ if !has(xproto::ConfigWindow::STACK_MODE) => None,
+                        xproto::StackMode::ABOVE => Some(StackMode::Above),
+                        xproto::StackMode::BELOW => Some(StackMode::Below),
+                        _ => None,
+                    },
+                    is_root:    e.window == root,
+                })
+            },
+            Event::PropertyNotify(e) => XEvent::PropertyNotify(PropertyEvent {
+                id:      e.window,
+                atom:    e.atom,
+                time:    e.time,
+                deleted: e.state == xproto::Property::DELETE,
+            }),
+            Event::ClientMessage(e) => XEvent::ClientMessage(XClientMessageEvent {
+                window: e.window,
+                type_:  e.type_,
+                data:   match e.format {
+                    8 => XClientMessageData::U8(e.data.as_data8()),
+                    16 => XClientMessageData::U16(e.data.as_data16()),
+                    _ => XClientMessageData::U32(e.data.as_data32()),
+                },
+            }),
+            Event::EnterNotify(e) => XEvent::EnterNotify(
+                PointerEvent {
+                    id:  e.event,
+                    abs: Point::new(e.root_x.into(), e.root_y.into()),
+                    rel: Point::new(e.event_x.into(), e.event_y.into()),
+                },
+                e.mode == xproto::NotifyMode::GRAB,
+            ),
+            Event::LeaveNotify(e) => XEvent::LeaveNotify(
+                PointerEvent {
+                    id:  e.event,
+                    abs: Point::new(e.root_x.into(), e.root_y.into()),
+                    rel: Point::new(e.event_x.into(), e.event_y.into()),
+                },
+                e.mode == xproto::NotifyMode::GRAB,
+            ),
+            Event::ReparentNotify(e) => XEvent::ReparentNotify(ReparentEvent {
+                event:    e.event,
+                parent:   e.parent,
+                child:    e.window,
+                over_red: e.override_redirect,
+            }),
+            Event::KeyPress(e) => XEvent::KeyPress(e.event, KeypressEvent {
+                mask:    e.state,
+                keycode: e.detail,
+            }),
+            Event::KeyRelease(_) => XEvent::KeyRelease,
+            Event::ButtonPress(e) => XEvent::MouseEvent(MouseEvent {
+                id:       e.event,
+                location: Point::new(e.root_x.into(), e.root_y.into()),
+            }),
+            Event::ButtonRelease(e) => XEvent::MouseRelease(MouseEvent {
+                id:       e.event,
+                location: Point::new(e.root_x.into(), e.root_y.into()),
+            }),
+            Event::MotionNotify(e) => XEvent::MouseMotion(MouseEvent {
+                id:       e.event,
+                location: Point::new(e.root_x.into(), e.root_y.into()),
+            }),
+            Event::RandrNotify(_) => XEvent::RandrNotify,
+            Event::RandrScreenChangeNotify(_) => XEvent::ScreenChange,
+            Event::Error(e) => {
+                log::warn!("received an error from the X-Server: {:?}", e);
+                return None;
+            },
+            e => {
+                log::trace!("ignoring event: {:?}", e);
+                return None;
+            },
+        })
+    }
+
+    /// Let a client know of its geometry with a synthetic `ConfigureNotify`,
+    /// when its request to be configured was not granted
+    pub(crate) fn send_configure_notify(
+        &self,
+        window: Window,
+        rect: Rectangle,
+        border_width: u32,
+    ) -> Result<()> {
+        let event = xproto::ConfigureNotifyEvent {
+            response_type: xproto::CONFIGURE_NOTIFY_EVENT,
+            sequence: 0,
+            event: window,
+            window,
+            above_sibling: x11rb::NONE,
+            x: rect.point.x as i16,
+            y: rect.point.y as i16,
+            width: (rect.dimension.width - border_width * 2) as u16,
+            height: (rect.dimension.height - border_width * 2) as u16,
+            border_width: border_width as u16,
+            override_redirect: false,
+        };
+
+        self.aux()
+            .send_event(false, window, EventMask::STRUCTURE_NOTIFY, &event)
+            .context(format!("failed to send `ConfigureNotify` to Window({:#0x})", window))?
+            .check()
+            .context(format!(
+                "failed to check sending `ConfigureNotify` to Window({:#0x})",
+                window
+            ))?;
+
+        Ok(())
+    }
+
+    /// Grant a `ConfigureRequest` as is
+    pub(crate) fn configure_window_request(&self, data: &ConfigureRequestData) -> Result<()> {
+        log::debug!("configuring Window({:#0x}) as requested", data.id);
+        let mut aux = ConfigureWindowAux::new()
+            .x(data.x)
+            .y(data.y)
+            .width(data.width)
+            .height(data.height)
+            .sibling(data.sibling);
+
+        if let Some(mode) = data.stack_mode {
+            aux = aux.stack_mode(match mode {
+                StackMode::Above => xproto::StackMode::ABOVE,
+                StackMode::Below => xproto::StackMode::BELOW,
+            });
+        }
+
+        self.aux()
+            .configure_window(data.id, &aux)
+            .context(format!("failed to configure Window({:#0x})", data.id))?
+            .check()
+            .context(format!("failed to check configuring Window({:#0x})", data.id))?;
+
+        Ok(())
+    }
+
+    // ]]] === Events ===
+
     // ========================== Replies ========================= [[[
 
     /// Return information about an [`Atom`]
@@ -2223,6 +2557,19 @@ impl XConnection {
             .context("failed to get `QueryPointerReply` reply")
     }
 
+    /// Return the keysym of a keycode when no modifier is held
+    pub(crate) fn get_keysym(&self, keycode: u8) -> Option<u32> {
+        log::debug!("requesting a `GetKeyboardMappingReply` reply");
+        self.aux()
+            .get_keyboard_mapping(keycode, 1)
+            .ok()?
+            .reply()
+            .ok()?
+            .keysyms
+            .first()
+            .copied()
+    }
+
     /// Delete the given property from the `root`
     pub(crate) fn delete_property(&self, property: Atom) -> Result<()> {
         log::debug!("deleting property: `{}`", property);
@@ -2278,6 +2625,18 @@ impl XConnection {
         Ok(())
     }
 
+    /// Ungrab/release the pointer
+    pub(crate) fn ungrab_pointer(&self) -> Result<()> {
+        log::debug!("attempting to ungrab control of the pointer");
+        self.aux()
+            .ungrab_pointer(x11rb::CURRENT_TIME)
+            .context("failed to ungrab pointer")?
+            .check()
+            .context("failed to check ungrabbing pointer")?;
+
+        Ok(())
+    }
+
     /// Regrab all buttons in the given [`Window`]
     pub(crate) fn regrab_buttons(&self, window: Window) -> Result<()> {
         self.aux()
@@ -2448,6 +2807,23 @@ impl XConnection {
         Ok(())
     }
 
+    /// Remove [`Window`] from the client's `save_set`
+    pub(crate) fn remove_window_from_save_set(&self, window: Window) -> Result<()> {
+        self.aux()
+            .change_save_set(xproto::SetMode::DELETE, window)
+            .context(format!(
+                "failed to `change_save_set` for Window({:#0x})",
+                window
+            ))?
+            .check()
+            .context(format!(
+                "failed to check `change_save_set` for Window({:#0x})",
+                window
+            ))?;
+
+        Ok(())
+    }
+
     // ]]] === Stack ===
 
     // =========================== Helper ========================= [[[
@@ -2578,8 +2954,11 @@ impl XConnection {
                     self.atoms()._NET_CURRENT_DESKTOP,
                     self.atoms()._NET_DESKTOP_NAMES,
                     self.atoms()._NET_DESKTOP_VIEWPORT,
+                    self.atoms()._NET_FRAME_EXTENTS,
                     self.atoms()._NET_MOVERESIZE_WINDOW,
                     self.atoms()._NET_NUMBER_OF_DESKTOPS,
+                    self.atoms()._NET_REQUEST_FRAME_EXTENTS,
+                    self.atoms()._NET_RESTACK_WINDOW,
                     self.atoms()._NET_SUPPORTED,
                     self.atoms()._NET_SUPPORTING_WM_CHECK,
                     self.atoms()._NET_WM_DESKTOP,
@@ -2587,6 +2966,8 @@ impl XConnection {
                     self.atoms()._NET_WM_MOVERESIZE,
                     self.atoms()._NET_WM_NAME,
                     self.atoms()._NET_WM_STATE,
+                    self.atoms()._NET_WM_STATE_ABOVE,
+                    self.atoms()._NET_WM_STATE_BELOW,
                     self.atoms()._NET_WM_STATE_DEMANDS_ATTENTION,
                     self.atoms()._NET_WM_STATE_FOCUSED,
                     self.atoms()._NET_WM_STATE_FULLSCREEN,
@@ -2607,6 +2988,7 @@ impl XConnection {
                     self.atoms()._NET_WM_WINDOW_TYPE_TOOLBAR,
                     self.atoms()._NET_WM_WINDOW_TYPE_TOOLTIP,
                     self.atoms()._NET_WM_WINDOW_TYPE_UTILITY,
+                    self.atoms()._NET_WORKAREA,
                 ],
             )
             .context("failed to initialize supported `_NET_SUPPORTED`")?
@@ -2717,12 +3099,14 @@ impl XConnection {
         Ok(id)
     }
 
-    /// Get a vector of the [`Window`]'s [`Strut`]s from `_NET_WM_STRUT`
-    pub(crate) fn get_window_strut(&self, window: Window) -> Option<Vec<Option<Strut>>> {
-        if let Some(strut) = self.get_window_strut_partial(window) {
-            return Some(strut);
+    /// Get the [`Strut`]s of a [`Window`] from `_NET_WM_STRUT_PARTIAL`, or from
+    /// `_NET_WM_STRUT` if it isn't set. The latter cover their whole edge
+    pub(crate) fn get_window_strut(&self, window: Window) -> Vec<Strut> {
+        if let Some(struts) = self.get_window_strut_partial(window) {
+            return struts;
         }
 
+        log::debug!("getting `_NET_WM_STRUT` for Window({:#0x})", window);
         self.aux()
             .get_property(
                 false,
@@ -2730,32 +3114,22 @@ impl XConnection {
                 self.atoms()._NET_WM_STRUT,
                 self.atoms().CARDINAL,
                 0,
-                u32::MAX,
+                4,
             )
-            .ok()?
-            .reply()
             .ok()
+            .and_then(|cookie| cookie.reply().ok())
             .and_then(|strut| {
                 let widths: Vec<u32> = strut.value32()?.collect();
-                if widths.is_empty() {
-                    return None;
-                }
-
-                let mut struts = Vec::with_capacity(4);
-                for (i, &width) in widths.iter().enumerate() {
-                    if i == 4 {
-                        break;
-                    }
-
-                    struts.push((width != 0).then(|| Strut { window, width }));
-                }
-
-                Some(struts)
+                (widths.len() == 4).then(|| {
+                    let spans = [(0, u32::MAX); 4];
+                    struts_from(window, &widths, &spans)
+                })
             })
+            .unwrap_or_default()
     }
 
-    /// Get the `_NET_WM_STRUT_PARTIAL` atom
-    pub(crate) fn get_window_strut_partial(&self, window: Window) -> Option<Vec<Option<Strut>>> {
+    /// Get the [`Strut`]s of a [`Window`] from `_NET_WM_STRUT_PARTIAL`
+    pub(crate) fn get_window_strut_partial(&self, window: Window) -> Option<Vec<Strut>> {
         log::debug!("getting `_NET_WM_STRUT_PARTIAL` for Window({:#0x})", window);
         self.aux()
             .get_property(
@@ -2764,27 +3138,24 @@ impl XConnection {
                 self.atoms()._NET_WM_STRUT_PARTIAL,
                 self.atoms().CARDINAL,
                 0,
-                u32::MAX,
+                12,
             )
             .ok()?
             .reply()
             .ok()
             .and_then(|strut_partial| {
-                let widths: Vec<u32> = strut_partial.value32()?.collect();
-                if widths.is_empty() {
+                let values: Vec<u32> = strut_partial.value32()?.collect();
+                if values.len() != 12 {
                     return None;
                 }
 
-                let mut struts = Vec::with_capacity(1);
-                for (i, &width) in widths.iter().enumerate() {
-                    if i == 4 {
-                        break;
-                    }
-
-                    struts.push((width != 0).then(|| Strut { window, width }));
-                }
-
-                Some(struts)
+                let spans = [
+                    (values[4], values[5]),
+                    (values[6], values[7]),
+                    (values[8], values[9]),
+                    (values[10], values[11]),
+                ];
+                Some(struts_from(window, &values[..4], &spans))
             })
     }
 
@@ -3006,7 +3377,7 @@ impl XConnection {
                 .map_or((None, None), |(inc_width, inc_height)| {
                     (
                         (inc_width > 0_i32 && inc_width < 0xFFFF_i32).then(|| inc_width as u32),
-                        (inc_width > 0_i32 && inc_width < 0xFFFF_i32).then(|| inc_width as u32),
+                        (inc_height > 0_i32 && inc_height < 0xFFFF_i32).then(|| inc_height as u32),
                     )
                 });
 
@@ -3015,10 +3386,12 @@ impl XConnection {
             .map_or(((None, None), (None, None)), |(min_ratio, max_ratio)| {
                 (
                     (
-                        (min_ratio.numerator > 0_i32 && min_ratio.denominator > 0_i32)
-                            .then(|| (min_ratio.numerator / min_ratio.denominator) as f64),
-                        (min_ratio.numerator > 0_i32 && min_ratio.denominator > 0_i32)
-                            .then(|| (min_ratio.numerator / min_ratio.denominator) as f64),
+                        (min_ratio.numerator > 0_i32 && min_ratio.denominator > 0_i32).then(|| {
+                            f64::from(min_ratio.numerator) / f64::from(min_ratio.denominator)
+                        }),
+                        (max_ratio.numerator > 0_i32 && max_ratio.denominator > 0_i32).then(|| {
+                            f64::from(max_ratio.numerator) / f64::from(max_ratio.denominator)
+                        }),
                     ),
                     (
                         Some(Ratio {
@@ -3266,3 +3639,15 @@ impl XConnection {
         println!("DataType: {:#?}", AtomEnum::from(reply.type_ as u8));
     }
 } // ]]] === XConnection ===
+
+/// Turn the widths of the left, right, top and bottom struts, and the spans
+/// they cover along their edge, into [`Strut`]s. Empty struts are left out
+fn struts_from(window: Window, widths: &[u32], spans: &[(u32, u32); 4]) -> Vec<Strut> {
+    [Direction::West, Direction::East, Direction::North, Direction::South]
+        .iter()
+        .zip(widths)
+        .zip(spans)
+        .filter(|((_, &width), _)| width != 0)
+        .map(|((&side, &width), &(start, end))| Strut::new(window, side, width, start, end))
+        .collect()
+}